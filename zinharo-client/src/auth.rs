@@ -0,0 +1,210 @@
+//! Logging into and signing up to the api with the configured credentials,
+//! shared by every subcommand that talks to the api
+
+use crate::config::{Config, ConfigError};
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+use zinharo_rs::{ZinharoAccess, ZinharoError};
+
+/// How many times logging in or signing up is tried before giving up whilst
+/// the api is ratelimiting or can't be reached
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Longest wait between attempts, unless the configured wait is longer
+const MAX_RETRY_SECS: u64 = 60 * 60;
+
+/// Reasons logging in or signing up failed, described for humans
+#[derive(Debug)]
+pub enum AuthError {
+    /// No username was configured
+    NoUsername,
+
+    /// No password was configured
+    NoPassword,
+
+    /// The password file couldn't be read
+    Password(ConfigError),
+
+    /// The api kept ratelimiting or couldn't be reached, with how many
+    /// attempts were made and the last error
+    GaveUp(u32, ZinharoError),
+
+    /// Username or password are wrong and signup is turned off
+    BadCredentials,
+
+    /// Signup was tried with a username someone else has
+    UsernameTaken,
+
+    /// The api no longer supports this client
+    OutOfDate,
+
+    /// Blocked by cloudflare or a local firewall
+    FirewallBlock,
+
+    /// Any other error from the api
+    Api(ZinharoError),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthError::NoUsername => write!(f, "Please supply a username with `--username`, the `ZINHARO_USERNAME` enviroment variable or the config file!"),
+            AuthError::NoPassword => write!(f, "Please supply a password with `--password-file`, the `ZINHARO_PASSWORD` enviroment variable or the config file!"),
+            AuthError::Password(e) => write!(f, "Could not get password: {}", e),
+            AuthError::GaveUp(attempts, e) => write!(
+                f,
+                "Gave up on the Zinharo API after {} attempts, last error: {:?}",
+                attempts, e
+            ),
+            AuthError::BadCredentials => write!(f, "Username or password invalid, signup can be turned on with `--signup` or the `ZINHARO_SIGNUP` env-var!"),
+            AuthError::UsernameTaken => write!(f, "Signup username taken, please choose another one or toggle signup off if it is your account!"),
+            AuthError::OutOfDate => write!(f, "This client is critically out of date, please update!"),
+            AuthError::FirewallBlock => write!(f, "You have been temporarily blocked from the Zinharo API by Cloudflare or a local firewall. Please ensure you are not routing through tor!"),
+            AuthError::Api(e) => write!(f, "Unknown error from the Zinharo API: {:?}", e),
+        }
+    }
+}
+
+impl From<ZinharoError> for AuthError {
+    fn from(e: ZinharoError) -> Self {
+        match e {
+            ZinharoError::BadCredentials => AuthError::BadCredentials,
+            ZinharoError::UsernameTaken => AuthError::UsernameTaken,
+            ZinharoError::ApiVersionInadequate => AuthError::OutOfDate,
+            ZinharoError::FirewallBlock => AuthError::FirewallBlock,
+            e => AuthError::Api(e),
+        }
+    }
+}
+
+/// Gets the configured username and password
+pub fn credentials(config: &Config) -> Result<(String, String), AuthError> {
+    let username = match &config.credentials.username {
+        Some(x) => String::clone(x),
+        None => return Err(AuthError::NoUsername),
+    };
+
+    match config.credentials.password() {
+        Ok(Some(password)) => Ok((username, password)),
+        Ok(None) => Err(AuthError::NoPassword),
+        Err(e) => Err(AuthError::Password(e)),
+    }
+}
+
+/// Signs up to Zinharo with new client, **should be used wisely**
+pub fn signup(config: &Config, username: &str, password: &str) -> Result<ZinharoAccess, AuthError> {
+    retrying("signup", config.backoff.signup_retry_secs, || {
+        ZinharoAccess::signup_at(&config.api.url, username, password)
+    })
+}
+
+/// Gets credentials from the configuration and logs in, retrying a few times
+/// whilst the api can't be reached and signing up instead if turned on
+pub fn login(config: &Config) -> Result<ZinharoAccess, AuthError> {
+    let (username, password) = credentials(config)?;
+
+    match retrying("login", config.backoff.login_retry_secs, || {
+        ZinharoAccess::login_at(&config.api.url, &username, &password)
+    }) {
+        Err(AuthError::BadCredentials) if config.credentials.signup => {
+            eprintln!("Username or password invalid, attempting signup..");
+            signup(config, &username, &password)
+        }
+        result => result,
+    }
+}
+
+/// Logs in once with the configured credentials, without retrying or
+/// signing up, for refreshing a session which has expired
+pub fn login_once(config: &Config) -> Result<ZinharoAccess, AuthError> {
+    let (username, password) = credentials(config)?;
+
+    ZinharoAccess::login_at(&config.api.url, &username, &password).map_err(AuthError::from)
+}
+
+/// Runs `attempt` until it succeeds, fails for good or has been tried
+/// [MAX_ATTEMPTS] times, waiting twice as long as before between each one
+/// from `retry_secs`
+fn retrying<F>(action: &str, retry_secs: u64, attempt: F) -> Result<ZinharoAccess, AuthError>
+where
+    F: Fn() -> Result<ZinharoAccess, ZinharoError>,
+{
+    let mut tried = 0;
+
+    loop {
+        let e = match attempt() {
+            Ok(access) => return Ok(access),
+            Err(e) => e,
+        };
+
+        tried += 1;
+
+        let reason = match e {
+            ZinharoError::Ratelimited => "Ratelimited",
+            ZinharoError::ReqwestError(_) => "Could not connect to Zinharo API",
+            e => return Err(e.into()),
+        };
+
+        if tried >= MAX_ATTEMPTS {
+            return Err(AuthError::GaveUp(tried, e));
+        }
+
+        let wait = retry_delay(retry_secs, tried);
+
+        eprintln!(
+            "{} whilst trying to {}, retrying in {} seconds..",
+            reason,
+            action,
+            wait.as_secs()
+        );
+        thread::sleep(wait);
+    }
+}
+
+/// Gives how long to wait after the `tried`th failed attempt, doubling from
+/// `retry_secs` up to [MAX_RETRY_SECS]
+fn retry_delay(retry_secs: u64, tried: u32) -> Duration {
+    let secs = retry_secs.saturating_mul(1 << (tried - 1).min(16));
+
+    Duration::from_secs(secs.min(retry_secs.max(MAX_RETRY_SECS)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn retries_are_bounded() {
+        assert_eq!(retry_delay(0, 1), Duration::from_secs(0));
+        assert_eq!(retry_delay(30, 1), Duration::from_secs(30));
+        assert_eq!(retry_delay(30, 3), Duration::from_secs(120));
+        assert_eq!(retry_delay(30, 12), Duration::from_secs(MAX_RETRY_SECS));
+        assert_eq!(
+            retry_delay(2 * MAX_RETRY_SECS, 4),
+            Duration::from_secs(2 * MAX_RETRY_SECS)
+        );
+
+        let tries = Cell::new(0);
+        let result = retrying("login", 0, || {
+            tries.set(tries.get() + 1);
+            Err(ZinharoError::Ratelimited)
+        });
+
+        match result {
+            Err(AuthError::GaveUp(MAX_ATTEMPTS, ZinharoError::Ratelimited)) => (),
+            other => panic!("expected to give up, got {:?}", other.map(|_| ())),
+        }
+        assert_eq!(tries.get(), MAX_ATTEMPTS);
+
+        tries.set(0);
+        let result = retrying("login", 0, || {
+            tries.set(tries.get() + 1);
+            Err(ZinharoError::BadCredentials)
+        });
+
+        assert!(matches!(result, Err(AuthError::BadCredentials)));
+        assert_eq!(tries.get(), 1);
+    }
+}
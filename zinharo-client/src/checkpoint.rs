@@ -0,0 +1,151 @@
+//! Periodic checkpoints of in-progress cracking, so a client that gets killed
+//! mid-job can resume the same job from roughly where it left off instead of
+//! abandoning it
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use zinharo_rs::ZinharoQueuedJob;
+
+/// How often a checkpoint of the current job is written
+pub const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Candidates fed to the cracker but possibly still sitting in pipe buffers
+/// when a checkpoint is taken. Resuming rewinds by this many so nothing gets
+/// skipped untested
+pub const RESUME_MARGIN: u64 = 100_000;
+
+/// Filename of the checkpoint inside the state directory
+const CHECKPOINT_FILE: &str = "checkpoint.json";
+
+/// Directory client state like checkpoints is kept in unless configured
+/// otherwise
+pub const DEFAULT_STATE_DIR: &str = "./state";
+
+/// Gets the directory the worker in `slot` keeps its state in. The first
+/// worker uses `state_dir` itself, so checkpoints from single-worker runs are
+/// still resumed
+pub fn worker_state_dir(state_dir: &Path, slot: usize) -> PathBuf {
+    match slot {
+        0 => state_dir.to_path_buf(),
+        slot => state_dir.join(format!("worker-{}", slot)),
+    }
+}
+
+/// The part of a job's attack plan that is currently being worked through
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+pub enum AttackStage {
+    /// A whole wordlist, by manifest identifier (or the default if none)
+    Wordlist { id: Option<String> },
+
+    /// A keyspace slice handed out by the server
+    Slice { id: i32 },
+}
+
+impl AttackStage {
+    /// Gets the stage a job is cracked with
+    pub fn for_job(job: &ZinharoQueuedJob) -> Self {
+        match &job.slice {
+            Some(slice) => AttackStage::Slice { id: slice.id },
+            None => AttackStage::Wordlist {
+                id: job.wordlist.clone(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for AttackStage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AttackStage::Wordlist { id: Some(id) } => write!(f, "wordlist `{}`", id),
+            AttackStage::Wordlist { id: None } => write!(f, "default wordlist"),
+            AttackStage::Slice { id } => write!(f, "keyspace slice #{}", id),
+        }
+    }
+}
+
+/// Progress on a single job as persisted to the state directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// ID of the job being cracked
+    pub job_id: i32,
+
+    /// Base64-encoded capture, used to make sure a resumed job is unchanged
+    pub cap: String,
+
+    /// Stage of the attack plan being worked through
+    pub stage: AttackStage,
+
+    /// Number of candidates of `stage` that can safely be skipped on resume
+    pub offset: u64,
+
+    /// Unix timestamp of when this checkpoint was written
+    pub saved: u64,
+}
+
+impl Checkpoint {
+    /// Creates a checkpoint for `job` where the first `offset` candidates are
+    /// known to have been tried
+    pub fn new(job: &ZinharoQueuedJob, offset: u64) -> Self {
+        Checkpoint {
+            job_id: job.id,
+            cap: base64::encode(&job.cap),
+            stage: AttackStage::for_job(job),
+            offset,
+            saved: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Checks this checkpoint was taken for the exact same work as `job`
+    pub fn matches(&self, job: &ZinharoQueuedJob) -> bool {
+        self.job_id == job.id
+            && self.cap == base64::encode(&job.cap)
+            && self.stage == AttackStage::for_job(job)
+    }
+
+    /// Atomically writes this checkpoint into `state_dir`, readable only by
+    /// the current user as it holds the capture
+    pub fn save(&self, state_dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(state_dir)?;
+
+        let path = state_dir.join(CHECKPOINT_FILE);
+        let part_path = path.with_extension("part");
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&part_path)?;
+
+        // a leftover part file may have been made with looser permissions
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        file.write_all(&serde_json::to_vec(self)?)?;
+        fs::rename(part_path, path)
+    }
+
+    /// Loads the checkpoint in `state_dir` if there is a readable one
+    pub fn load(state_dir: &Path) -> Option<Self> {
+        let contents = fs::read(state_dir.join(CHECKPOINT_FILE)).ok()?;
+
+        match serde_json::from_slice(&contents) {
+            Ok(checkpoint) => Some(checkpoint),
+            Err(_) => {
+                eprintln!("Ignoring unreadable checkpoint in state directory..");
+                None
+            }
+        }
+    }
+
+    /// Removes any checkpoint in `state_dir`, used once a job is finished
+    pub fn clear(state_dir: &Path) {
+        fs::remove_file(state_dir.join(CHECKPOINT_FILE)).ok();
+    }
+}
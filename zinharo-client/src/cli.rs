@@ -0,0 +1,173 @@
+//! Command-line interface of the client. Every subcommand shares the same
+//! global flags, which override the configuration file and env vars
+
+use crate::history::Outcome;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+/// Command-line interface of the client
+#[derive(Debug, Parser)]
+#[command(
+    name = "zinharo-client",
+    version,
+    about = "The automated Zinharo.com cracking client"
+)]
+pub struct Cli {
+    /// Configuration file to read, `./zinharo.toml` if it exists by default
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Api to crack for, like `https://zinharo.com/api/`
+    #[arg(long, global = true, value_name = "URL")]
+    pub api_url: Option<String>,
+
+    /// Username to log in with
+    #[arg(long, global = true)]
+    pub username: Option<String>,
+
+    /// File holding the password to log in with, so it never shows up in
+    /// process listings
+    #[arg(long, global = true, value_name = "PATH")]
+    pub password_file: Option<PathBuf>,
+
+    /// Sign up with the given credentials if logging in with them fails
+    #[arg(long, global = true)]
+    pub signup: bool,
+
+    /// Root per-job working directories are made in
+    #[arg(long, global = true, value_name = "DIR")]
+    pub work_dir: Option<PathBuf>,
+
+    /// Directory checkpoints and other client state are kept in
+    #[arg(long, global = true, value_name = "DIR")]
+    pub state_dir: Option<PathBuf>,
+
+    /// Directory downloaded wordlists are kept in
+    #[arg(long, global = true, value_name = "DIR")]
+    pub wordlist_dir: Option<PathBuf>,
+
+    /// Path to the cracker's executable
+    #[arg(long, global = true, value_name = "PATH")]
+    pub cracker: Option<PathBuf>,
+
+    /// Number of jobs to crack at once
+    #[arg(long, global = true, value_name = "N")]
+    pub workers: Option<usize>,
+
+    /// Seconds between progress logs of a running job, 0 to never log
+    #[arg(long, global = true, value_name = "SECS")]
+    pub progress_interval: Option<u64>,
+
+    /// Print machine-readable JSON on stdout instead of text
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// What to do, cracking jobs by default
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Subcommands of the client
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Cracks jobs handed out by the api until shut down
+    Worker,
+
+    /// Uploads captures for cracking, giving the ID of each one's hash.
+    /// Captures already uploaded are recognised by their contents and skipped
+    Upload {
+        /// Capture files to upload
+        #[arg(required_unless_present = "watch", value_name = "CAP")]
+        caps: Vec<PathBuf>,
+
+        /// Keep watching a directory, uploading captures as they're written
+        /// into it until interrupted
+        #[arg(long, value_name = "DIR", conflicts_with = "caps")]
+        watch: Option<PathBuf>,
+
+        /// Number of captures to upload at once
+        #[arg(long, value_name = "N")]
+        concurrency: Option<usize>,
+    },
+
+    /// Shows whether a hash has been cracked and what was reported for it
+    Status {
+        /// ID of the hash, as given when uploading
+        hash_id: i32,
+    },
+
+    /// Lists the networks in a capture and which of them can be cracked,
+    /// without contacting the api
+    Inspect {
+        /// Capture file to inspect
+        #[arg(value_name = "CAP")]
+        cap: PathBuf,
+    },
+
+    /// Shows the jobs this client worked on, newest last
+    History {
+        /// Only show jobs for this hash
+        #[arg(long, value_name = "ID")]
+        hash: Option<i32>,
+
+        /// Only show jobs which ended this way, like `found` or `reported`
+        #[arg(long)]
+        outcome: Option<Outcome>,
+
+        /// Only show jobs finished within this many days
+        #[arg(long, value_name = "DAYS")]
+        days: Option<u32>,
+
+        /// Show only the latest jobs, this many of them
+        #[arg(long, value_name = "N")]
+        limit: Option<usize>,
+
+        /// Export as CSV instead of text
+        #[arg(long, conflicts_with = "json")]
+        csv: bool,
+    },
+
+    /// Signs up with the configured credentials
+    Signup,
+
+    /// Checks the configured credentials can log in
+    Login,
+
+    /// Works with the client's configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Shares keys found before with hashcat
+    Potfile {
+        #[command(subcommand)]
+        action: PotfileAction,
+    },
+}
+
+/// Subcommands of `config`
+#[derive(Debug, Subcommand)]
+pub enum ConfigAction {
+    /// Prints the effective configuration with secrets redacted, failing if
+    /// it is invalid
+    Check,
+}
+
+/// Subcommands of `potfile`
+#[derive(Debug, Subcommand)]
+pub enum PotfileAction {
+    /// Adds the WPA keys from a hashcat potfile, cracked with `-m 22000`
+    Import {
+        /// Hashcat potfile to import
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+    },
+
+    /// Writes every key out in hashcat's potfile format
+    Export {
+        /// File to write to, stdout by default
+        #[arg(value_name = "FILE")]
+        file: Option<PathBuf>,
+    },
+}
@@ -0,0 +1,623 @@
+//! One-shot subcommands for working with the api and captures from scripts.
+//! Each prints text for humans, or a single JSON document on stdout with
+//! `--json`, and gives whether it succeeded
+
+use crate::auth;
+use crate::config::Config;
+use crate::coordinator::Coordinator;
+use crate::cracker::Aircrack;
+use crate::history::{History, Outcome, CSV_HEADER};
+use crate::potfile::Potfile;
+use crate::progress;
+use crate::uploader::{Manifest, Uploaded, Uploader, Watcher, LEDGER_FILE};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use zinharo_rs::{Ledger, ReportReason, ZinharoError, ZinharoHash};
+
+/// Prints `value` as pretty JSON on stdout
+fn print_json<T: Serialize>(value: &T) {
+    println!("{}", serde_json::to_string_pretty(value).unwrap());
+}
+
+/// Reports an error which stopped a command, as `{"error": ..}` JSON on
+/// stdout if asked for or otherwise to stderr
+fn print_error(message: &str, json: bool) {
+    /// Why the command failed
+    #[derive(Serialize)]
+    struct Failed<'a> {
+        error: &'a str,
+    }
+
+    if json {
+        print_json(&Failed { error: message });
+    } else {
+        eprintln!("{}", message);
+    }
+}
+
+/// Prints the effective configuration with secrets redacted, as TOML or JSON
+pub fn config_check(config: &Config, path: Option<&Path>, json: bool) -> bool {
+    /// Configuration alongside where it was read from
+    #[derive(Serialize)]
+    struct Checked<'a> {
+        config_file: Option<&'a Path>,
+        config: Config,
+    }
+
+    if json {
+        print_json(&Checked {
+            config_file: path,
+            config: config.redacted(),
+        });
+        return true;
+    }
+
+    match path {
+        Some(path) => println!("# Read from `{}`", path.display()),
+        None => println!("# No config file found, using defaults"),
+    }
+
+    print!("{}", config.redacted().to_toml());
+    true
+}
+
+/// Checks the configured credentials by logging in
+pub fn login(config: &Config, json: bool) -> bool {
+    /// Account that was logged into
+    #[derive(Serialize)]
+    struct LoggedIn<'a> {
+        username: &'a str,
+        api_url: &'a str,
+    }
+
+    let (username, _) = match auth::credentials(config) {
+        Ok(x) => x,
+        Err(e) => {
+            print_error(&e.to_string(), json);
+            return false;
+        }
+    };
+
+    if let Err(e) = auth::login(config) {
+        print_error(&e.to_string(), json);
+        return false;
+    }
+
+    if json {
+        print_json(&LoggedIn {
+            username: &username,
+            api_url: &config.api.url,
+        });
+    } else {
+        println!("Logged in as `{}` at {}", username, config.api.url);
+    }
+
+    true
+}
+
+/// Signs up with the configured credentials
+pub fn signup(config: &Config, json: bool) -> bool {
+    /// Account that was created
+    #[derive(Serialize)]
+    struct SignedUp<'a> {
+        username: &'a str,
+        api_url: &'a str,
+    }
+
+    let (username, password) = match auth::credentials(config) {
+        Ok(x) => x,
+        Err(e) => {
+            print_error(&e.to_string(), json);
+            return false;
+        }
+    };
+
+    if let Err(e) = auth::signup(config, &username, &password) {
+        print_error(&e.to_string(), json);
+        return false;
+    }
+
+    if json {
+        print_json(&SignedUp {
+            username: &username,
+            api_url: &config.api.url,
+        });
+    } else {
+        println!("Signed up as `{}` at {}", username, config.api.url);
+    }
+
+    true
+}
+
+/// Uploads each capture in `caps`, or every capture written into `watch`
+/// until interrupted, carrying on past any that fail
+pub fn upload(config: &Config, caps: Vec<PathBuf>, watch: Option<&Path>, json: bool) -> bool {
+    let manifest_path = config.upload.manifest_path(&config.paths);
+    let manifest = match Manifest::load(&manifest_path) {
+        Ok(x) => x,
+        Err(e) => {
+            let error = format!(
+                "Could not read upload manifest `{}`: {}",
+                manifest_path.display(),
+                e
+            );
+            print_error(&error, json);
+            return false;
+        }
+    };
+
+    let ledger_path = manifest_path.with_file_name(LEDGER_FILE);
+    let ledger = match Ledger::open(&ledger_path) {
+        Ok(x) => x,
+        Err(e) => {
+            let error = format!(
+                "Could not read fingerprint ledger `{}`: {:?}",
+                ledger_path.display(),
+                e
+            );
+            print_error(&error, json);
+            return false;
+        }
+    };
+
+    let access = match auth::login(config) {
+        Ok(x) => x,
+        Err(e) => {
+            print_error(&e.to_string(), json);
+            return false;
+        }
+    };
+    let streaming = match ZinharoHash::supports_streaming(&access) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!(
+                "Could not check for streamed uploads, sending JSON instead: {:?}",
+                e
+            );
+            false
+        }
+    };
+
+    let coordinator = Arc::new(Coordinator::new(config.backoff.fetch()));
+    let uploader = Uploader::new(
+        &access,
+        &coordinator,
+        manifest,
+        ledger,
+        config.upload.options(streaming),
+        config.upload.max_attempts,
+    );
+
+    let failed = AtomicBool::new(false);
+    let results = Mutex::new(Vec::new());
+
+    let done = |uploaded: Uploaded| {
+        if uploaded.error.is_some() {
+            failed.store(true, Ordering::SeqCst);
+        }
+
+        if json {
+            // a watch never ends, so each upload is its own line of JSON
+            match watch {
+                Some(_) => println!("{}", serde_json::to_string(&uploaded).unwrap()),
+                None => results.lock().unwrap().push(uploaded),
+            }
+            return;
+        }
+
+        let path = uploaded.path.display();
+
+        match (&uploaded.error, uploaded.hash_id) {
+            (Some(e), _) => eprintln!("{}: {}", path, e),
+            (None, Some(id)) if uploaded.duplicate => {
+                println!("{}: already uploaded as hash #{}", path, id)
+            }
+            (None, Some(id)) => println!("{}: uploaded as hash #{}", path, id),
+            (None, None) => println!("{}: same capture as another file being uploaded", path),
+        }
+    };
+
+    match watch {
+        Some(dir) => {
+            let handler_coordinator = Arc::clone(&coordinator);
+            let handled = ctrlc::set_handler(move || {
+                eprintln!("Stopping, finishing up uploads in progress..");
+                handler_coordinator.shutdown(false);
+            });
+
+            if let Err(e) = handled {
+                eprintln!("Could not listen for shutdown signals: '{}'", e);
+            }
+
+            eprintln!("Watching `{}` for new captures..", dir.display());
+
+            let handled = uploader.handled();
+            let watcher = Watcher::new(
+                dir,
+                Duration::from_secs(config.upload.settle_secs),
+                &handled,
+            );
+
+            uploader.watch(
+                watcher,
+                Duration::from_secs(config.upload.poll_interval_secs),
+                config.upload.concurrency,
+                &done,
+            );
+        }
+        None => uploader.upload_all(caps, config.upload.concurrency, &done),
+    }
+
+    if json && watch.is_none() {
+        print_json(&results.into_inner().unwrap());
+    }
+
+    !failed.load(Ordering::SeqCst)
+}
+
+/// Shows whether the hash `hash_id` has been cracked, and why it was reported
+/// if it has been
+pub fn status(config: &Config, hash_id: i32, json: bool) -> bool {
+    /// A job that cracked the hash
+    #[derive(Serialize)]
+    struct Cracked {
+        job_id: i32,
+        password: String,
+        client_id: i32,
+        created: String,
+    }
+
+    /// A report filed against the hash
+    #[derive(Serialize)]
+    struct Reported {
+        report_id: i32,
+        info: Option<String>,
+        #[serde(flatten)]
+        reason: Option<ReportReason>,
+        client_id: i32,
+        created: String,
+    }
+
+    /// Everything known about the hash
+    #[derive(Serialize)]
+    struct HashStatus {
+        hash_id: i32,
+        created: String,
+        cracked: bool,
+        jobs: Vec<Cracked>,
+        reports: Vec<Reported>,
+    }
+
+    let access = match auth::login(config) {
+        Ok(x) => x,
+        Err(e) => {
+            print_error(&e.to_string(), json);
+            return false;
+        }
+    };
+    let hash = match ZinharoHash::from_id(&access, hash_id) {
+        Ok(x) => x,
+        Err(ZinharoError::HashNotFound) => {
+            print_error(&format!("No hash #{} exists!", hash_id), json);
+            return false;
+        }
+        Err(e) => {
+            print_error(&format!("Could not fetch hash #{}: {:?}", hash_id, e), json);
+            return false;
+        }
+    };
+
+    let status = HashStatus {
+        hash_id: hash.id,
+        created: hash.created.to_rfc3339(),
+        cracked: !hash.jobs.is_empty(),
+        jobs: hash
+            .jobs
+            .into_iter()
+            .map(|job| Cracked {
+                job_id: job.id,
+                password: job.password,
+                client_id: job.client_id,
+                created: job.created.to_rfc3339(),
+            })
+            .collect(),
+        reports: hash
+            .reports
+            .into_iter()
+            .map(|report| Reported {
+                report_id: report.id,
+                info: report.info,
+                reason: report.reason,
+                client_id: report.client_id,
+                created: report.created.to_rfc3339(),
+            })
+            .collect(),
+    };
+
+    if json {
+        print_json(&status);
+        return true;
+    }
+
+    println!("Hash #{}, uploaded {}", status.hash_id, status.created);
+
+    if !status.cracked {
+        println!("Not cracked yet");
+    }
+
+    for job in &status.jobs {
+        println!(
+            "Cracked by client #{} (job #{}): `{}`",
+            job.client_id, job.job_id, job.password
+        );
+    }
+
+    for report in &status.reports {
+        let reason = match (&report.reason, &report.info) {
+            (Some(reason), _) => reason.to_string(),
+            (None, Some(info)) => String::clone(info),
+            (None, None) => String::from("no reason given"),
+        };
+
+        println!(
+            "Reported by client #{} (report #{}): {}",
+            report.client_id, report.report_id, reason
+        );
+    }
+
+    true
+}
+
+/// Which jobs of the history to show
+pub struct HistoryQuery {
+    /// Only jobs for this hash
+    pub hash_id: Option<i32>,
+
+    /// Only jobs which ended this way
+    pub outcome: Option<Outcome>,
+
+    /// Only jobs finished within this many days
+    pub days: Option<u32>,
+
+    /// Only this many of the latest jobs
+    pub limit: Option<usize>,
+}
+
+/// Shows the jobs this client worked on matching `query`, as text, CSV or
+/// JSON
+pub fn history(config: &Config, query: HistoryQuery, csv: bool, json: bool) -> bool {
+    let history = History::open_read_only(&config.paths.state_dir);
+    let mut records = match history.records() {
+        Ok(x) => x,
+        Err(e) => {
+            print_error(&format!("Could not read the job history: '{}'", e), json);
+            return false;
+        }
+    };
+
+    let cutoff = query
+        .days
+        .map(|days| Utc::now() - chrono::Duration::days(days as i64));
+
+    records.retain(|record| {
+        query.hash_id.is_none_or(|id| record.hash_id == id)
+            && query
+                .outcome
+                .is_none_or(|outcome| record.outcome == outcome)
+            && cutoff.is_none_or(|cutoff| record.finished_at().is_some_and(|t| t >= cutoff))
+    });
+
+    if let Some(limit) = query.limit {
+        let excess = records.len().saturating_sub(limit);
+        records.drain(..excess);
+    }
+
+    if json {
+        print_json(&records);
+        return true;
+    }
+
+    if csv {
+        println!("{}", CSV_HEADER);
+
+        for record in &records {
+            println!("{}", record.to_csv());
+        }
+
+        return true;
+    }
+
+    if records.is_empty() {
+        println!("No jobs in the history");
+    }
+
+    for record in &records {
+        let took = match (
+            DateTime::parse_from_rfc3339(&record.fetched),
+            record.finished_at(),
+        ) {
+            (Ok(fetched), Some(finished)) => (finished - fetched.with_timezone(&Utc))
+                .to_std()
+                .map(progress::format_eta)
+                .unwrap_or_default(),
+            _ => String::from("?"),
+        };
+        let keys = match record.keys_tested {
+            Some(keys) => format!(", {} keys tested", keys),
+            None => String::new(),
+        };
+        let detail = match &record.detail {
+            Some(detail) => format!(" ({})", detail),
+            None => String::new(),
+        };
+
+        println!(
+            "{} job #{} for hash #{}: {}{} in {}{}",
+            record.finished, record.job_id, record.hash_id, record.outcome, detail, took, keys
+        );
+    }
+
+    true
+}
+
+/// Opens the configured potfile, reporting why if it can't be
+fn open_potfile(config: &Config, json: bool) -> Option<Potfile> {
+    let path = config.potfile.path(&config.paths);
+
+    match Potfile::open(&path) {
+        Ok(x) => Some(x),
+        Err(e) => {
+            let error = format!("Could not open potfile `{}`: '{}'", path.display(), e);
+            print_error(&error, json);
+            None
+        }
+    }
+}
+
+/// Adds the keys from the hashcat potfile at `path` to the potfile
+pub fn potfile_import(config: &Config, path: &Path, json: bool) -> bool {
+    let potfile = match open_potfile(config, json) {
+        Some(x) => x,
+        None => return false,
+    };
+
+    let imported = match potfile.import(path) {
+        Ok(x) => x,
+        Err(e) => {
+            print_error(
+                &format!("Could not import `{}`: '{}'", path.display(), e),
+                json,
+            );
+            return false;
+        }
+    };
+
+    if json {
+        print_json(&imported);
+    } else {
+        println!(
+            "Imported {} key(s), skipping {} line(s) already known or not for WPA",
+            imported.added, imported.skipped
+        );
+    }
+
+    true
+}
+
+/// Writes the potfile out in hashcat's format, to `path` or else stdout
+pub fn potfile_export(config: &Config, path: Option<&Path>, json: bool) -> bool {
+    let potfile = match open_potfile(config, json) {
+        Some(x) => x,
+        None => return false,
+    };
+
+    let exported = match path {
+        Some(path) => File::create(path).and_then(|file| potfile.export(BufWriter::new(file))),
+        None => potfile.export(io::stdout().lock()),
+    };
+
+    match (exported, path) {
+        (Ok(count), Some(path)) => {
+            eprintln!("Exported {} key(s) to `{}`", count, path.display());
+            true
+        }
+        (Ok(_), None) => true,
+        (Err(e), _) => {
+            print_error(&format!("Could not export the potfile: '{}'", e), json);
+            false
+        }
+    }
+}
+
+/// Lists the networks in the capture at `path` and which of them can be
+/// cracked, using only the local cracker
+pub fn inspect(config: &Config, path: &Path, json: bool) -> bool {
+    /// A network found in the capture
+    #[derive(Serialize)]
+    struct Found {
+        bssid: String,
+        essid: String,
+        encryption: String,
+        crackable: bool,
+    }
+
+    /// Everything found out about the capture
+    #[derive(Serialize)]
+    struct Inspected<'a> {
+        path: &'a Path,
+        size: usize,
+        sha256: String,
+        networks: Vec<Found>,
+    }
+
+    let cap = match fs::read(path) {
+        Ok(x) => x,
+        Err(e) => {
+            print_error(&format!("Could not read `{}`: {}", path.display(), e), json);
+            return false;
+        }
+    };
+
+    let mut aircrack = Aircrack::new(path.to_path_buf(), PathBuf::new());
+    aircrack.program = PathBuf::clone(&config.cracker.path);
+    aircrack.limits = config.cracker.limits().unwrap_or_default();
+
+    let networks = match aircrack.networks() {
+        Ok(x) => x,
+        Err(e) => {
+            let error = format!("Could not run the cracker on `{}`: {}", path.display(), e);
+            print_error(&error, json);
+            return false;
+        }
+    };
+
+    let inspected = Inspected {
+        path,
+        size: cap.len(),
+        sha256: hex::encode(Sha256::digest(&cap)),
+        networks: networks
+            .into_iter()
+            .map(|network| Found {
+                crackable: network.has_handshake(),
+                bssid: network.bssid,
+                essid: network.essid,
+                encryption: network.encryption.to_string(),
+            })
+            .collect(),
+    };
+
+    if json {
+        print_json(&inspected);
+        return true;
+    }
+
+    println!(
+        "{} ({} bytes, sha256 {})",
+        path.display(),
+        inspected.size,
+        inspected.sha256
+    );
+
+    if inspected.networks.is_empty() {
+        println!("No networks found");
+    }
+
+    for network in &inspected.networks {
+        println!(
+            "{}  `{}`  {}{}",
+            network.bssid,
+            network.essid,
+            network.encryption,
+            if network.crackable { ", crackable" } else { "" }
+        );
+    }
+
+    true
+}
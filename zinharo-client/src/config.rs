@@ -0,0 +1,904 @@
+//! Layered client configuration. Built-in defaults are overridden by a TOML
+//! file, then by `ZINHARO_*` env vars, then by command-line flags, so a
+//! deployment can keep most settings in one file and tweak a few per run
+
+use crate::cli::{Cli, Command};
+use crate::coordinator::Backoff;
+use crate::history::Retention;
+use crate::supervisor::{IoPriority, Limits};
+use crate::wordlist::{self, WordlistLocations};
+use crate::{checkpoint, potfile, uploader, workdir};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use zinharo_rs::{UploadOptions, DEFAULT_MAX_CAP_SIZE};
+
+/// Configuration file read when `--config` and `ZINHARO_CONFIG` aren't given,
+/// if it exists
+const DEFAULT_CONFIG_FILE: &str = "./zinharo.toml";
+
+/// Shown in place of secrets by `config check`
+const REDACTED: &str = "<redacted>";
+
+/// Errors whilst loading the configuration
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The configuration file couldn't be read
+    IOError(PathBuf, io::Error),
+
+    /// The configuration file isn't valid TOML or has unknown settings
+    Parse(PathBuf, toml::de::Error),
+
+    /// A setting has a value it can't take, described for humans
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::IOError(path, e) => {
+                write!(f, "could not read `{}`: {}", path.display(), e)
+            }
+            ConfigError::Parse(path, e) => write!(f, "could not parse `{}`: {}", path.display(), e),
+            ConfigError::Invalid(info) => write!(f, "{}", info),
+        }
+    }
+}
+
+/// The whole client configuration
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Api the client cracks for
+    pub api: ApiConfig,
+
+    /// Account the client logs in with
+    pub credentials: Credentials,
+
+    /// Where the client keeps its files
+    pub paths: PathsConfig,
+
+    /// Where wordlists come from and how they're prepared
+    pub wordlists: WordlistConfig,
+
+    /// Cracker to run and the limits it runs under
+    pub cracker: CrackerConfig,
+
+    /// How long to hold back after the api turns the client away
+    pub backoff: BackoffConfig,
+
+    /// What gets logged
+    pub logging: LoggingConfig,
+
+    /// How captures are bulk uploaded
+    pub upload: UploadConfig,
+
+    /// How long the job history is kept
+    pub history: HistoryConfig,
+
+    /// Where keys found before are kept
+    pub potfile: PotfileConfig,
+}
+
+/// Settings for the api, under `[api]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiConfig {
+    /// Prefix of every api route, `ZINHARO_API_URL`
+    pub url: String,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        ApiConfig {
+            url: String::from(zinharo_rs::API_PREFIX),
+        }
+    }
+}
+
+/// Account settings, under `[credentials]`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Credentials {
+    /// Username, `ZINHARO_USERNAME`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+
+    /// Password, `ZINHARO_PASSWORD`. Setting it replaces any `password_file`
+    /// from a lower layer, and setting both in one layer is an error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+
+    /// File the password is read from, `ZINHARO_PASSWORD_FILE`. Setting it
+    /// replaces any `password` from a lower layer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password_file: Option<PathBuf>,
+
+    /// Whether to sign up if logging in fails, set by `ZINHARO_SIGNUP`
+    pub signup: bool,
+}
+
+impl Credentials {
+    /// Checks a single layer of configuration didn't give the password both
+    /// directly and as a file, naming the layer if it did
+    fn check_single_password(&self, layer: &str) -> Result<(), ConfigError> {
+        if self.password.is_some() && self.password_file.is_some() {
+            return Err(ConfigError::Invalid(format!(
+                "{} sets both a password and a password file, only one can be used",
+                layer
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Gets the password, reading it from [Credentials::password_file] if it
+    /// isn't given directly. A single trailing newline in the file is ignored
+    pub fn password(&self) -> Result<Option<String>, ConfigError> {
+        if let Some(password) = &self.password {
+            return Ok(Some(String::clone(password)));
+        }
+
+        match &self.password_file {
+            Some(path) => fs::read_to_string(path)
+                .map(|password| {
+                    let password = password.strip_suffix('\n').unwrap_or(&password);
+                    Some(password.strip_suffix('\r').unwrap_or(password).to_string())
+                })
+                .map_err(|e| ConfigError::IOError(PathBuf::clone(path), e)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Directories the client keeps its files in, under `[paths]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathsConfig {
+    /// Root per-job working directories are made in, `ZINHARO_WORK_DIR`
+    pub work_dir: PathBuf,
+
+    /// Directory checkpoints are kept in, `ZINHARO_STATE_DIR`
+    pub state_dir: PathBuf,
+}
+
+impl Default for PathsConfig {
+    fn default() -> Self {
+        PathsConfig {
+            work_dir: workdir::default_work_root(),
+            state_dir: PathBuf::from(checkpoint::DEFAULT_STATE_DIR),
+        }
+    }
+}
+
+/// Wordlist settings, under `[wordlists]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WordlistConfig {
+    /// Directory raw wordlists are downloaded to, `ZINHARO_WORDLIST_DIR`
+    pub dir: PathBuf,
+
+    /// Directory preprocessed wordlists are cached in,
+    /// `ZINHARO_WORDLIST_CACHE_DIR`
+    pub cache_dir: PathBuf,
+
+    /// Wordlist used when the server offers no manifest,
+    /// `ZINHARO_WORDLIST_URL`
+    pub fallback_url: String,
+
+    /// Whether to order candidates by frequency, set by
+    /// `ZINHARO_WORDLIST_FREQUENCY`
+    pub frequency_order: bool,
+
+    /// Whether to keep preprocessed wordlists compressed, set by
+    /// `ZINHARO_WORDLIST_STREAM`
+    pub stream: bool,
+}
+
+impl Default for WordlistConfig {
+    fn default() -> Self {
+        WordlistConfig {
+            dir: PathBuf::from(wordlist::DEFAULT_WORDLIST_DIR),
+            cache_dir: PathBuf::from(wordlist::DEFAULT_CACHE_DIR),
+            fallback_url: String::from(wordlist::DEFAULT_WORDLIST_URL),
+            frequency_order: false,
+            stream: false,
+        }
+    }
+}
+
+impl WordlistConfig {
+    /// Gives where wordlists are downloaded from and kept
+    pub fn locations(&self) -> WordlistLocations {
+        WordlistLocations {
+            dir: PathBuf::clone(&self.dir),
+            cache_dir: PathBuf::clone(&self.cache_dir),
+            fallback_url: String::clone(&self.fallback_url),
+        }
+    }
+}
+
+/// Crackers the client can run
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// aircrack-ng, fed candidates through its stdin
+    AircrackNg,
+}
+
+impl FromStr for Backend {
+    type Err = ConfigError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "aircrack-ng" => Ok(Backend::AircrackNg),
+            other => Err(ConfigError::Invalid(format!(
+                "unknown cracker backend `{}`, only `aircrack-ng` is supported",
+                other
+            ))),
+        }
+    }
+}
+
+/// Cracker settings, under `[cracker]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CrackerConfig {
+    /// Cracker to run, `ZINHARO_CRACKER_BACKEND`
+    pub backend: Backend,
+
+    /// Path to the cracker's executable, `ZINHARO_CRACKER`
+    pub path: PathBuf,
+
+    /// Number of jobs to crack at once, `ZINHARO_WORKERS`
+    pub workers: usize,
+
+    /// Wall-clock seconds a run may take, `ZINHARO_CRACK_TIMEOUT`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+
+    /// Seconds a run may go silent before it is presumed hung, 0 to never
+    /// presume so, `ZINHARO_CRACK_SILENCE`
+    pub silence_secs: u64,
+
+    /// Cpu seconds a run may use, `ZINHARO_CRACK_CPU_SECS`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_secs: Option<u64>,
+
+    /// Megabytes of memory a run may use, `ZINHARO_CRACK_MEMORY_MB`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_mb: Option<u64>,
+
+    /// Niceness from -20 to 19 the cracker runs at, `ZINHARO_CRACK_NICE`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nice: Option<i32>,
+
+    /// Io scheduling class, `idle`, `best-effort` or `best-effort:0-7`,
+    /// `ZINHARO_CRACK_IONICE`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ionice: Option<String>,
+}
+
+impl Default for CrackerConfig {
+    fn default() -> Self {
+        CrackerConfig {
+            backend: Backend::AircrackNg,
+            path: PathBuf::from("aircrack-ng"),
+            workers: 1,
+            timeout_secs: None,
+            silence_secs: Limits::default()
+                .silence
+                .map_or(0, |silence| silence.as_secs()),
+            cpu_secs: None,
+            memory_mb: None,
+            nice: None,
+            ionice: None,
+        }
+    }
+}
+
+impl CrackerConfig {
+    /// Gives the limits every cracker is supervised under
+    pub fn limits(&self) -> Result<Limits, ConfigError> {
+        if let Some(nice) = self.nice {
+            if !(-20..=19).contains(&nice) {
+                return Err(ConfigError::Invalid(format!(
+                    "cracker niceness should be from -20 to 19, not {}",
+                    nice
+                )));
+            }
+        }
+
+        let ionice = match &self.ionice {
+            Some(ionice) => match IoPriority::parse(ionice) {
+                Some(x) => Some(x),
+                None => {
+                    return Err(ConfigError::Invalid(format!(
+                    "cracker ionice should be `idle`, `best-effort` or `best-effort:0-7`, not `{}`",
+                    ionice
+                )))
+                }
+            },
+            None => None,
+        };
+
+        Ok(Limits {
+            deadline: self.timeout_secs.map(Duration::from_secs),
+            silence: match self.silence_secs {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            cpu_secs: self.cpu_secs,
+            memory_bytes: self.memory_mb.map(|mb| mb.saturating_mul(1024 * 1024)),
+            nice: self.nice,
+            ionice,
+        })
+    }
+}
+
+/// Backoff timings in seconds, under `[backoff]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackoffConfig {
+    /// After being ratelimited whilst fetching a job
+    pub ratelimited_secs: u64,
+
+    /// After finding no jobs available
+    pub no_jobs_secs: u64,
+
+    /// After any other error whilst fetching a job
+    pub error_secs: u64,
+
+    /// Before retrying a failed login
+    pub login_retry_secs: u64,
+
+    /// Before retrying a ratelimited signup
+    pub signup_retry_secs: u64,
+}
+
+impl Default for BackoffConfig {
+    fn default() -> Self {
+        let backoff = Backoff::default();
+
+        BackoffConfig {
+            ratelimited_secs: backoff.ratelimited.as_secs(),
+            no_jobs_secs: backoff.no_jobs.as_secs(),
+            error_secs: backoff.error.as_secs(),
+            login_retry_secs: 30,
+            signup_retry_secs: 60 * 60,
+        }
+    }
+}
+
+impl BackoffConfig {
+    /// Gives the backoff shared between workers fetching jobs
+    pub fn fetch(&self) -> Backoff {
+        Backoff {
+            ratelimited: Duration::from_secs(self.ratelimited_secs),
+            no_jobs: Duration::from_secs(self.no_jobs_secs),
+            error: Duration::from_secs(self.error_secs),
+        }
+    }
+}
+
+/// Logging settings, under `[logging]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// Seconds between progress logs of a running job, 0 to never log,
+    /// `ZINHARO_PROGRESS_INTERVAL`
+    pub progress_interval_secs: u64,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            progress_interval_secs: 30,
+        }
+    }
+}
+
+impl LoggingConfig {
+    /// Gives how often progress is logged, if ever
+    pub fn progress_interval(&self) -> Option<Duration> {
+        match self.progress_interval_secs {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+}
+
+/// Bulk upload settings, under `[upload]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UploadConfig {
+    /// Captures uploaded at once, `ZINHARO_UPLOAD_CONCURRENCY`
+    pub concurrency: usize,
+
+    /// Seconds between checks of a watched directory
+    pub poll_interval_secs: u64,
+
+    /// Seconds a capture must go unchanged before it is uploaded
+    pub settle_secs: u64,
+
+    /// Attempts at uploading a capture before giving up on it, not counting
+    /// ratelimits
+    pub max_attempts: u32,
+
+    /// Manifest of uploaded captures, `uploads.json` in the state directory
+    /// by default
+    pub manifest: Option<PathBuf>,
+
+    /// Compress captures with zstd whilst uploading, if the api supports it
+    pub compress: bool,
+
+    /// Largest capture uploaded, in megabytes
+    pub max_size_mb: u64,
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        UploadConfig {
+            concurrency: 2,
+            poll_interval_secs: 5,
+            settle_secs: 10,
+            max_attempts: 5,
+            manifest: None,
+            compress: true,
+            max_size_mb: DEFAULT_MAX_CAP_SIZE / (1024 * 1024),
+        }
+    }
+}
+
+impl UploadConfig {
+    /// Gives where the manifest of uploaded captures is kept
+    pub fn manifest_path(&self, paths: &PathsConfig) -> PathBuf {
+        match &self.manifest {
+            Some(path) => PathBuf::clone(path),
+            None => paths.state_dir.join(uploader::MANIFEST_FILE),
+        }
+    }
+
+    /// Gives how captures are sent, `streaming` being whether the api takes
+    /// streamed uploads
+    pub fn options(&self, streaming: bool) -> UploadOptions {
+        UploadOptions {
+            compress: self.compress,
+            max_size: self.max_size_mb.saturating_mul(1024 * 1024),
+            streaming: Some(streaming),
+        }
+    }
+}
+
+/// Job history settings, under `[history]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    /// Days jobs are kept in the history for, 0 to keep them forever,
+    /// `ZINHARO_HISTORY_RETENTION_DAYS`
+    pub retention_days: u32,
+
+    /// Most jobs kept in the history, 0 for no limit
+    pub max_records: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            retention_days: 90,
+            max_records: 10_000,
+        }
+    }
+}
+
+impl HistoryConfig {
+    /// Gives how long records are kept for
+    pub fn retention(&self) -> Retention {
+        Retention {
+            max_age_days: Some(self.retention_days).filter(|&days| days > 0),
+            max_records: Some(self.max_records).filter(|&max| max > 0),
+        }
+    }
+}
+
+/// Potfile settings, under `[potfile]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PotfileConfig {
+    /// Try keys found before on new captures of the same network before
+    /// cracking them, `ZINHARO_POTFILE_DISABLED` to turn off
+    pub enabled: bool,
+
+    /// Potfile in hashcat's format, `zinharo.potfile` in the state directory
+    /// by default
+    pub path: Option<PathBuf>,
+}
+
+impl Default for PotfileConfig {
+    fn default() -> Self {
+        PotfileConfig {
+            enabled: true,
+            path: None,
+        }
+    }
+}
+
+impl PotfileConfig {
+    /// Gives where the potfile is kept
+    pub fn path(&self, paths: &PathsConfig) -> PathBuf {
+        match &self.path {
+            Some(path) => PathBuf::clone(path),
+            None => paths.state_dir.join(potfile::POTFILE),
+        }
+    }
+}
+
+/// Reads the env var `name`, if set
+fn env_string(name: &str) -> Option<String> {
+    env::var(name).ok()
+}
+
+/// Reads and parses the env var `name`, if set
+fn env_parse<T: FromStr>(name: &str) -> Result<Option<T>, ConfigError> {
+    match env::var(name) {
+        Ok(value) => value.trim().parse().map(Some).map_err(|_| {
+            ConfigError::Invalid(format!("`{}` has an invalid value `{}`", name, value))
+        }),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Reads the on/off switch in the env var `name`, if set, taking `true`,
+/// `false`, `1` or `0`
+fn env_bool(name: &str) -> Result<Option<bool>, ConfigError> {
+    match env::var(name) {
+        Ok(value) => match value.trim().to_ascii_lowercase().as_str() {
+            "true" | "1" => Ok(Some(true)),
+            "false" | "0" => Ok(Some(false)),
+            _ => Err(ConfigError::Invalid(format!(
+                "`{}` should be `true`, `false`, `1` or `0`, not `{}`",
+                name, value
+            ))),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+impl Config {
+    /// Loads the configuration for a run, layering the configuration file,
+    /// env vars and `cli` flags over the defaults. Gives the file that was
+    /// read, if any, alongside it
+    pub fn load(cli: &Cli) -> Result<(Self, Option<PathBuf>), ConfigError> {
+        let path = cli
+            .config
+            .clone()
+            .or_else(|| env_string("ZINHARO_CONFIG").map(PathBuf::from))
+            .or_else(|| Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|path| path.exists()));
+
+        let mut config = match &path {
+            Some(path) => Config::from_file(path)?,
+            None => Config::default(),
+        };
+
+        config.apply_env()?;
+        config.apply_cli(cli);
+        config.validate()?;
+
+        Ok((config, path))
+    }
+
+    /// Reads a configuration file, with anything it leaves out defaulted
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text =
+            fs::read_to_string(path).map_err(|e| ConfigError::IOError(PathBuf::from(path), e))?;
+
+        let config: Config =
+            toml::from_str(&text).map_err(|e| ConfigError::Parse(PathBuf::from(path), e))?;
+
+        config
+            .credentials
+            .check_single_password(&format!("`{}`", path.display()))?;
+        Ok(config)
+    }
+
+    /// Overrides settings with any `ZINHARO_*` env vars set
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        if let Some(value) = env_string("ZINHARO_API_URL") {
+            self.api.url = value;
+        }
+
+        if let Some(value) = env_string("ZINHARO_USERNAME") {
+            self.credentials.username = Some(value);
+        }
+
+        match (
+            env_string("ZINHARO_PASSWORD"),
+            env_string("ZINHARO_PASSWORD_FILE"),
+        ) {
+            (Some(_), Some(_)) => {
+                return Err(ConfigError::Invalid(String::from(
+                    "`ZINHARO_PASSWORD` and `ZINHARO_PASSWORD_FILE` can't both be set",
+                )))
+            }
+            (Some(password), None) => {
+                self.credentials.password = Some(password);
+                self.credentials.password_file = None;
+            }
+            (None, Some(path)) => {
+                self.credentials.password = None;
+                self.credentials.password_file = Some(PathBuf::from(path));
+            }
+            (None, None) => (),
+        }
+
+        if let Some(value) = env_bool("ZINHARO_SIGNUP")? {
+            self.credentials.signup = value;
+        }
+
+        if let Some(value) = env_string("ZINHARO_WORK_DIR").map(PathBuf::from) {
+            self.paths.work_dir = value;
+        }
+
+        if let Some(value) = env_string("ZINHARO_STATE_DIR").map(PathBuf::from) {
+            self.paths.state_dir = value;
+        }
+
+        if let Some(value) = env_string("ZINHARO_WORDLIST_DIR").map(PathBuf::from) {
+            self.wordlists.dir = value;
+        }
+
+        if let Some(value) = env_string("ZINHARO_WORDLIST_CACHE_DIR").map(PathBuf::from) {
+            self.wordlists.cache_dir = value;
+        }
+
+        if let Some(value) = env_string("ZINHARO_WORDLIST_URL") {
+            self.wordlists.fallback_url = value;
+        }
+
+        if let Some(value) = env_bool("ZINHARO_WORDLIST_FREQUENCY")? {
+            self.wordlists.frequency_order = value;
+        }
+
+        if let Some(value) = env_bool("ZINHARO_WORDLIST_STREAM")? {
+            self.wordlists.stream = value;
+        }
+
+        if let Some(value) = env_parse("ZINHARO_CRACKER_BACKEND")? {
+            self.cracker.backend = value;
+        }
+
+        if let Some(value) = env_string("ZINHARO_CRACKER").map(PathBuf::from) {
+            self.cracker.path = value;
+        }
+
+        if let Some(value) = env_parse("ZINHARO_WORKERS")? {
+            self.cracker.workers = value;
+        }
+
+        if let Some(value) = env_parse("ZINHARO_CRACK_TIMEOUT")? {
+            self.cracker.timeout_secs = Some(value);
+        }
+
+        if let Some(value) = env_parse("ZINHARO_CRACK_SILENCE")? {
+            self.cracker.silence_secs = value;
+        }
+
+        if let Some(value) = env_parse("ZINHARO_CRACK_CPU_SECS")? {
+            self.cracker.cpu_secs = Some(value);
+        }
+
+        if let Some(value) = env_parse("ZINHARO_CRACK_MEMORY_MB")? {
+            self.cracker.memory_mb = Some(value);
+        }
+
+        if let Some(value) = env_parse("ZINHARO_CRACK_NICE")? {
+            self.cracker.nice = Some(value);
+        }
+
+        if let Some(value) = env_string("ZINHARO_CRACK_IONICE") {
+            self.cracker.ionice = Some(value);
+        }
+
+        if let Some(value) = env_parse("ZINHARO_PROGRESS_INTERVAL")? {
+            self.logging.progress_interval_secs = value;
+        }
+
+        if let Some(value) = env_parse("ZINHARO_UPLOAD_CONCURRENCY")? {
+            self.upload.concurrency = value;
+        }
+
+        if let Some(value) = env_parse("ZINHARO_HISTORY_RETENTION_DAYS")? {
+            self.history.retention_days = value;
+        }
+
+        if let Some(disabled) = env_bool("ZINHARO_POTFILE_DISABLED")? {
+            self.potfile.enabled = !disabled;
+        }
+
+        Ok(())
+    }
+
+    /// Overrides settings with any flags given on the command line
+    fn apply_cli(&mut self, cli: &Cli) {
+        if let Some(url) = &cli.api_url {
+            self.api.url = String::clone(url);
+        }
+
+        if let Some(username) = &cli.username {
+            self.credentials.username = Some(String::clone(username));
+        }
+
+        if let Some(path) = &cli.password_file {
+            // a file asked for on the command line beats a password from
+            // anywhere else
+            self.credentials.password = None;
+            self.credentials.password_file = Some(PathBuf::clone(path));
+        }
+
+        self.credentials.signup |= cli.signup;
+
+        if let Some(dir) = &cli.work_dir {
+            self.paths.work_dir = PathBuf::clone(dir);
+        }
+
+        if let Some(dir) = &cli.state_dir {
+            self.paths.state_dir = PathBuf::clone(dir);
+        }
+
+        if let Some(dir) = &cli.wordlist_dir {
+            self.wordlists.dir = PathBuf::clone(dir);
+        }
+
+        if let Some(path) = &cli.cracker {
+            self.cracker.path = PathBuf::clone(path);
+        }
+
+        if let Some(workers) = cli.workers {
+            self.cracker.workers = workers;
+        }
+
+        if let Some(secs) = cli.progress_interval {
+            self.logging.progress_interval_secs = secs;
+        }
+
+        if let Some(Command::Upload {
+            concurrency: Some(concurrency),
+            ..
+        }) = &cli.command
+        {
+            self.upload.concurrency = *concurrency;
+        }
+    }
+
+    /// Checks every setting can be used, normalising the api url so routes
+    /// can be appended straight to it
+    fn validate(&mut self) -> Result<(), ConfigError> {
+        if !self.api.url.starts_with("http://") && !self.api.url.starts_with("https://") {
+            return Err(ConfigError::Invalid(format!(
+                "api url should start with `http://` or `https://`, not `{}`",
+                self.api.url
+            )));
+        }
+
+        if !self.api.url.ends_with('/') {
+            self.api.url.push('/');
+        }
+
+        if self.cracker.workers == 0 {
+            return Err(ConfigError::Invalid(String::from(
+                "the number of workers should be above zero",
+            )));
+        }
+
+        if self.upload.concurrency == 0 || self.upload.max_attempts == 0 {
+            return Err(ConfigError::Invalid(String::from(
+                "upload concurrency and attempts should be above zero",
+            )));
+        }
+
+        self.cracker.limits()?;
+
+        Ok(())
+    }
+
+    /// Gives a copy safe to print, with secrets replaced
+    pub fn redacted(&self) -> Self {
+        let mut redacted = Config::clone(self);
+
+        if redacted.credentials.password.is_some() {
+            redacted.credentials.password = Some(String::from(REDACTED));
+        }
+
+        redacted
+    }
+
+    /// Renders the configuration as TOML, the same way it is written in a
+    /// configuration file
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Command, ConfigAction};
+    use clap::Parser;
+
+    #[test]
+    fn layers_and_redacts() {
+        let file = "[api]\nurl = \"https://zinharo.example/api\"\n\n\
+                    [credentials]\nusername = \"owez\"\npassword = \"hunter22\"\n\n\
+                    [cracker]\nworkers = 2\nnice = 10\n";
+        let mut config: Config = toml::from_str(file).unwrap();
+        let cli = Cli::parse_from(["zinharo-client", "--workers", "4", "config", "check"]);
+
+        config.apply_cli(&cli);
+        config.validate().unwrap();
+
+        assert_eq!(config.api.url, "https://zinharo.example/api/");
+        assert_eq!(config.cracker.workers, 4);
+        assert_eq!(config.cracker.limits().unwrap().nice, Some(10));
+        assert_eq!(config.logging, LoggingConfig::default());
+        assert!(matches!(
+            cli.command,
+            Some(Command::Config {
+                action: ConfigAction::Check
+            })
+        ));
+
+        let printed = config.redacted().to_toml();
+        assert!(printed.contains(REDACTED));
+        assert!(!printed.contains("hunter22"));
+        assert_eq!(
+            toml::from_str::<Config>(&printed).unwrap().redacted(),
+            config.redacted()
+        );
+
+        assert!(toml::from_str::<Config>("[cracker]\nthreads = 2\n").is_err());
+        config.cracker.ionice = Some(String::from("realtime"));
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn switches_and_passwords() {
+        // a name no other test or setting reads, as env vars are per process
+        let name = "ZINHARO_TEST_SWITCH";
+
+        for (value, expected) in [("true", true), ("1", true), ("FALSE", false), ("0", false)] {
+            env::set_var(name, value);
+            assert_eq!(env_bool(name).unwrap(), Some(expected));
+        }
+
+        env::set_var(name, "off");
+        assert!(env_bool(name).is_err());
+        env::remove_var(name);
+        assert_eq!(env_bool(name).unwrap(), None);
+
+        let mut credentials = Credentials {
+            password: Some(String::from("hunter22")),
+            password_file: Some(PathBuf::from("password.txt")),
+            ..Default::default()
+        };
+        assert!(credentials.check_single_password("`zinharo.toml`").is_err());
+
+        credentials.password_file = None;
+        let mut config = Config {
+            credentials,
+            ..Default::default()
+        };
+        let cli = Cli::parse_from(["zinharo-client", "--password-file", "password.txt"]);
+        config.apply_cli(&cli);
+
+        assert_eq!(config.credentials.password, None);
+        assert!(config
+            .credentials
+            .check_single_password("the command line")
+            .is_ok());
+    }
+}
@@ -0,0 +1,198 @@
+//! Coordinates the workers of a client process, so several jobs can be cracked
+//! at once whilst the api only sees one polite client. Fetch backoff is shared,
+//! meaning a ratelimit or an empty queue seen by one worker holds back all of
+//! them, and shutting down stops every worker from taking on new jobs
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use zinharo_rs::{ZinharoAccess, ZinharoError, ZinharoQueuedJob};
+
+/// How often waiting workers check if the client is shutting down
+const WAIT_STEP: Duration = Duration::from_millis(250);
+
+/// How long workers hold back from fetching after each kind of failure
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    /// After being ratelimited
+    pub ratelimited: Duration,
+
+    /// After finding no jobs available
+    pub no_jobs: Duration,
+
+    /// After any other error
+    pub error: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            ratelimited: Duration::from_secs(60),
+            no_jobs: Duration::from_secs(120),
+            error: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Shared state between all workers of one client process
+pub struct Coordinator {
+    /// When workers may next ask the api for a job
+    next_fetch: Mutex<Instant>,
+
+    /// How long to hold back after failing to fetch
+    backoff: Backoff,
+
+    /// Set once no more jobs should be taken on
+    shutdown: AtomicBool,
+
+    /// Set if shutdown was caused by a fatal error
+    failed: AtomicBool,
+}
+
+impl Coordinator {
+    /// Creates a coordinator allowing jobs to be fetched straight away
+    pub fn new(backoff: Backoff) -> Self {
+        Coordinator {
+            next_fetch: Mutex::new(Instant::now()),
+            backoff,
+            shutdown: AtomicBool::new(false),
+            failed: AtomicBool::new(false),
+        }
+    }
+
+    /// Stops every worker from taking on new jobs. `failed` marks the
+    /// shutdown as caused by a fatal error
+    pub fn shutdown(&self, failed: bool) {
+        if failed {
+            self.failed.store(true, Ordering::Relaxed);
+        }
+
+        self.shutdown.store(true, Ordering::Relaxed);
+    }
+
+    /// Checks if the client is shutting down
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown.load(Ordering::Relaxed)
+    }
+
+    /// Checks if the client shut down because of a fatal error
+    pub fn failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    /// Sleeps for `duration`, waking early if the client starts shutting down.
+    /// Gives `false` if it did
+    pub fn wait(&self, duration: Duration) -> bool {
+        let until = Instant::now() + duration;
+
+        while !self.is_shutdown() {
+            let now = Instant::now();
+
+            if now >= until {
+                return true;
+            }
+
+            thread::sleep(WAIT_STEP.min(until - now));
+        }
+
+        false
+    }
+
+    /// Holds back every worker from fetching for at least `duration`
+    fn back_off(&self, duration: Duration) {
+        let mut next_fetch = self.next_fetch.lock().unwrap();
+        *next_fetch = (*next_fetch).max(Instant::now() + duration);
+    }
+
+    /// Holds back every worker after the api ratelimited one of them
+    pub fn ratelimited(&self) -> Duration {
+        self.back_off(self.backoff.ratelimited);
+        self.backoff.ratelimited
+    }
+
+    /// Holds back every worker after an unexpected api error
+    pub fn errored(&self) -> Duration {
+        self.back_off(self.backoff.error);
+        self.backoff.error
+    }
+
+    /// Waits out any shared backoff before calling the api. Gives `false` if
+    /// the client started shutting down instead
+    pub fn wait_turn(&self) -> bool {
+        loop {
+            let next_fetch = *self.next_fetch.lock().unwrap();
+
+            if !self.wait(next_fetch.saturating_duration_since(Instant::now())) {
+                return false;
+            }
+
+            // another worker may have backed off whilst this one waited
+            if *self.next_fetch.lock().unwrap() <= Instant::now() {
+                return true;
+            }
+        }
+    }
+
+    /// Fetches a new job for a worker, waiting out any shared backoff and
+    /// retrying until one is handed out. Gives [Option::None] once the client
+    /// is shutting down
+    pub fn fetch_job(&self, access: &ZinharoAccess) -> Option<ZinharoQueuedJob> {
+        loop {
+            if !self.wait_turn() {
+                return None;
+            }
+
+            match ZinharoQueuedJob::new(access) {
+                Ok(job) => return Some(job),
+                Err(ZinharoError::Ratelimited) => {
+                    let waiting = self.ratelimited();
+                    eprintln!(
+                        "Ratelimited whilst fetching job, retrying in {} seconds..",
+                        waiting.as_secs()
+                    );
+                }
+                Err(ZinharoError::NoJobsAvailable) => {
+                    eprintln!(
+                        "No jobs currently available, asking again in {} seconds..",
+                        self.backoff.no_jobs.as_secs()
+                    );
+                    self.back_off(self.backoff.no_jobs);
+                }
+                Err(e) => {
+                    let waiting = self.errored();
+                    eprintln!(
+                        "Unknown error whilst fetching job, retrying in {} seconds..\n{:?}",
+                        waiting.as_secs(),
+                        e
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shutdown_interrupts_waits() {
+        let coordinator = Coordinator::new(Backoff::default());
+
+        assert!(coordinator.wait(Duration::from_millis(10)));
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(50));
+                coordinator.shutdown(false);
+            });
+
+            let started = Instant::now();
+            assert!(!coordinator.wait(Duration::from_secs(30)));
+            assert!(started.elapsed() < Duration::from_secs(5));
+        });
+
+        assert!(!coordinator.failed());
+    }
+}
@@ -0,0 +1,526 @@
+//! Runs the cracker (aircrack-ng) against a dumped capture, feeding it
+//! candidates from a wordlist or a keyspace slice through its stdin so the
+//! client always knows how far through the candidates it has got
+
+use crate::keyspace;
+use crate::outcome::{self, Network};
+use crate::preprocess::{PreprocessOptions, WPA_MAX_LEN, WPA_MIN_LEN};
+use crate::progress::{self, CrackProgress, ProgressParser};
+use crate::supervisor::{self, Limits, Verdict, Watchdog};
+use crate::wordlist::{self, Wordlist};
+use std::fs::{self, File};
+use std::io::{self, prelude::*, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use zinharo_rs::{ZinharoRange, ZinharoSlice};
+
+/// How often the running cracker is checked on
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How long checking a single key or listing networks may take before the
+/// cracker is killed
+const QUICK_RUN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Bytes kept from the end of each of the cracker's output streams, being
+/// more than its final screen and any error it exits with. Runs last for
+/// hours, so everything before is only parsed as it goes by
+const OUTPUT_TAIL: usize = 64 * 1024;
+
+/// Writes candidates into the cracker's stdin, returning how many were written
+type Feeder = Box<dyn FnOnce(&mut dyn Write) -> io::Result<u64> + Send>;
+
+/// Where the candidates for a job come from
+#[derive(Debug, Clone)]
+pub enum Candidates {
+    /// Every candidate of a preprocessed wordlist
+    Wordlist(Wordlist),
+
+    /// Only the candidates covered by a keyspace slice. Wordlist slices read
+    /// from the raw `wordlist` the slice indexes into, filtered by `options`
+    Slice {
+        slice: ZinharoSlice,
+        wordlist: Option<PathBuf>,
+        options: PreprocessOptions,
+    },
+}
+
+impl Candidates {
+    /// Gives how many candidates will be fed when it can be known upfront.
+    /// Line slices give an upper bound, as filtered lines are never fed
+    pub fn keyspace(&self) -> Option<u64> {
+        match self {
+            Candidates::Wordlist(_) => None,
+            Candidates::Slice { slice, options, .. } => match &slice.range {
+                ZinharoRange::Lines { start, end } => Some(end.saturating_sub(*start)),
+                ZinharoRange::Bytes { .. } => None,
+                ZinharoRange::Mask { mask, start, end } => {
+                    keyspace::parse_mask(mask).map(|positions| {
+                        if keyspace::mask_fits(&positions, options) {
+                            (*end)
+                                .min(keyspace::mask_keyspace(&positions))
+                                .saturating_sub(*start)
+                        } else {
+                            0
+                        }
+                    })
+                }
+            },
+        }
+    }
+
+    /// Builds a feeder that writes every candidate to the cracker's stdin
+    fn feeder(&self) -> io::Result<Feeder> {
+        match self {
+            Candidates::Wordlist(Wordlist::Plain(path)) => {
+                let mut plain = BufReader::new(File::open(path)?);
+                Ok(Box::new(move |out| io::copy(&mut plain, out)))
+            }
+            Candidates::Wordlist(Wordlist::Compressed(path)) => {
+                let mut decoded = wordlist::open_decoded(path)?;
+                Ok(Box::new(move |out| io::copy(&mut decoded, out)))
+            }
+            Candidates::Slice {
+                slice,
+                wordlist,
+                options,
+            } => {
+                let (slice, wordlist, options) = (
+                    ZinharoSlice::clone(slice),
+                    wordlist.clone(),
+                    options.clone(),
+                );
+
+                Ok(Box::new(move |out| {
+                    keyspace::write_slice(&slice, wordlist.as_deref(), &options, out)
+                }))
+            }
+        }
+    }
+}
+
+/// Sits between a [Feeder] and the cracker, dropping the first `skip` lines
+/// (candidates already tried before a resume) and counting every line passed
+/// on into `fed`
+struct CandidateWriter<W: Write> {
+    inner: W,
+    skip: u64,
+    fed: Arc<AtomicU64>,
+}
+
+impl<W: Write> Write for CandidateWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+
+        while self.skip > 0 {
+            match rest.iter().position(|byte| *byte == b'\n') {
+                Some(pos) => {
+                    rest = &rest[pos + 1..];
+                    self.skip -= 1;
+                }
+                None => return Ok(buf.len()),
+            }
+        }
+
+        self.inner.write_all(rest)?;
+
+        let lines = rest.iter().filter(|byte| **byte == b'\n').count();
+        self.fed.fetch_add(lines as u64, Ordering::Relaxed);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes candidates into the cracker's stdin. aircrack-ng closes its stdin
+/// once a key is found, so a broken pipe here is expected and harmless
+fn feed(stdin: ChildStdin, feeder: Feeder, skip: u64, fed: Arc<AtomicU64>) -> io::Result<u64> {
+    let mut writer = CandidateWriter {
+        inner: BufWriter::new(stdin),
+        skip,
+        fed,
+    };
+
+    let written = feeder(&mut writer)?;
+    writer.flush()?;
+
+    Ok(written)
+}
+
+/// Turns the contents of aircrack-ng's key file into the raw passphrase.
+/// Passphrases are arbitrary bytes so nothing is decoded, only a single
+/// trailing line ending is dropped
+pub fn normalize_key(mut key: Vec<u8>) -> Vec<u8> {
+    if key.ends_with(b"\n") {
+        key.pop();
+
+        if key.ends_with(b"\r") {
+            key.pop();
+        }
+    }
+
+    key
+}
+
+/// Reads all of a child's output stream, keeping only its last
+/// [OUTPUT_TAIL] bytes. Bytes read are added to `activity` and handed to
+/// `each` as they arrive
+fn read_tail<R: Read, F: FnMut(&[u8])>(
+    stream: Option<R>,
+    activity: &AtomicU64,
+    mut each: F,
+) -> Vec<u8> {
+    let mut tail = Vec::new();
+    let mut buf = [0; 4096];
+
+    if let Some(mut stream) = stream {
+        while let Ok(read) = stream.read(&mut buf) {
+            if read == 0 {
+                break;
+            }
+
+            activity.fetch_add(read as u64, Ordering::Relaxed);
+            each(&buf[..read]);
+            tail.extend_from_slice(&buf[..read]);
+
+            // trimmed in bulk, so old output isn't shifted on every read
+            trim_tail(&mut tail, OUTPUT_TAIL * 2);
+        }
+    }
+
+    trim_tail(&mut tail, OUTPUT_TAIL);
+    tail
+}
+
+/// Drops the oldest bytes of `tail` once it is longer than `max`, down to
+/// [OUTPUT_TAIL]
+fn trim_tail(tail: &mut Vec<u8>, max: usize) {
+    if tail.len() > max {
+        tail.drain(..tail.len() - OUTPUT_TAIL);
+    }
+}
+
+/// Reads a child's output stream in the background so it can't fill its
+/// pipe and stall whilst being polled, giving its tail once it ends. Bytes
+/// read are added to `activity`
+fn collect<R: Read + Send + 'static>(
+    stream: Option<R>,
+    activity: Arc<AtomicU64>,
+) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || read_tail(stream, &activity, |_| ()))
+}
+
+/// Reads the cracker's stdout in the background like [collect], parsing its
+/// status screens into `progress` along the way
+fn collect_progress<R: Read + Send + 'static>(
+    stream: Option<R>,
+    progress: Sender<CrackProgress>,
+    activity: Arc<AtomicU64>,
+) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut parser = ProgressParser::new(progress);
+        let tail = read_tail(stream, &activity, |chunk| parser.feed(chunk));
+
+        parser.finish();
+        tail
+    })
+}
+
+/// Waits up to `timeout` for a short-lived cracker run to exit, killing it
+/// otherwise. Gives if it exited in time
+fn wait_within(child: &mut Child, timeout: Duration) -> io::Result<bool> {
+    let started = Instant::now();
+
+    while child.try_wait()?.is_none() {
+        if started.elapsed() >= timeout {
+            supervisor::kill_group(child);
+            child.wait()?;
+            return Ok(false);
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    Ok(true)
+}
+
+/// A run of aircrack-ng against a dumped capture
+#[derive(Debug, Clone)]
+pub struct Aircrack {
+    /// Capture to crack
+    pub cap_path: PathBuf,
+
+    /// File aircrack-ng writes the key to once found
+    pub output_path: PathBuf,
+
+    /// Network to attack when the capture has several, passed as `-b`
+    pub bssid: Option<String>,
+
+    /// CPU threads the cracker may use, passed as `-p`. If [Option::None],
+    /// aircrack-ng uses every CPU
+    pub threads: Option<usize>,
+
+    /// Limits the cracker is supervised under
+    pub limits: Limits,
+
+    /// Path to the aircrack-ng executable
+    pub program: PathBuf,
+}
+
+impl Aircrack {
+    /// Builds the aircrack-ng command reading candidates from stdin and
+    /// writing any key found to `output_path`
+    fn command(&self, output_path: &Path) -> Command {
+        let mut command = Command::new(&self.program);
+        command.arg(&self.cap_path);
+
+        if let Some(bssid) = &self.bssid {
+            command.args(["-b", bssid]);
+        }
+
+        if let Some(threads) = self.threads {
+            command.arg("-p").arg(threads.to_string());
+        }
+
+        command
+            .args(["-w", "-", "-l"])
+            .arg(output_path)
+            .stdin(Stdio::piped());
+        self.limits.apply(&mut command);
+
+        command
+    }
+
+    /// Checks `key` really is the key for the capture by having aircrack-ng
+    /// try it as the only candidate, so a stale or bogus key file is never
+    /// trusted. Keys spanning several lines or of a length WPA doesn't allow
+    /// can't be fed and never verify. Gives an error if aircrack-ng doesn't
+    /// finish within [QUICK_RUN_TIMEOUT]
+    pub fn verify(&self, key: &[u8]) -> io::Result<bool> {
+        // also keeps the write below from ever filling the pipe and blocking
+        if key.contains(&b'\n') || key.len() < WPA_MIN_LEN || key.len() > WPA_MAX_LEN {
+            return Ok(false);
+        }
+
+        let verify_path = self.output_path.with_extension("verify");
+        fs::remove_file(&verify_path).ok();
+
+        let mut child = self
+            .command(&verify_path)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            // exiting early just means the key wasn't accepted
+            stdin.write_all(key).ok();
+            stdin.write_all(b"\n").ok();
+        }
+
+        if !wait_within(&mut child, QUICK_RUN_TIMEOUT)? {
+            fs::remove_file(&verify_path).ok();
+
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "aircrack-ng took too long checking the key",
+            ));
+        }
+
+        let verified = match fs::read(&verify_path) {
+            Ok(found) => normalize_key(found) == key,
+            Err(_) => false,
+        };
+
+        fs::remove_file(&verify_path).ok();
+        Ok(verified)
+    }
+
+    /// Lists the networks in the capture by running aircrack-ng without any
+    /// candidates, which makes it print its network table and give up
+    pub fn networks(&self) -> io::Result<Vec<Network>> {
+        let mut command = Command::new(&self.program);
+        command
+            .arg(&self.cap_path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        self.limits.apply(&mut command);
+
+        let mut child = command.spawn()?;
+
+        let stdout = collect(child.stdout.take(), Arc::new(AtomicU64::new(0)));
+        let stderr = collect(child.stderr.take(), Arc::new(AtomicU64::new(0)));
+
+        if !wait_within(&mut child, QUICK_RUN_TIMEOUT)? {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "aircrack-ng took too long reading the capture",
+            ));
+        }
+
+        let mut output = stdout.join().unwrap_or_default();
+        output.extend(stderr.join().unwrap_or_default());
+
+        Ok(outcome::parse_networks(&progress::strip_escapes(&output)))
+    }
+
+    /// Creates a run attacking whichever network aircrack-ng picks itself
+    pub fn new(cap_path: PathBuf, output_path: PathBuf) -> Self {
+        Aircrack {
+            cap_path,
+            output_path,
+            bssid: None,
+            threads: None,
+            limits: Limits::default(),
+            program: PathBuf::from("aircrack-ng"),
+        }
+    }
+
+    /// Runs aircrack-ng, streaming candidates into its stdin. The first
+    /// `skip` candidates are left out (used when resuming) and `fed` is kept
+    /// updated with how many candidates have been handed over in total. Live
+    /// progress read from the cracker is sent to `progress` and setting
+    /// `cancel` kills the cracker early. The run is supervised under
+    /// [Aircrack::limits], giving how it ended alongside the output
+    pub fn run(
+        &self,
+        candidates: &Candidates,
+        skip: u64,
+        fed: Arc<AtomicU64>,
+        progress: Sender<CrackProgress>,
+        cancel: &AtomicBool,
+    ) -> io::Result<(Output, Verdict)> {
+        let feeder = candidates.feeder()?;
+
+        fed.store(skip, Ordering::Relaxed);
+
+        let mut child = self
+            .command(&self.output_path)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let activity = Arc::new(AtomicU64::new(0));
+        let consumed = Arc::clone(&fed);
+        let feeding = child
+            .stdin
+            .take()
+            .map(|stdin| thread::spawn(move || feed(stdin, feeder, skip, fed)));
+        let stdout = collect_progress(child.stdout.take(), progress, Arc::clone(&activity));
+        let stderr = collect(child.stderr.take(), Arc::clone(&activity));
+        let mut watchdog = Watchdog::new(&self.limits);
+
+        let (exit, verdict) = loop {
+            if let Some(exit) = supervisor::try_reap(&child)? {
+                break (exit, Verdict::Exited);
+            }
+
+            let verdict = if cancel.load(Ordering::Relaxed) {
+                Some(Verdict::Cancelled)
+            } else {
+                watchdog.check(activity.load(Ordering::Relaxed) + consumed.load(Ordering::Relaxed))
+            };
+
+            if let Some(verdict) = verdict {
+                supervisor::kill_group(&mut child);
+                break (supervisor::reap(&child)?, verdict);
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        };
+
+        if let Some(feeding) = feeding {
+            feeding.join().ok();
+        }
+
+        let output = Output {
+            status: exit.status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        };
+
+        let verdict = match verdict {
+            Verdict::Exited => self
+                .limits
+                .limit_hit(&exit, &progress::strip_escapes(&output.stderr))
+                .map_or(Verdict::Exited, Verdict::ResourceLimit),
+            verdict => verdict,
+        };
+
+        Ok((output, verdict))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn candidate_writer_skips_and_counts() {
+        let fed = Arc::new(AtomicU64::new(2));
+        let mut writer = CandidateWriter {
+            inner: Vec::new(),
+            skip: 2,
+            fed: Arc::clone(&fed),
+        };
+
+        writer.write_all(b"password\nletm").unwrap();
+        writer.write_all(b"ein123\nhunter22\nqwerty").unwrap();
+        writer.write_all(b"uiop\n").unwrap();
+
+        assert_eq!(writer.inner, b"hunter22\nqwertyuiop\n");
+        assert_eq!(fed.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn output_tail_is_bounded() {
+        let output = vec![b'x'; OUTPUT_TAIL * 3];
+        let activity = AtomicU64::new(0);
+        let mut seen = 0;
+        let tail = read_tail(
+            Some(&[&output[..], b"KEY FOUND!"].concat()[..]),
+            &activity,
+            |chunk| seen += chunk.len(),
+        );
+
+        assert_eq!(seen, OUTPUT_TAIL * 3 + 10);
+        assert_eq!(activity.load(Ordering::Relaxed), seen as u64);
+        assert_eq!(tail.len(), OUTPUT_TAIL);
+        assert!(tail.ends_with(b"xKEY FOUND!"));
+    }
+
+    #[test]
+    fn quick_runs_are_bounded() {
+        let mut command = Command::new("sleep");
+        command.arg("30");
+        Limits::default().apply(&mut command);
+
+        let started = Instant::now();
+        let mut child = command.spawn().unwrap();
+
+        assert!(!wait_within(&mut child, Duration::from_millis(300)).unwrap());
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(child.try_wait().unwrap().is_some());
+
+        let verifier = Aircrack {
+            program: PathBuf::from("/nonexistent/aircrack-ng"),
+            ..Aircrack::new(PathBuf::from("a.cap"), PathBuf::from("key.txt"))
+        };
+        assert!(!verifier.verify(b"short").unwrap());
+        assert!(!verifier.verify(&[b'a'; 64]).unwrap());
+    }
+
+    #[test]
+    fn keys_lose_one_line_ending() {
+        assert_eq!(normalize_key(b"hunter22\r\n".to_vec()), b"hunter22");
+        assert_eq!(normalize_key(b"hunter22 \n\n".to_vec()), b"hunter22 \n");
+        assert_eq!(normalize_key(b"\xffpass\x00".to_vec()), b"\xffpass\x00");
+    }
+}
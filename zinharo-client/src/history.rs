@@ -0,0 +1,496 @@
+//! Local history of every job the client worked on, kept as JSON lines in the
+//! state directory so it can be looked back on with the `history` subcommand
+//! long after the logs have scrolled away. Old records are pruned whenever
+//! the history is opened to be written to, according to the `[history]`
+//! retention settings
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use zinharo_rs::ZinharoQueuedJob;
+
+/// Filename of the history inside the state directory
+pub const HISTORY_FILE: &str = "history.jsonl";
+
+/// Filename of the secret key fingerprints are made with, inside the state
+/// directory
+pub const SECRET_FILE: &str = "history.key";
+
+/// Length of the secret key fingerprints are made with
+const SECRET_LEN: usize = 32;
+
+/// Block length of SHA-256, which HMAC pads its key to
+const SHA256_BLOCK_LEN: usize = 64;
+
+/// Hex digits of a found key's HMAC kept as its fingerprint
+const KEY_FINGERPRINT_LEN: usize = 16;
+
+/// Stage of a job whose capture was downloaded into its working directory
+pub const STAGE_DOWNLOAD: &str = "download";
+
+/// Stage of a job whose key was found in the potfile
+pub const STAGE_POTFILE: &str = "potfile";
+
+/// Stage of a job whose found key was checked against its capture
+pub const STAGE_VERIFY: &str = "verify";
+
+/// How a job ended for this client
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// A key was found, verified and queued for submission
+    Found,
+
+    /// The job's hash was reported, like for an exhausted wordlist
+    Reported,
+
+    /// The job was handed back for someone else to crack
+    Released,
+
+    /// The job was handed back after making the cracker fail
+    Abandoned,
+
+    /// The job was checkpointed by a shutdown, to be resumed on restart
+    Interrupted,
+
+    /// The job's lease was lost whilst cracking
+    LeaseLost,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Found => write!(f, "found"),
+            Outcome::Reported => write!(f, "reported"),
+            Outcome::Released => write!(f, "released"),
+            Outcome::Abandoned => write!(f, "abandoned"),
+            Outcome::Interrupted => write!(f, "interrupted"),
+            Outcome::LeaseLost => write!(f, "lease_lost"),
+        }
+    }
+}
+
+impl FromStr for Outcome {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "found" => Ok(Outcome::Found),
+            "reported" => Ok(Outcome::Reported),
+            "released" => Ok(Outcome::Released),
+            "abandoned" => Ok(Outcome::Abandoned),
+            "interrupted" => Ok(Outcome::Interrupted),
+            "lease_lost" => Ok(Outcome::LeaseLost),
+            other => Err(format!(
+                "unknown outcome `{}`, expected one of found, reported, released, abandoned, interrupted or lease_lost",
+                other
+            )),
+        }
+    }
+}
+
+/// What the client did with a single job
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobRecord {
+    /// ID of the job
+    pub job_id: i32,
+
+    /// ID of the hash the job was for
+    pub hash_id: i32,
+
+    /// When the job was fetched, as RFC 3339
+    pub fetched: String,
+
+    /// When the client was done with the job, as RFC 3339
+    pub finished: String,
+
+    /// Stages worked through in order, from downloading the job through each
+    /// attack to what was sent back for it
+    pub stages: Vec<String>,
+
+    /// Candidates known to have been tested, if cracking got that far
+    pub keys_tested: Option<u64>,
+
+    /// How the job ended
+    pub outcome: Outcome,
+
+    /// Why it ended that way, like the reason it was reported for
+    pub detail: Option<String>,
+
+    /// Fingerprint of the found key, so keys can be matched up without the
+    /// history holding them. Made with a secret kept only on this install,
+    /// so short keys can't be brute-forced back from the history alone
+    pub key_fingerprint: Option<String>,
+}
+
+impl JobRecord {
+    /// Starts a record of `job`, fetched at `fetched`, which is released
+    /// unless told otherwise
+    pub fn new(job: &ZinharoQueuedJob, fetched: DateTime<Utc>) -> Self {
+        JobRecord {
+            job_id: job.id,
+            hash_id: job.hash_id,
+            fetched: fetched.to_rfc3339(),
+            finished: fetched.to_rfc3339(),
+            stages: vec![],
+            keys_tested: None,
+            outcome: Outcome::Released,
+            detail: None,
+            key_fingerprint: None,
+        }
+    }
+
+    /// Adds a stage the job moved on to
+    pub fn stage<S: Into<String>>(&mut self, stage: S) {
+        self.stages.push(stage.into());
+    }
+
+    /// Sets how the job ended, adding what was done with it as its last
+    /// stage
+    pub fn ended(&mut self, outcome: Outcome, detail: Option<String>) {
+        let last = match outcome {
+            Outcome::Found => Some("submit"),
+            Outcome::Reported => Some("report"),
+            Outcome::Released | Outcome::Abandoned => Some("release"),
+            Outcome::Interrupted => Some("checkpoint"),
+            Outcome::LeaseLost => None,
+        };
+
+        if let Some(last) = last {
+            self.stage(last);
+        }
+
+        self.outcome = outcome;
+        self.detail = detail;
+    }
+
+    /// Gives when the client was done with the job
+    pub fn finished_at(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.finished)
+            .ok()
+            .map(|finished| finished.with_timezone(&Utc))
+    }
+
+    /// Renders the record as a CSV row, in the order of [CSV_HEADER]
+    pub fn to_csv(&self) -> String {
+        let fields = [
+            self.job_id.to_string(),
+            self.hash_id.to_string(),
+            String::clone(&self.fetched),
+            String::clone(&self.finished),
+            self.stages.join(";"),
+            self.keys_tested.map(|x| x.to_string()).unwrap_or_default(),
+            self.outcome.to_string(),
+            self.detail.clone().unwrap_or_default(),
+            self.key_fingerprint.clone().unwrap_or_default(),
+        ];
+
+        fields
+            .iter()
+            .map(|field| csv_field(field))
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+}
+
+/// Header row of the CSV export
+pub const CSV_HEADER: &str =
+    "job_id,hash_id,fetched,finished,stages,keys_tested,outcome,detail,key_fingerprint";
+
+/// Quotes `field` for CSV if it holds anything that would break the row up
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        String::from(field)
+    }
+}
+
+/// Gives the HMAC-SHA256 of `message` under `secret`, as in RFC 2104
+fn hmac_sha256(secret: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut block = [0; SHA256_BLOCK_LEN];
+
+    if secret.len() > SHA256_BLOCK_LEN {
+        block[..32].copy_from_slice(&Sha256::digest(secret));
+    } else {
+        block[..secret.len()].copy_from_slice(secret);
+    }
+
+    let padded = |pad: u8| block.iter().map(|byte| byte ^ pad).collect::<Vec<u8>>();
+
+    let mut inner = Sha256::new();
+    inner.update(padded(0x36));
+    inner.update(message);
+
+    let mut outer = Sha256::new();
+    outer.update(padded(0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().to_vec()
+}
+
+/// Loads the secret key fingerprints are made with from `state_dir`, making
+/// a random one readable only by the current user if there isn't one yet
+fn load_secret(state_dir: &Path) -> io::Result<[u8; SECRET_LEN]> {
+    let path = state_dir.join(SECRET_FILE);
+    let mut secret = [0; SECRET_LEN];
+
+    let created = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path);
+
+    match created {
+        Ok(mut file) => {
+            File::open("/dev/urandom")?.read_exact(&mut secret)?;
+            file.write_all(&secret)?;
+            file.sync_data()?;
+        }
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            let mut file = File::open(&path)?;
+            let len = file.read(&mut secret)?;
+
+            if len != SECRET_LEN || file.read(&mut [0])? != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("`{}` is not a {} byte secret", path.display(), SECRET_LEN),
+                ));
+            }
+
+            // may have been copied in with looser permissions
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        }
+        Err(e) => return Err(e),
+    }
+
+    Ok(secret)
+}
+
+/// How long records are kept for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Retention {
+    /// Days records are kept for, if limited
+    pub max_age_days: Option<u32>,
+
+    /// Most records kept, dropping the oldest first, if limited
+    pub max_records: Option<usize>,
+}
+
+impl Retention {
+    /// Drops records from `records`, oldest first, which shouldn't be kept
+    /// any longer as of `now`
+    fn prune(&self, records: &mut Vec<JobRecord>, now: DateTime<Utc>) {
+        if let Some(days) = self.max_age_days {
+            let cutoff = now - ChronoDuration::days(days as i64);
+
+            // unreadable times are kept rather than silently dropped
+            records.retain(|record| record.finished_at().is_none_or(|t| t >= cutoff));
+        }
+
+        if let Some(max) = self.max_records {
+            let excess = records.len().saturating_sub(max);
+            records.drain(..excess);
+        }
+    }
+}
+
+/// Job history appended to by every worker
+pub struct History {
+    path: PathBuf,
+    lock: Mutex<()>,
+
+    /// Secret found keys are fingerprinted with, if opened to be written to
+    secret: Option<[u8; SECRET_LEN]>,
+}
+
+impl History {
+    /// Opens the history in `state_dir`, pruning records which are past
+    /// `retention` and loading the secret found keys are fingerprinted with
+    pub fn open(state_dir: &Path, retention: Retention) -> io::Result<Self> {
+        fs::create_dir_all(state_dir)?;
+
+        let history = History {
+            path: state_dir.join(HISTORY_FILE),
+            lock: Mutex::new(()),
+            secret: Some(load_secret(state_dir)?),
+        };
+
+        let mut records = history.records()?;
+        let total = records.len();
+        retention.prune(&mut records, Utc::now());
+
+        if records.len() != total {
+            history.rewrite(&records)?;
+        }
+
+        Ok(history)
+    }
+
+    /// Opens the history in `state_dir` only to read it back, leaving every
+    /// record in place. The file is never written to, so a client running
+    /// alongside can't lose records to a rewrite
+    pub fn open_read_only(state_dir: &Path) -> Self {
+        History {
+            path: state_dir.join(HISTORY_FILE),
+            lock: Mutex::new(()),
+            secret: None,
+        }
+    }
+
+    /// Gives the fingerprint of a found `key`, or [Option::None] if the
+    /// history was opened read-only
+    pub fn key_fingerprint(&self, key: &[u8]) -> Option<String> {
+        let mut fingerprint = hex::encode(hmac_sha256(&self.secret?, key));
+        fingerprint.truncate(KEY_FINGERPRINT_LEN);
+        Some(fingerprint)
+    }
+
+    /// Reads back every record, oldest first
+    pub fn records(&self) -> io::Result<Vec<JobRecord>> {
+        let file = match File::open(&self.path) {
+            Ok(x) => x,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+
+        let mut records = vec![];
+
+        for line in BufReader::new(file).lines() {
+            match serde_json::from_str(&line?) {
+                Ok(record) => records.push(record),
+                Err(e) => eprintln!("Skipping unreadable history record: '{}'", e),
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// Appends `record`, logging rather than failing as the history is only
+    /// for looking back on
+    pub fn record(&self, record: &JobRecord) {
+        let _guard = self.lock.lock().unwrap();
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{}", serde_json::to_string(record)?));
+
+        if let Err(e) = result {
+            eprintln!(
+                "Could not record job #{} in the history: '{}'",
+                record.job_id, e
+            );
+        }
+    }
+
+    /// Replaces every record with `records`
+    fn rewrite(&self, records: &[JobRecord]) -> io::Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        let part_path = self.path.with_extension("part");
+        let mut file = File::create(&part_path)?;
+
+        for record in records {
+            writeln!(file, "{}", serde_json::to_string(record)?)?;
+        }
+
+        file.sync_data()?;
+        fs::rename(part_path, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    /// Keeps every record
+    const NO_RETENTION: Retention = Retention {
+        max_age_days: None,
+        max_records: None,
+    };
+
+    #[test]
+    fn pruned_and_exported() {
+        let now = Utc::now();
+        let record = |job_id, days_ago| JobRecord {
+            job_id,
+            hash_id: 7,
+            fetched: (now - ChronoDuration::days(days_ago)).to_rfc3339(),
+            finished: (now - ChronoDuration::days(days_ago)).to_rfc3339(),
+            stages: vec![String::from("wordlist `rockyou`"), String::from("a, b")],
+            keys_tested: Some(1000),
+            outcome: Outcome::Reported,
+            detail: Some(String::from("said \"no\"")),
+            key_fingerprint: None,
+        };
+        let mut records = vec![record(1, 40), record(2, 20), record(3, 10), record(4, 0)];
+
+        Retention {
+            max_age_days: Some(30),
+            max_records: Some(2),
+        }
+        .prune(&mut records, now);
+
+        let kept: Vec<i32> = records.iter().map(|record| record.job_id).collect();
+        assert_eq!(kept, vec![3, 4]);
+
+        let row = records[0].to_csv();
+        assert!(row.starts_with("3,7,"));
+        assert!(row.ends_with(",1000,reported,\"said \"\"no\"\"\","));
+        assert!(row.contains(",\"wordlist `rockyou`;a, b\","));
+
+        let mut record = record(5, 0);
+        record.stages.clear();
+        record.stage(STAGE_DOWNLOAD);
+        record.stage("wordlist `rockyou`");
+        record.ended(Outcome::Found, None);
+        assert_eq!(
+            record.stages,
+            vec!["download", "wordlist `rockyou`", "submit"]
+        );
+    }
+
+    #[test]
+    fn keys_fingerprinted_with_secret() {
+        // RFC 4231 test cases 2 and 6
+        assert_eq!(
+            hex::encode(hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            hex::encode(hmac_sha256(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+
+        let dir = TestDir::new("history");
+        let history = History::open(dir.path(), NO_RETENTION).unwrap();
+        let fingerprint = history.key_fingerprint(b"hunter22").unwrap();
+
+        assert_eq!(fingerprint.len(), KEY_FINGERPRINT_LEN);
+        assert_ne!(fingerprint, &hex::encode(Sha256::digest(b"hunter22"))[..16]);
+
+        let mode = fs::metadata(dir.join(SECRET_FILE))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // the same install keeps fingerprinting keys the same
+        let reopened = History::open(dir.path(), NO_RETENTION).unwrap();
+        assert_eq!(reopened.key_fingerprint(b"hunter22"), Some(fingerprint));
+        assert_eq!(
+            History::open_read_only(dir.path()).key_fingerprint(b"hunter22"),
+            None
+        );
+    }
+}
@@ -0,0 +1,280 @@
+//! Candidate generation for sliced jobs, streaming only the part of the
+//! keyspace a [ZinharoSlice] covers into the cracker
+
+use crate::preprocess::{is_candidate, trim_line_ending, PreprocessOptions};
+use crate::wordlist;
+use std::io::{self, prelude::*};
+use std::path::Path;
+use zinharo_rs::{ZinharoRange, ZinharoSlice};
+
+/// Hashcat's `?s` charset of printable symbols, including space
+const SYMBOLS: &[u8] = b" !\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+
+/// Expands a hashcat-style mask into the charset used at each position,
+/// supporting `?l`, `?u`, `?d`, `?h`, `?H`, `?s`, `?a` and `??`. Returns
+/// [Option::None] for an unknown placeholder
+pub fn parse_mask(mask: &str) -> Option<Vec<Vec<u8>>> {
+    let mut positions = Vec::new();
+    let mut bytes = mask.bytes();
+
+    while let Some(byte) = bytes.next() {
+        if byte != b'?' {
+            positions.push(vec![byte]);
+            continue;
+        }
+
+        let charset: Vec<u8> = match bytes.next()? {
+            b'l' => (b'a'..=b'z').collect(),
+            b'u' => (b'A'..=b'Z').collect(),
+            b'd' => (b'0'..=b'9').collect(),
+            b'h' => (b'0'..=b'9').chain(b'a'..=b'f').collect(),
+            b'H' => (b'0'..=b'9').chain(b'A'..=b'F').collect(),
+            b's' => SYMBOLS.to_vec(),
+            b'a' => (b' '..=b'~').collect(),
+            b'?' => vec![b'?'],
+            _ => return None,
+        };
+
+        positions.push(charset);
+    }
+
+    Some(positions)
+}
+
+/// Total number of candidates a parsed mask produces, saturating on overflow
+pub fn mask_keyspace(positions: &[Vec<u8>]) -> u64 {
+    positions.iter().fold(1u64, |total, charset| {
+        total.saturating_mul(charset.len() as u64)
+    })
+}
+
+/// Checks if the candidates of a parsed mask, which all share its length,
+/// pass the length filter of `options`
+pub fn mask_fits(positions: &[Vec<u8>], options: &PreprocessOptions) -> bool {
+    positions.len() >= options.min_len && positions.len() <= options.max_len
+}
+
+/// Writes mask candidates `start..end` that pass the length filter to `out`,
+/// one per line
+fn write_mask<W: Write + ?Sized>(
+    mask: &str,
+    start: u64,
+    end: u64,
+    options: &PreprocessOptions,
+    out: &mut W,
+) -> io::Result<u64> {
+    let positions = match parse_mask(mask) {
+        Some(x) => x,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid mask `{}`", mask),
+            ))
+        }
+    };
+
+    let end = end.min(mask_keyspace(&positions));
+
+    // every candidate has the mask's length, so either all or none pass
+    if start >= end || !mask_fits(&positions, options) {
+        return Ok(0);
+    }
+
+    // odometer digits for `start`, last position changing fastest
+    let mut digits = vec![0; positions.len()];
+    let mut remaining = start;
+
+    for (digit, charset) in digits.iter_mut().zip(&positions).rev() {
+        *digit = (remaining % charset.len() as u64) as usize;
+        remaining /= charset.len() as u64;
+    }
+
+    let mut candidate: Vec<u8> = digits
+        .iter()
+        .zip(&positions)
+        .map(|(digit, charset)| charset[*digit])
+        .collect();
+    candidate.push(b'\n');
+
+    for _ in start..end {
+        out.write_all(&candidate)?;
+
+        for (pos, charset) in positions.iter().enumerate().rev() {
+            digits[pos] += 1;
+
+            if digits[pos] < charset.len() {
+                candidate[pos] = charset[digits[pos]];
+                break;
+            }
+
+            digits[pos] = 0;
+            candidate[pos] = charset[0];
+        }
+    }
+
+    Ok(end - start)
+}
+
+/// Writes wordlist lines numbered `start..end` that pass the length filter
+fn write_lines<R: BufRead, W: Write + ?Sized>(
+    mut source: R,
+    start: u64,
+    end: u64,
+    options: &PreprocessOptions,
+    out: &mut W,
+) -> io::Result<u64> {
+    let mut line = Vec::new();
+    let mut written = 0;
+
+    for index in 0..end {
+        line.clear();
+
+        if source.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+
+        let word = trim_line_ending(&line);
+
+        if index >= start && is_candidate(word, options) {
+            out.write_all(word)?;
+            out.write_all(b"\n")?;
+            written += 1;
+        }
+    }
+
+    Ok(written)
+}
+
+/// Writes wordlist lines whose first byte lies within `start..end` that pass
+/// the length filter
+fn write_bytes<R: BufRead, W: Write + ?Sized>(
+    mut source: R,
+    start: u64,
+    end: u64,
+    options: &PreprocessOptions,
+    out: &mut W,
+) -> io::Result<u64> {
+    let mut line = Vec::new();
+    let mut offset = 0;
+    let mut written = 0;
+
+    if start > 0 {
+        // skip to just before `start`, then drop the rest of any line which
+        // began in the previous slice
+        offset = io::copy(&mut (&mut source).take(start - 1), &mut io::sink())?;
+
+        if offset == start - 1 {
+            offset += source.read_until(b'\n', &mut line)? as u64;
+        }
+    }
+
+    while offset < end {
+        line.clear();
+
+        match source.read_until(b'\n', &mut line)? {
+            0 => break,
+            read => offset += read as u64,
+        }
+
+        let word = trim_line_ending(&line);
+
+        if is_candidate(word, options) {
+            out.write_all(word)?;
+            out.write_all(b"\n")?;
+            written += 1;
+        }
+    }
+
+    Ok(written)
+}
+
+/// Streams every candidate of `slice` into `out`, one per line, returning how
+/// many were written. Wordlist ranges are read from the raw `wordlist` file,
+/// decompressing it on the fly if needed
+pub fn write_slice<W: Write + ?Sized>(
+    slice: &ZinharoSlice,
+    wordlist: Option<&Path>,
+    options: &PreprocessOptions,
+    out: &mut W,
+) -> io::Result<u64> {
+    let open = || match wordlist {
+        Some(path) => wordlist::open_decoded(path),
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "wordlist slice given without a wordlist",
+        )),
+    };
+
+    match &slice.range {
+        ZinharoRange::Lines { start, end } => write_lines(open()?, *start, *end, options, out),
+        ZinharoRange::Bytes { start, end } => write_bytes(open()?, *start, *end, options, out),
+        ZinharoRange::Mask { mask, start, end } => write_mask(mask, *start, *end, options, out),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORDS: &[u8] = b"password\nshort\nletmein123\nhunter22\nqwertyuiop\n";
+
+    /// Runs a writer into a string for easy comparison
+    fn collect<F: FnOnce(&mut Vec<u8>) -> io::Result<u64>>(write: F) -> String {
+        let mut out = Vec::new();
+        write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn line_slices() {
+        let options = PreprocessOptions::default();
+
+        assert_eq!(
+            collect(|out| write_lines(WORDS, 1, 4, &options, out)),
+            "letmein123\nhunter22\n"
+        );
+    }
+
+    #[test]
+    fn byte_slices_partition_lines() {
+        let options = PreprocessOptions::default();
+        let first = collect(|out| write_bytes(WORDS, 0, 20, &options, out));
+        let second = collect(|out| write_bytes(WORDS, 20, WORDS.len() as u64, &options, out));
+
+        assert_eq!(first, "password\nletmein123\n");
+        assert_eq!(second, "hunter22\nqwertyuiop\n");
+    }
+
+    #[test]
+    fn mask_slices() {
+        let options = PreprocessOptions {
+            min_len: 1,
+            ..PreprocessOptions::default()
+        };
+        let positions = parse_mask("ab?d?l").unwrap();
+        assert_eq!(mask_keyspace(&positions), 260);
+
+        assert_eq!(
+            collect(|out| write_mask("ab?d?l", 24, 28, &options, out)),
+            "ab0y\nab0z\nab1a\nab1b\n"
+        );
+        assert_eq!(
+            collect(|out| write_mask("?d", 8, 100, &options, out)),
+            "8\n9\n"
+        );
+    }
+
+    #[test]
+    fn mask_slices_length_filtered() {
+        let options = PreprocessOptions::default();
+
+        assert_eq!(
+            collect(|out| write_mask("?d?d?d", 0, 10, &options, out)),
+            ""
+        );
+        assert_eq!(
+            collect(|out| write_mask("pass?d?d?d?d", 0, 2, &options, out)),
+            "pass0000\npass0001\n"
+        );
+    }
+}
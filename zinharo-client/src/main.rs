@@ -1,328 +1,1200 @@
-use bzip2::read::BzDecoder;
-use std::fs::File;
-use std::io::prelude::*;
-use std::path::PathBuf;
-use std::process::Command;
-use std::{env, process, thread, time};
-use zinharo_rs::{ZinharoAccess, ZinharoError, ZinharoQueuedJob};
+// process supervision, rlimits and private file permissions all rely on unix,
+// so unlike zinharo-rs the client makes no attempt at other platforms
+#[cfg(not(unix))]
+compile_error!("zinharo-client only supports unix-like systems");
+
+mod auth;
+mod checkpoint;
+mod cli;
+mod commands;
+mod config;
+mod coordinator;
+mod cracker;
+mod history;
+mod keyspace;
+mod outbox;
+mod outcome;
+mod potfile;
+mod prefetch;
+mod preprocess;
+mod progress;
+mod supervisor;
+#[cfg(test)]
+mod testdir;
+mod uploader;
+mod wordlist;
+mod workdir;
+
+use checkpoint::{AttackStage, Checkpoint, CHECKPOINT_INTERVAL, RESUME_MARGIN};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use cli::{Cli, Command, ConfigAction, PotfileAction};
+use config::Config;
+use coordinator::Coordinator;
+use cracker::{Aircrack, Candidates};
+use history::{History, JobRecord, Outcome, STAGE_DOWNLOAD, STAGE_POTFILE, STAGE_VERIFY};
+use outbox::{Outbox, Request};
+use outcome::CrackOutcome;
+use potfile::Potfile;
+use prefetch::PrefetchQueue;
+use preprocess::PreprocessOptions;
+use progress::CrackProgress;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{process, thread};
+use supervisor::{Limits, Verdict};
+use wordlist::WordlistStore;
+use workdir::JobDir;
+use zinharo_rs::{
+    HandshakeId, ReportReason, ZinharoAccess, ZinharoDiagnostics, ZinharoError, ZinharoProgress,
+    ZinharoQueuedJob,
+};
 
 /// Graffiti hackerman header message
 const HEADER_MSG: &str = " _______       _                        _____ _ _            _   \n|___  (_)     | |                      / ____| (_)          | |  \n   / / _ _ __ | |__   __ _ _ __ ___   | |    | |_  ___ _ __ | |_ \n  / / | | '_ \\| '_ \\ / _` | '__/ _ \\  | |    | | |/ _ \\ '_ \\| __|\n / /__| | | | | | | | (_| | | | (_) | | |____| | |  __/ | | | |_ \n/_____|_|_| |_|_| |_|\\__,_|_|  \\___/   \\_____|_|_|\\___|_| |_|\\__|\n\n";
 
-/// Sleeps for x seconds, used because of rusts bad std
-fn sleep_sec(secs: u64) {
-    let sleep_dur = time::Duration::from_secs(secs);
-    thread::sleep(sleep_dur);
-}
+/// Longest time between heartbeats of the job being cracked
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
 
-/// Signs up to Zinharo with new client, **should be used wisely**
-fn signup(username: &str, password: &str) -> ZinharoAccess {
-    match ZinharoAccess::signup(username, password) {
-        Ok(access) => access,
-        Err(ZinharoError::Ratelimited) => {
-            eprintln!("Ratelimited when attempting signup, retrying in 1 hour..");
+/// Shortest time between heartbeats, even for a lease about to run out
+const MIN_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 
-            sleep_sec(60 * 60);
-            signup(username, password)
-        }
-        Err(ZinharoError::UsernameTaken) => {
-            eprintln!("Signup username taken, please choose another one or toggle `ZINHARO_SIGNUP` off if it is your account!");
-            process::exit(1);
-        }
-        Err(_) => {
-            eprintln!("Unknown error whilst trying to signup, this shouldn't happen!");
-            process::exit(1);
-        }
+/// Exit status after shutting down because of a signal
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// Longest time spent sending results left in the outbox once shut down,
+/// anything still pending is sent next time the client runs
+const SHUTDOWN_DELIVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Filename of a job's dumped capture inside its directory
+const CAP_FILE: &str = "inprogress.cap";
+
+/// Prefetched jobs with less than this left on their lease are released
+/// rather than started
+const PREFETCH_LEASE_MARGIN: Duration = Duration::from_secs(60);
+
+/// How often a full prefetch queue is checked for jobs to release
+const PREFETCH_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Queues a report of the job in the outbox, to be sent by its flusher
+fn report_job(
+    outbox: &Outbox,
+    job: &ZinharoQueuedJob,
+    reason: &ReportReason,
+    diagnostics: Option<&ZinharoDiagnostics>,
+) {
+    let report = job.report_for(reason, diagnostics);
+
+    if !outbox.push(job.id, Request::Report { report }) {
+        eprintln!("Job #{} was already reported, continuing anyway..", job.id);
     }
 }
 
-/// Gets enviroment variables and logs in
-fn login_startup() -> ZinharoAccess {
-    let username = match env::var("ZINHARO_USERNAME") {
-        Ok(x) => x,
+/// Reads the key aircrack-ng dumped to `path`
+fn read_key(path: &Path) -> Option<Vec<u8>> {
+    match fs::read(path) {
+        Ok(x) => Some(cracker::normalize_key(x)),
         Err(_) => {
-            eprintln!("Please supply the `ZINHARO_USERNAME` enviroment variable!");
-            process::exit(1);
+            eprintln!("Error whilst reading password file, please check zinharo has rights to read files!");
+            None
         }
-    };
+    }
+}
 
-    let password = match env::var("ZINHARO_PASSWORD") {
-        Ok(x) => x,
-        Err(_) => {
-            eprintln!("Please supply the `ZINHARO_PASSWORD` enviroment variable!");
-            process::exit(1);
-        }
-    };
+/// Queues a found and verified wifi password in the outbox, to be sent by
+/// its flusher
+fn upload_job(outbox: &Outbox, job: &ZinharoQueuedJob, key: &[u8]) {
+    let submission = job.submission(key);
 
-    match ZinharoAccess::login(&username, &password) {
-        Ok(access) => access,
-        Err(ZinharoError::Ratelimited) => {
-            eprintln!("Ratelimited when trying to login, retrying in 30 seconds..");
+    if !outbox.push(job.id, Request::Submit { submission }) {
+        eprintln!(
+            "Key for job #{} was already submitted, continuing anyway..",
+            job.id
+        );
+    }
+}
 
-            sleep_sec(30);
-            login_startup()
-        }
-        Err(ZinharoError::ReqwestError(_)) => {
-            eprintln!("Could not connect to Zinaro API, retrying in 30 seconds..");
+/// Looks for a checkpoint left behind by a previous run and, if its job is
+/// still leased to this client, gives back the job alongside how many
+/// candidates can be skipped and when it was fetched again
+fn resume_job(
+    access: &ZinharoAccess,
+    state_dir: &Path,
+) -> Option<(ZinharoQueuedJob, u64, DateTime<Utc>)> {
+    let checkpoint = Checkpoint::load(state_dir)?;
+    let leased = ZinharoQueuedJob::fetch(access, checkpoint.job_id);
+    let fetched = Utc::now();
 
-            sleep_sec(30);
-            login_startup()
-        }
-        Err(ZinharoError::ApiVersionInadequate) => {
-            eprintln!("This client is critically out of date, please update!");
-            process::exit(1);
+    match leased {
+        Ok(job) if checkpoint.matches(&job) => {
+            println!(
+                "Resuming job #{} from candidate {}..",
+                job.id, checkpoint.offset
+            );
+            Some((job, checkpoint.offset, fetched))
         }
-        Err(ZinharoError::BadCredentials) => {
-            eprintln!("Username or password invalid, will attempt signup if the env-var\n`ZINHARO_SIGNUP` is set!");
-
-            if env::var("ZINHARO_SIGNUP").is_ok() {
-                signup(&username, &password) // return this
-            } else {
-                process::exit(1);
-            }
+        Ok(job) => {
+            eprintln!(
+                "Job #{} has changed since it was checkpointed, restarting it..",
+                job.id
+            );
+            Some((job, 0, fetched))
         }
-        Err(ZinharoError::FirewallBlock) => {
-            eprintln!("You have been temporarily blocked from the Zinharo API by\nCloudflare or a local firewall. Please ensure you are not routing\nthrough tor!");
-            process::exit(1);
+        Err(ZinharoError::JobNotLeased) => {
+            eprintln!(
+                "Checkpointed job #{} is no longer leased to this client, discarding checkpoint..",
+                checkpoint.job_id
+            );
+            Checkpoint::clear(state_dir);
+            None
         }
-        Err(_) => {
-            eprintln!("Unknown fatal error when logging in!");
-            process::exit(1);
+        Err(e) => {
+            eprintln!(
+                "Could not check lease of checkpointed job #{}, not resuming..\n{:?}",
+                checkpoint.job_id, e
+            );
+            None
         }
     }
 }
 
-/// Saves byte-formatted bzip2 to a given wordlist path while decoding it
-fn save_bzip2(compressed: bytes::Bytes, wordlist_path: PathBuf) {
-    let mut decompressor = BzDecoder::new(&compressed[..]);
-    let mut contents: Vec<u8> = Vec::new();
+/// Hands a job back to the server when this client stops working on it
+/// early. If the job itself made cracking fail, it is abandoned instead of
+/// released so the server can keep track of it
+fn release_job(access: &ZinharoAccess, job: &ZinharoQueuedJob, failed: bool) {
+    let result = if failed {
+        job.abandon(access)
+    } else {
+        job.release(access)
+    };
 
-    match decompressor.read_to_end(&mut contents) {
-        Ok(_) => (),
-        Err(_) => {
-            eprintln!("Error whilst decompressing standardized wordlist, this shouldn't happen!");
-            process::exit(1);
+    match result {
+        Ok(_) | Err(ZinharoError::JobNotLeased) => (),
+        Err(e) => eprintln!(
+            "Could not hand job #{} back to the server, it will expire on its own..\n{:?}",
+            job.id, e
+        ),
+    }
+}
+
+/// Hands back a job interrupted by shutdown. If it can be checkpointed, the
+/// lease is kept so the job is resumed once the client starts again,
+/// otherwise it is released for someone else to crack
+fn interrupt_job(access: &ZinharoAccess, job: &ZinharoQueuedJob, offset: u64, state_dir: &Path) {
+    match Checkpoint::new(job, offset).save(state_dir) {
+        Ok(_) => println!(
+            "Checkpointed job #{} at candidate {}, it will be resumed on restart..",
+            job.id, offset
+        ),
+        Err(e) => {
+            eprintln!(
+                "Could not checkpoint job #{}: '{}', releasing it instead..",
+                job.id, e
+            );
+            release_job(access, job, false);
         }
     }
+}
 
-    let fileperm_error = ", ensure file permissions are correct!";
+/// Works out how long to wait between heartbeats so a lease expiring at
+/// `lease_expires` is renewed well before it runs out
+fn heartbeat_interval(lease_expires: Option<DateTime<Utc>>) -> Duration {
+    match lease_expires.and_then(|expires| (expires - Utc::now()).to_std().ok()) {
+        Some(remaining) => (remaining / 3).clamp(MIN_HEARTBEAT_INTERVAL, HEARTBEAT_INTERVAL),
+        None => HEARTBEAT_INTERVAL,
+    }
+}
 
-    let mut wordlist_file = match File::create(wordlist_path) {
-        Ok(x) => x,
-        Err(_) => {
-            eprintln!("Could not create file to save wordlist{}", fileperm_error);
-            process::exit(1);
-        }
-    };
+/// Logs the latest progress on a job, with an ETA if the total number of
+/// candidates is known
+fn log_progress(job: &ZinharoQueuedJob, progress: &CrackProgress, keyspace: Option<u64>) {
+    let total = progress.keys_total.or(keyspace);
 
-    match wordlist_file.write_all(&contents) {
-        Ok(_) => (),
-        Err(_) => {
-            eprintln!("Could not save wordlist contents to file{}", fileperm_error);
-            process::exit(1);
-        }
-    };
+    match total.and_then(|total| progress.eta(total)) {
+        Some(eta) => println!(
+            "Job #{}: {}, ETA {}",
+            job.id,
+            progress,
+            progress::format_eta(eta)
+        ),
+        None => println!("Job #{}: {}", job.id, progress),
+    }
 }
 
-/// Gets the `rockyou.txt` wordlist from an external source and saves it to
-/// local `wordlist.txt` path
-fn get_wordlist(access: &ZinharoAccess) -> PathBuf {
-    let wordlist_path = PathBuf::from("./wordlist.txt");
+/// Why cracking stopped before the cracker finished by itself
+enum Stopped {
+    /// The job's lease was lost, so it is no longer ours to crack
+    LeaseLost,
 
-    if !wordlist_path.exists() {
-        println!("Downloading wordlist..");
+    /// The client is shutting down. The first `offset` candidates are known
+    /// to have been tested
+    Interrupted { offset: u64 },
+}
 
-        let wordlist_url = "http://downloads.skullsecurity.org/passwords/cain.txt.bz2";
-        let resp = match access.client.get(wordlist_url).send() {
-            Ok(x) => x,
-            Err(_) => {
-                eprintln!("Fatal whilst downloading wordlist, `skullsecurity.org` may be down!");
-                process::exit(1);
-            }
-        };
+/// Gives how many candidates can safely be skipped when resuming, from the
+/// latest progress read from the cracker or else how many were fed to it
+fn resume_offset(latest: Option<&CrackProgress>, fed: &AtomicU64, skip: u64) -> u64 {
+    match latest {
+        // the cracker's own count is exact, whilst candidates fed may still
+        // be sitting untested in pipe buffers
+        Some(progress) => skip + progress.keys_tested,
+        None => fed
+            .load(Ordering::Relaxed)
+            .saturating_sub(RESUME_MARGIN)
+            .max(skip),
+    }
+}
+
+/// Runs the cracker whilst following its live progress, periodically
+/// logging it, checkpointing how far through the candidates it has got and
+/// heartbeating the job's lease. Gives the cracker's output and how its
+/// supervisor saw it end alongside how many candidates are known to have been
+/// tested, or why the cracker was stopped early
+fn crack_job(
+    worker: &Worker,
+    job: &ZinharoQueuedJob,
+    aircrack: &Aircrack,
+    candidates: &Candidates,
+    skip: u64,
+) -> io::Result<Result<(Output, Verdict, u64), Stopped>> {
+    let (access, coordinator) = (&worker.shared.access, &worker.shared.coordinator);
+    let fed = Arc::new(AtomicU64::new(skip));
+    let lease_lost = AtomicBool::new(false);
+    let cancel = AtomicBool::new(false);
+    let (progress_tx, progress_rx) = mpsc::channel::<CrackProgress>();
+    let keyspace = candidates.keyspace();
+
+    thread::scope(|s| {
+        let monitor = s.spawn(|| {
+            let progress_rx = progress_rx;
+            let mut latest: Option<CrackProgress> = None;
+            let mut last_logged = Instant::now();
+            let mut last_saved = Instant::now();
+            let mut last_heartbeat = Instant::now();
+            let mut interval = heartbeat_interval(job.lease_expires);
+
+            loop {
+                match progress_rx.recv_timeout(Duration::from_secs(1)) {
+                    Ok(progress) => latest = Some(progress),
+                    Err(RecvTimeoutError::Timeout) => (),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+
+                if coordinator.is_shutdown() {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+
+                if let Some(log_interval) = worker.shared.progress_interval {
+                    if last_logged.elapsed() >= log_interval {
+                        if let Some(progress) = &latest {
+                            log_progress(job, progress, keyspace);
+                        }
+
+                        last_logged = Instant::now();
+                    }
+                }
+
+                if last_saved.elapsed() >= CHECKPOINT_INTERVAL {
+                    let offset = resume_offset(latest.as_ref(), &fed, skip);
+
+                    if let Err(e) = Checkpoint::new(job, offset).save(&worker.state_dir) {
+                        eprintln!("Could not checkpoint job #{}: '{}'", job.id, e);
+                    }
+
+                    last_saved = Instant::now();
+                }
 
-        let compressed = match resp.bytes() {
-            Ok(x) => x,
-            Err(_) => {
-                eprintln!("Could not download compressed wordlist body, may have been timed-out!");
-                process::exit(1);
+                if last_heartbeat.elapsed() >= interval {
+                    let progress = match &latest {
+                        Some(progress) => ZinharoProgress {
+                            keys_tested: skip + progress.keys_tested,
+                            keys_total: keyspace,
+                            keys_per_sec: progress.keys_per_sec,
+                        },
+                        None => ZinharoProgress {
+                            keys_tested: fed.load(Ordering::Relaxed),
+                            keys_total: keyspace,
+                            ..Default::default()
+                        },
+                    };
+
+                    match job.heartbeat(access, &progress) {
+                        Ok(expires) => interval = heartbeat_interval(Some(expires)),
+                        Err(ZinharoError::JobNotLeased) => {
+                            eprintln!("Lost lease on job #{}, stopping cracker..", job.id);
+                            lease_lost.store(true, Ordering::Relaxed);
+                            cancel.store(true, Ordering::Relaxed);
+                        }
+                        Err(e) => eprintln!(
+                            "Could not heartbeat job #{}, retrying later..\n{:?}",
+                            job.id, e
+                        ),
+                    }
+
+                    last_heartbeat = Instant::now();
+                }
             }
-        };
 
-        save_bzip2(compressed, PathBuf::clone(&wordlist_path));
-    }
+            resume_offset(latest.as_ref(), &fed, skip)
+        });
 
-    wordlist_path
+        let run = aircrack.run(candidates, skip, Arc::clone(&fed), progress_tx, &cancel);
+        let offset = monitor.join().unwrap_or(skip);
+
+        // the cracker may also have been hit by the signal itself, so its
+        // output says nothing about the job once shutting down, unless it
+        // managed to find the key first
+        let interrupted = coordinator.is_shutdown() && !aircrack.output_path.exists();
+
+        match run {
+            Ok(_) if lease_lost.load(Ordering::Relaxed) => Ok(Err(Stopped::LeaseLost)),
+            Ok(_) if interrupted => Ok(Err(Stopped::Interrupted { offset })),
+            run => run.map(|(output, verdict)| Ok((output, verdict, offset))),
+        }
+    })
 }
 
-/// In a seperate function for error handling
-fn report_job(access: &ZinharoAccess, job: ZinharoQueuedJob, info: Option<&str>) {
-    let cont_message = ", continuing anyway..";
+/// Runs the cracker until it gives a final [CrackOutcome], retrying against a
+/// single network if the capture has several but only one is crackable.
+/// Gives the outcome alongside how many candidates were tested, or why
+/// cracking was stopped early
+fn crack_until_outcome(
+    worker: &Worker,
+    job: &ZinharoQueuedJob,
+    aircrack: &mut Aircrack,
+    candidates: &Candidates,
+    skip: u64,
+) -> io::Result<Result<(CrackOutcome, u64), Stopped>> {
+    loop {
+        let (output, verdict, keys_tested) =
+            match crack_job(worker, job, aircrack, candidates, skip)? {
+                Ok(x) => x,
+                Err(stopped) => return Ok(Err(stopped)),
+            };
 
-    match job.report(access, info) {
-        Ok(_) => (),
-        Err(ZinharoError::Ratelimited) => eprintln!("Ratelimited when reporting{}", cont_message),
-        Err(_) => eprintln!("Could not report job{}", cont_message),
+        let outcome = CrackOutcome::classify(&output, &verdict, aircrack.output_path.exists());
+
+        if let CrackOutcome::NeedsSelection(networks) = &outcome {
+            if aircrack.bssid.is_none() {
+                if let Some(network) = CrackOutcome::only_crackable(networks) {
+                    println!(
+                        "Capture has {} networks, retrying against `{}` ({})..",
+                        networks.len(),
+                        network.essid,
+                        network.bssid
+                    );
+
+                    aircrack.bssid = Some(String::clone(&network.bssid));
+                    continue;
+                }
+            }
+        }
+
+        return Ok(Ok((outcome, keys_tested)));
     }
 }
 
-/// Opens a file with the wifi password in and uploads it to Zinharo
-fn upload_job(access: &ZinharoAccess, job: ZinharoQueuedJob, path: PathBuf) -> Result<(), ()> {
-    if !path.exists() {
-        eprintln!("The password file aircrack-ng dumped to does not exist, this shouldn't happen!");
-        process::exit(1);
-    }
+/// Lists the handshakes in a job's dumped capture, narrowed down to the
+/// job's network if it names one
+fn handshake_ids(job: &ZinharoQueuedJob, cap_path: &Path) -> Vec<HandshakeId> {
+    let ids = match fs::read(cap_path).map(|cap| HandshakeId::from_cap(&cap)) {
+        Ok(Ok(x)) => x,
+        _ => return vec![],
+    };
 
-    let mut file = match File::open(PathBuf::clone(&path)) {
-        Ok(x) => x,
-        Err(_) => {
-            eprintln!("Error whilst opening password file, please check zinharo has rights to read files!");
-            process::exit(1);
-        }
+    let targeted: Vec<HandshakeId> = match &job.essid {
+        Some(essid) => ids
+            .iter()
+            .filter(|id| id.essid == essid.as_bytes())
+            .cloned()
+            .collect(),
+        None => vec![],
     };
 
-    let mut contents = String::new();
+    if targeted.is_empty() {
+        ids
+    } else {
+        targeted
+    }
+}
 
-    match file.read_to_string(&mut contents) {
-        Ok(_) => (),
-        Err(_) => {
-            eprintln!("Error while reading password file, password possibly invalid utf-8!");
-            return Err(());
-        }
-    };
+/// Looks for a key found before for the handshakes in `ids`, verifying each
+/// candidate from the potfile against the capture. The cracker is pointed at
+/// the handshakes' access point whilst verifying, if they share one
+fn known_key(
+    potfile: &Potfile,
+    job: &ZinharoQueuedJob,
+    aircrack: &mut Aircrack,
+    ids: &[HandshakeId],
+) -> Option<Vec<u8>> {
+    let candidates = potfile.candidates(ids);
+
+    if candidates.is_empty() {
+        return None;
+    }
 
-    match job.submit(access, &contents) {
-        Ok(()) => Ok(()),
-        Err(ZinharoError::Ratelimited) => {
-            eprintln!("Ratelimited whilst submitting job, retrying in 30 seconds..");
+    let bssid = aircrack.bssid.clone();
 
-            sleep_sec(30);
-            upload_job(access, job, path) // I know this is expensive
+    if bssid.is_none() && ids.iter().all(|id| id.ap == ids[0].ap) {
+        let ap: Vec<String> = ids[0].ap.iter().map(|b| format!("{:02X}", b)).collect();
+        aircrack.bssid = Some(ap.join(":"));
+    }
+
+    let mut found = None;
+
+    for key in candidates.into_iter().take(potfile::MAX_CANDIDATES) {
+        match aircrack.verify(&key) {
+            Ok(true) => {
+                found = Some(key);
+                break;
+            }
+            Ok(false) => (),
+            Err(e) => {
+                eprintln!(
+                    "Could not check keys from the potfile for job #{}: '{}', cracking instead..",
+                    job.id, e
+                );
+                break;
+            }
         }
-        Err(_) => {
-            eprintln!("Unknown error whilst submitting job, retrying in 10 seconds..");
+    }
 
-            sleep_sec(10);
-            upload_job(access, job, path) // I know this is expensive (x2)
+    aircrack.bssid = bssid;
+    found
+}
+
+/// Adds a key found for the handshakes in `ids` to the potfile, for the next
+/// capture of the same network. Keys found in the potfile are added again
+/// for a new capture's own handshakes, so they rank first next time
+fn remember_key(shared: &Shared, job: &ZinharoQueuedJob, ids: &[HandshakeId], key: &[u8]) {
+    if let Some(potfile) = &shared.potfile {
+        if let Err(e) = potfile.insert(ids, key) {
+            eprintln!(
+                "Could not add key for job #{} to the potfile: '{}'",
+                job.id, e
+            );
         }
     }
 }
 
-/// Starts to crack given cap file inside job
-fn start_job(
-    access: &ZinharoAccess,
+/// Records a job in the history which was given up on before it could be
+/// cracked
+fn record_unstarted(shared: &Shared, mut record: JobRecord, outcome: Outcome, detail: String) {
+    record.ended(outcome, Some(detail));
+    record.finished = Utc::now().to_rfc3339();
+    shared.history.record(&record);
+}
+
+/// Dumps a job leased at `fetched` into its own private directory and
+/// resolves its candidates, so it is ready to crack. Gives [Option::None] if
+/// the job couldn't be prepared, after reporting or releasing it
+fn prepare_job(
+    shared: &Shared,
     job: ZinharoQueuedJob,
-    wordlist_path: PathBuf,
-) -> Option<PathBuf> {
-    let output_path = PathBuf::from("./out.txt");
-    let cap_path = PathBuf::from("./inprogress.cap");
+    fetched: DateTime<Utc>,
+    skip: u64,
+) -> Option<PreparedJob> {
+    let mut record = JobRecord::new(&job, fetched);
+    let job_dir = match JobDir::create(&shared.work_root, job.id) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!(
+                "Could not create working directory for job #{}: '{}', releasing job..",
+                job.id, e
+            );
+            release_job(&shared.access, &job, false);
+            record_unstarted(
+                shared,
+                record,
+                Outcome::Released,
+                format!("could not create working directory: {}", e),
+            );
+            return None;
+        }
+    };
 
-    match job.dump_cap(PathBuf::clone(&cap_path)) {
+    match job.dump_cap(job_dir.file(CAP_FILE)) {
         Ok(_) => (),
         Err(e) => {
             eprintln!(
                 "Could not save job #{} to file: '{:?}', reporting job!",
                 job.id, e
             );
-            report_job(
-                access,
-                job,
-                Some("Could not save to file, possibly invalid cap"),
+            report_job(&shared.outbox, &job, &ReportReason::InvalidCapture, None);
+            record_unstarted(
+                shared,
+                record,
+                Outcome::Reported,
+                ReportReason::InvalidCapture.to_string(),
             );
             return None;
         }
     };
 
-    let output_path_str = PathBuf::clone(&output_path)
-        .into_os_string()
-        .into_string()
-        .unwrap();
-    let cap_path_str = cap_path.into_os_string().into_string().unwrap();
-    let wordlist_path_str = wordlist_path.into_os_string().into_string().unwrap();
-
-    let output = Command::new("aircrack-ng")
-        .args(&[
-            &cap_path_str,
-            "-w",
-            &wordlist_path_str,
-            "-l",
-            &output_path_str,
-        ])
-        .output();
-
-    match output {
-        Ok(cmd) => {
-            if cmd.status.success() {
-                if output_path.exists() {
-                    println!("Found password, uploading..");
-
-                    match upload_job(access, job, PathBuf::clone(&output_path)) {
-                        Ok(_) => (),
-                        Err(_) => return None,
+    record.stage(STAGE_DOWNLOAD);
+
+    let candidates = shared.wordlists.candidates(&shared.access, &job);
+
+    match candidates {
+        Ok(candidates) => Some(PreparedJob {
+            job,
+            record,
+            heartbeat: Instant::now(),
+            job_dir,
+            candidates,
+            skip,
+        }),
+        Err(e) => {
+            eprintln!("Could not get wordlist for job #{}: {}", job.id, e);
+            release_job(&shared.access, &job, false);
+            record_unstarted(
+                shared,
+                record,
+                Outcome::Released,
+                format!("could not get wordlist: {}", e),
+            );
+            None
+        }
+    }
+}
+
+/// Checks if a job's lease runs out too soon for it to be started
+fn lease_expiring(job: &ZinharoQueuedJob) -> bool {
+    match job.lease_expires {
+        Some(expires) => (expires - Utc::now())
+            .to_std()
+            .map_or(true, |remaining| remaining < PREFETCH_LEASE_MARGIN),
+        None => false,
+    }
+}
+
+/// Starts to crack a prepared job, skipping the first candidates if it is
+/// being resumed, and records what became of it in the history. Gives if a
+/// key was found
+fn start_job(worker: &Worker, prepared: PreparedJob) -> bool {
+    let mut record = JobRecord::clone(&prepared.record);
+    let found = crack_prepared(worker, prepared, &mut record);
+
+    record.finished = Utc::now().to_rfc3339();
+    worker.shared.history.record(&record);
+    found
+}
+
+/// Cracks a prepared job and deals with how it ended, filling in `record`.
+/// Everything written for the job lives in its own directory under the work
+/// root, removed again once this returns. Gives if a key was found
+fn crack_prepared(worker: &Worker, prepared: PreparedJob, record: &mut JobRecord) -> bool {
+    let (access, state_dir) = (&worker.shared.access, worker.state_dir.as_path());
+    let PreparedJob {
+        job,
+        job_dir,
+        candidates,
+        skip,
+        ..
+    } = prepared;
+
+    let output_path = job_dir.file("out.txt");
+    let cap_path = job_dir.file(CAP_FILE);
+
+    let started = Instant::now();
+    let mut aircrack = Aircrack::new(cap_path, output_path);
+    aircrack.threads = worker.threads;
+    aircrack.limits = Limits::clone(&worker.shared.limits);
+    aircrack.program = PathBuf::clone(&worker.shared.cracker);
+
+    let ids = handshake_ids(&job, &aircrack.cap_path);
+    let potfile = worker.shared.potfile.as_ref();
+
+    if let Some(key) = potfile.and_then(|potfile| known_key(potfile, &job, &mut aircrack, &ids)) {
+        println!(
+            "Job #{} is a network cracked before and its key verifies, uploading..",
+            job.id
+        );
+        Checkpoint::clear(state_dir);
+        record.stage(STAGE_POTFILE);
+        upload_job(&worker.shared.outbox, &job, &key);
+        remember_key(worker.shared, &job, &ids, &key);
+        record.key_fingerprint = worker.shared.history.key_fingerprint(&key);
+        record.ended(Outcome::Found, Some(String::from("key from the potfile")));
+        return true;
+    }
+
+    record.stage(AttackStage::for_job(&job).to_string());
+
+    let outcome = crack_until_outcome(worker, &job, &mut aircrack, &candidates, skip);
+
+    let (outcome, keys_tested) = match outcome {
+        Ok(Ok(x)) => x,
+        Ok(Err(Stopped::LeaseLost)) => {
+            Checkpoint::clear(state_dir);
+            record.ended(Outcome::LeaseLost, None);
+            return false;
+        }
+        Ok(Err(Stopped::Interrupted { offset })) => {
+            interrupt_job(access, &job, offset, state_dir);
+            record.keys_tested = Some(offset);
+            record.ended(Outcome::Interrupted, None);
+            return false;
+        }
+        Err(e) => {
+            eprintln!("Could not crack due to underlying error when calling aircrack-ng, maybe give zinharo admin rights?");
+            release_job(access, &job, false);
+            record.ended(
+                Outcome::Released,
+                Some(format!("could not run cracker: {}", e)),
+            );
+            worker.shared.coordinator.shutdown(true);
+            return false;
+        }
+    };
+
+    Checkpoint::clear(state_dir);
+    record.keys_tested = Some(keys_tested);
+
+    match outcome {
+        CrackOutcome::KeyFound => {
+            let key = match read_key(&aircrack.output_path) {
+                Some(x) => x,
+                None => {
+                    release_job(access, &job, false);
+                    record.ended(
+                        Outcome::Released,
+                        Some(String::from("could not read found key")),
+                    );
+                    return false;
+                }
+            };
+
+            record.stage(STAGE_VERIFY);
+
+            match aircrack.verify(&key) {
+                Ok(true) => {
+                    println!("Found and verified password, uploading..");
+                    upload_job(&worker.shared.outbox, &job, &key);
+                    remember_key(worker.shared, &job, &ids, &key);
+                    record.key_fingerprint = worker.shared.history.key_fingerprint(&key);
+                    record.ended(Outcome::Found, None);
+                    true
+                }
+                Ok(false) => {
+                    eprintln!(
+                        "Key aircrack-ng claimed for job #{} does not validate, reporting..",
+                        job.id
+                    );
+
+                    let diagnostics = ZinharoDiagnostics {
+                        stages_tried: vec![AttackStage::for_job(&job).to_string()],
+                        elapsed_secs: Some(started.elapsed().as_secs()),
+                        keys_tested: Some(keys_tested),
+                        cracker_output: None,
                     };
 
-                    Some(output_path)
-                } else {
-                    eprintln!("No password found, reporting..");
                     report_job(
-                        &access,
-                        job,
-                        Some("Could not crack using standardised wordlist"),
+                        &worker.shared.outbox,
+                        &job,
+                        &ReportReason::UnverifiedKey,
+                        Some(&diagnostics),
+                    );
+                    record.ended(
+                        Outcome::Reported,
+                        Some(ReportReason::UnverifiedKey.to_string()),
+                    );
+                    false
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Could not verify key for job #{}: '{}', releasing without submitting..",
+                        job.id, e
+                    );
+                    release_job(access, &job, false);
+                    record.ended(
+                        Outcome::Released,
+                        Some(format!("could not verify key: {}", e)),
                     );
-                    None
+                    false
                 }
-            } else {
-                eprintln!("Could not crack due to underlying error in aircrack-ng!");
-                process::exit(1);
             }
         }
-        Err(_) => {
-            eprintln!("Could not crack due to underlying error when calling aircrack-ng, maybe give zinharo admin rights?");
-            process::exit(1);
+        CrackOutcome::Failed { code, detail } => {
+            eprintln!(
+                "Could not crack job #{} due to underlying error in aircrack-ng (exit code {:?}): '{}', abandoning..",
+                job.id, code, detail
+            );
+            release_job(access, &job, true);
+            record.ended(Outcome::Abandoned, Some(detail));
+            false
+        }
+        outcome => {
+            let stage = AttackStage::for_job(&job);
+            let reason = match outcome.report_reason(&stage) {
+                Some(x) => x,
+                None => return false,
+            };
+            let diagnostics = ZinharoDiagnostics {
+                stages_tried: vec![stage.to_string()],
+                elapsed_secs: Some(started.elapsed().as_secs()),
+                keys_tested: Some(keys_tested),
+                cracker_output: outcome.detail(),
+            };
+
+            eprintln!("Could not crack job #{}: {}, reporting..", job.id, reason);
+            report_job(&worker.shared.outbox, &job, &reason, Some(&diagnostics));
+            record.ended(Outcome::Reported, Some(reason.to_string()));
+            false
         }
     }
 }
 
-fn main() {
-    println!("{}\n            The automated Zinharo.com cracking client\n=================================================================", HEADER_MSG);
+/// A job made ready to crack, with its capture dumped and candidates resolved
+struct PreparedJob {
+    /// The job itself
+    job: ZinharoQueuedJob,
 
-    let access = login_startup();
-    let wordlist_path = get_wordlist(&access);
+    /// What has happened to the job so far, from when it was leased
+    record: JobRecord,
 
-    println!("Client launched successfully!");
+    /// When the job's lease was last extended, whilst waiting to be started
+    heartbeat: Instant,
 
-    loop {
-        let found_job = match ZinharoQueuedJob::new(&access) {
-            Ok(x) => x,
-            Err(ZinharoError::Ratelimited) => {
-                eprintln!("Ratelimited whilst fetching job, retrying in 1 min..");
-                sleep_sec(60);
-                continue;
+    /// Private directory holding the job's files
+    job_dir: JobDir,
+
+    /// Candidates the job is cracked with
+    candidates: Candidates,
+
+    /// Candidates already tried, when resuming
+    skip: u64,
+}
+
+/// Everything shared between the workers and prefetcher of one client
+struct Shared {
+    /// Access to the api
+    access: ZinharoAccess,
+
+    /// Wordlists candidates are resolved from
+    wordlists: WordlistStore,
+
+    /// Coordinator of fetching and shutdown, also held by the signal handler
+    coordinator: Arc<Coordinator>,
+
+    /// Results waiting to be sent to the api
+    outbox: Outbox,
+
+    /// History every job is recorded in
+    history: History,
+
+    /// Keys found before, if looked up at all
+    potfile: Option<Potfile>,
+
+    /// Jobs prepared ahead of time for the next free worker
+    prefetched: PrefetchQueue<PreparedJob>,
+
+    /// Root job directories are made in
+    work_root: PathBuf,
+
+    /// Limits every cracker is supervised under
+    limits: Limits,
+
+    /// Path to the cracker's executable
+    cracker: PathBuf,
+
+    /// How often progress on running jobs is logged, if ever
+    progress_interval: Option<Duration>,
+}
+
+/// Everything a single worker needs to crack jobs alongside other workers
+struct Worker<'a> {
+    /// Index of this worker, keeping its state apart from the others
+    slot: usize,
+
+    /// State shared by every worker
+    shared: &'a Shared,
+
+    /// Directory this worker's checkpoints are kept in
+    state_dir: PathBuf,
+
+    /// CPU threads given to this worker's cracker
+    threads: Option<usize>,
+}
+
+/// Keeps the prefetch queue topped up with prepared jobs whilst the workers
+/// crack, releasing any whose lease would run out before a worker gets to
+/// them. Releases everything left in the queue once the client shuts down
+fn run_prefetcher(shared: &Shared) {
+    while !shared.coordinator.is_shutdown() {
+        for stale in shared
+            .prefetched
+            .take_where(|prepared| lease_expiring(&prepared.job))
+        {
+            eprintln!(
+                "Prefetched job #{} was not started before its lease ran low, releasing..",
+                stale.job.id
+            );
+            release_job(&shared.access, &stale.job, false);
+            record_unstarted(
+                shared,
+                stale.record,
+                Outcome::Released,
+                String::from("lease ran low whilst prefetched"),
+            );
+        }
+
+        if !shared.prefetched.has_room() {
+            shared.coordinator.wait(PREFETCH_POLL_INTERVAL);
+            continue;
+        }
+
+        let job = match shared.coordinator.fetch_job(&shared.access) {
+            Some(x) => x,
+            None => break,
+        };
+
+        match prepare_job(shared, job, Utc::now(), 0) {
+            Some(prepared) => shared.prefetched.push(prepared),
+            None => {
+                eprintln!("Fetching new job in 30 secs..");
+                shared.coordinator.wait(Duration::from_secs(30));
             }
-            Err(ZinharoError::NoJobsAvailable) => {
-                eprintln!("No jobs currently available, asking again in 2 mins..");
-                sleep_sec(120);
-                continue;
+        }
+    }
+
+    for prepared in shared.prefetched.drain() {
+        release_job(&shared.access, &prepared.job, false);
+        record_unstarted(
+            shared,
+            prepared.record,
+            Outcome::Released,
+            String::from("shut down whilst prefetched"),
+        );
+    }
+}
+
+/// Keeps the leases of prefetched jobs alive whilst they wait in the queue,
+/// dropping any whose lease was lost. Jobs are taken out of the queue whilst
+/// heartbeated so workers are never held up by the api
+fn run_prefetch_heartbeats(shared: &Shared) {
+    while !shared.coordinator.is_shutdown() {
+        let due = shared.prefetched.take_where(|prepared| {
+            prepared.heartbeat.elapsed() >= heartbeat_interval(prepared.job.lease_expires)
+        });
+        let mut kept = Vec::with_capacity(due.len());
+
+        for mut prepared in due {
+            let job = &mut prepared.job;
+
+            match job.heartbeat(&shared.access, &ZinharoProgress::default()) {
+                Ok(expires) => job.lease_expires = Some(expires),
+                Err(ZinharoError::JobNotLeased) => {
+                    eprintln!("Lost lease on prefetched job #{}, dropping it..", job.id);
+                    record_unstarted(
+                        shared,
+                        prepared.record,
+                        Outcome::LeaseLost,
+                        String::from("lease lost whilst prefetched"),
+                    );
+                    continue;
+                }
+                Err(e) => eprintln!(
+                    "Could not heartbeat prefetched job #{}, retrying later..\n{:?}",
+                    job.id, e
+                ),
+            }
+
+            prepared.heartbeat = Instant::now();
+            kept.push(prepared);
+        }
+
+        // the prefetcher releases whatever is queued once shutting down
+        for prepared in shared.prefetched.restore(kept, &shared.coordinator) {
+            release_job(&shared.access, &prepared.job, false);
+            record_unstarted(
+                shared,
+                prepared.record,
+                Outcome::Released,
+                String::from("shut down whilst prefetched"),
+            );
+        }
+
+        shared.coordinator.wait(PREFETCH_POLL_INTERVAL);
+    }
+}
+
+/// Cracks jobs one after another until the client shuts down, starting with
+/// any job this worker had checkpointed
+fn run_worker(worker: &Worker) {
+    let shared = worker.shared;
+    let mut resumed = resume_job(&shared.access, &worker.state_dir);
+
+    while !shared.coordinator.is_shutdown() {
+        let prepared = match resumed.take() {
+            Some((job, skip, fetched)) => match prepare_job(shared, job, fetched, skip) {
+                Some(x) => x,
+                None => {
+                    Checkpoint::clear(&worker.state_dir);
+                    continue;
+                }
+            },
+            None => match shared.prefetched.pop(&shared.coordinator) {
+                Some(x) => x,
+                None => break,
+            },
+        };
+
+        let job = &prepared.job;
+
+        if lease_expiring(job) {
+            eprintln!("Lease on job #{} is running out, releasing..", job.id);
+            release_job(&shared.access, job, false);
+            record_unstarted(
+                shared,
+                prepared.record,
+                Outcome::Released,
+                String::from("lease ran low before starting"),
+            );
+            continue;
+        }
+
+        match &job.essid {
+            Some(essid) => println!(
+                "Worker {} took job #{} for hash #{} (`{}`), cracking..",
+                worker.slot, job.id, job.hash_id, essid
+            ),
+            None => println!(
+                "Worker {} took job #{} for hash #{}, cracking..",
+                worker.slot, job.id, job.hash_id
+            ),
+        }
+
+        if start_job(worker, prepared) {
+            println!("Worker {} found a key, starting next job..", worker.slot);
+        }
+    }
+}
+
+/// Splits the CPUs between `workers` crackers, leaving aircrack-ng to use
+/// every CPU if there is only one
+fn threads_per_worker(workers: usize) -> Option<usize> {
+    if workers == 1 {
+        return None;
+    }
+
+    let cpus = thread::available_parallelism().map_or(1, |cpus| cpus.get());
+
+    Some((cpus / workers).max(1))
+}
+
+/// Cracks jobs handed out by the api with every configured worker until
+/// shut down by a signal or a fatal error
+fn worker(config: Config) {
+    println!("{}\n            The automated Zinharo.com cracking client\n=================================================================", HEADER_MSG);
+
+    let workers = config.cracker.workers;
+    let limits = match config.cracker.limits() {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("Invalid cracker limits: {}", e);
+            process::exit(1);
+        }
+    };
+    let access = match auth::login(&config) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let preprocess_options = PreprocessOptions {
+        frequency_order: config.wordlists.frequency_order,
+        compress: config.wordlists.stream,
+        ..Default::default()
+    };
+    let wordlists = WordlistStore::new(&access, preprocess_options, config.wordlists.locations());
+
+    match wordlists.get(&access, None) {
+        Ok(_) => (),
+        Err(e) => {
+            eprintln!(
+                "Could not get default wordlist, check permissions and connection: {}",
+                e
+            );
+            process::exit(1);
+        }
+    }
+
+    let outbox = match Outbox::open(&config.paths.state_dir) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("Could not open the results outbox: '{}'", e);
+            process::exit(1);
+        }
+    };
+
+    let history = match History::open(&config.paths.state_dir, config.history.retention()) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("Could not open the job history: '{}'", e);
+            process::exit(1);
+        }
+    };
+
+    let potfile = if config.potfile.enabled {
+        match Potfile::open(&config.potfile.path(&config.paths)) {
+            Ok(x) => {
+                println!("Potfile holds keys for {} handshake(s)", x.len());
+                Some(x)
             }
             Err(e) => {
                 eprintln!(
-                    "Unknown error whilst fetching job, retrying in 30 seconds..\n{:?}",
+                    "Could not open the potfile: '{}', cracking every job from scratch..",
                     e
                 );
-                sleep_sec(30);
-                continue;
+                None
             }
-        };
+        }
+    } else {
+        None
+    };
 
-        println!("Fetched job #{}, cracking..", found_job.id);
-        match start_job(&access, found_job, PathBuf::clone(&wordlist_path)) {
-            Some(_) => {
-                println!("Fetching new job..");
-            }
-            None => {
-                eprintln!("Fetching new job in 30 secs..");
-                sleep_sec(30); // 30 second timeout so hopefully new job comes
-            }
+    let shared = Shared {
+        access,
+        wordlists,
+        coordinator: Arc::new(Coordinator::new(config.backoff.fetch())),
+        outbox,
+        history,
+        potfile,
+        prefetched: PrefetchQueue::new(workers),
+        work_root: PathBuf::clone(&config.paths.work_dir),
+        limits,
+        cracker: PathBuf::clone(&config.cracker.path),
+        progress_interval: config.logging.progress_interval(),
+    };
+    let state_dir = PathBuf::clone(&config.paths.state_dir);
+    let threads = threads_per_worker(workers);
+    workdir::sweep(&shared.work_root);
+
+    let coordinator = Arc::clone(&shared.coordinator);
+    let handled = ctrlc::set_handler(move || {
+        if coordinator.is_shutdown() {
+            eprintln!("Stopping immediately, jobs in progress will expire on their own..");
+            process::exit(EXIT_INTERRUPTED);
         }
+
+        eprintln!("Shutting down, finishing up current jobs (signal again to force)..");
+        coordinator.shutdown(false);
+    });
+
+    if let Err(e) = handled {
+        eprintln!("Could not listen for shutdown signals: '{}'", e);
+    }
+
+    println!("Client launched successfully with {} worker(s)!", workers);
+
+    // the outbox logs in again by itself if the session expires, once per
+    // attempt so it keeps to its own retry interval
+    let login = || auth::login_once(&config);
+
+    thread::scope(|s| {
+        s.spawn(|| run_prefetcher(&shared));
+        s.spawn(|| run_prefetch_heartbeats(&shared));
+        s.spawn(|| {
+            shared
+                .outbox
+                .run(&shared.access, &login, &shared.coordinator)
+        });
+
+        for slot in 0..workers {
+            let worker = Worker {
+                slot,
+                shared: &shared,
+                state_dir: checkpoint::worker_state_dir(&state_dir, slot),
+                threads,
+            };
+
+            s.spawn(move || run_worker(&worker));
+        }
+    });
+
+    // one last try, so results found whilst shutting down aren't left behind,
+    // bounded so an unreachable api can't hold the shutdown up
+    let deadline = Instant::now() + SHUTDOWN_DELIVERY_TIMEOUT;
+    shared
+        .outbox
+        .deliver(&shared.access, &login, &|| Instant::now() >= deadline);
+
+    let pending = shared.outbox.pending();
+    if pending > 0 {
+        eprintln!(
+            "{} result(s) could not be sent yet, they will be sent next time the client runs",
+            pending
+        );
+    }
+
+    if shared.coordinator.failed() {
+        process::exit(1);
+    }
+
+    if shared.coordinator.is_shutdown() {
+        println!("Shut down gracefully");
+        process::exit(EXIT_INTERRUPTED);
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let (config, config_path) = match Config::load(&cli) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let json = cli.json;
+    let succeeded = match cli.command {
+        None | Some(Command::Worker) => {
+            worker(config);
+            true
+        }
+        Some(Command::Upload { caps, watch, .. }) => {
+            commands::upload(&config, caps, watch.as_deref(), json)
+        }
+        Some(Command::History {
+            hash,
+            outcome,
+            days,
+            limit,
+            csv,
+        }) => {
+            let query = commands::HistoryQuery {
+                hash_id: hash,
+                outcome,
+                days,
+                limit,
+            };
+            commands::history(&config, query, csv, json)
+        }
+        Some(Command::Status { hash_id }) => commands::status(&config, hash_id, json),
+        Some(Command::Inspect { cap }) => commands::inspect(&config, &cap, json),
+        Some(Command::Signup) => commands::signup(&config, json),
+        Some(Command::Login) => commands::login(&config, json),
+        Some(Command::Config {
+            action: ConfigAction::Check,
+        }) => commands::config_check(&config, config_path.as_deref(), json),
+        Some(Command::Potfile {
+            action: PotfileAction::Import { file },
+        }) => commands::potfile_import(&config, &file, json),
+        Some(Command::Potfile {
+            action: PotfileAction::Export { file },
+        }) => commands::potfile_export(&config, file.as_deref(), json),
+    };
+
+    if !succeeded {
+        process::exit(1);
     }
 }
@@ -0,0 +1,521 @@
+//! Durable outbox of results waiting to be sent to the api. Every submission
+//! and report is journalled to disk before the api is contacted, then sent by
+//! a background flusher which keeps retrying whilst the api is unreachable and
+//! picks up anything left over from a previous run on startup. Results are
+//! keyed by their job, so the same result is never sent twice
+
+use crate::auth::AuthError;
+use crate::coordinator::Coordinator;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use zinharo_rs::{ZinharoAccess, ZinharoError, ZinharoReportSubmission, ZinharoSubmission};
+
+/// Filename of the journal inside the state directory
+const JOURNAL_FILE: &str = "outbox.jsonl";
+
+/// How long the flusher waits between attempts whilst results are pending
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often a waiting flusher checks if the client is shutting down
+const WAIT_STEP: Duration = Duration::from_millis(250);
+
+/// Statuses the api turns a result away with for good, like the job no longer
+/// existing or the result not being valid for it. Any other status is
+/// retried
+const REJECTED_STATUSES: &[u16] = &[404, 409, 410, 422];
+
+/// Status the api gives once the session's token has expired
+const UNAUTHORIZED_STATUS: u16 = 401;
+
+/// A result of a job waiting to be sent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    /// A found passphrase
+    Submit { submission: ZinharoSubmission },
+
+    /// A report against the job's hash
+    Report { report: ZinharoReportSubmission },
+}
+
+impl Request {
+    /// Gives the key results are deduplicated on, one of each kind per job
+    fn key(&self, job_id: i32) -> String {
+        match self {
+            Request::Submit { .. } => format!("submit-{}", job_id),
+            Request::Report { .. } => format!("report-{}", job_id),
+        }
+    }
+
+    /// Sends the result to the api
+    fn send(&self, access: &ZinharoAccess) -> Result<(), ZinharoError> {
+        match self {
+            Request::Submit { submission } => submission.send(access),
+            Request::Report { report } => report.send(access),
+        }
+    }
+}
+
+/// A line of the journal
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "entry", rename_all = "snake_case")]
+enum Entry {
+    /// A result was queued
+    Queued {
+        key: String,
+        job_id: i32,
+        queued: u64,
+        request: Request,
+    },
+
+    /// The result with `key` was accepted by the api
+    Sent { key: String },
+
+    /// The result with `key` was turned away by the api for good, so it is
+    /// kept in the journal but never retried
+    Rejected { key: String, error: String },
+}
+
+/// Where a queued result is at
+#[derive(Debug, Clone, PartialEq)]
+enum State {
+    /// Waiting to be sent
+    Pending,
+
+    /// Accepted by the api
+    Sent,
+
+    /// Turned away by the api for good
+    Rejected(String),
+}
+
+/// A queued result alongside where it is at
+struct Queued {
+    job_id: i32,
+    queued: u64,
+    request: Request,
+    state: State,
+}
+
+/// Results in the journal, by key
+struct Journal {
+    path: PathBuf,
+    results: BTreeMap<String, Queued>,
+}
+
+impl Journal {
+    /// Appends `entry` to the journal, making sure it is on disk before
+    /// giving back
+    fn append(&self, entry: &Entry) -> io::Result<()> {
+        let mut file = open_private(&self.path, true)?;
+
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        file.sync_data()
+    }
+
+    /// Rewrites the journal with only what is still of use, dropping results
+    /// which were sent
+    fn compact(&self) -> io::Result<()> {
+        let part_path = self.path.with_extension("part");
+        let mut file = open_private(&part_path, false)?;
+
+        for (key, queued) in &self.results {
+            if queued.state == State::Sent {
+                continue;
+            }
+
+            let entry = Entry::Queued {
+                key: String::clone(key),
+                job_id: queued.job_id,
+                queued: queued.queued,
+                request: Request::clone(&queued.request),
+            };
+            writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+
+            if let State::Rejected(error) = &queued.state {
+                let entry = Entry::Rejected {
+                    key: String::clone(key),
+                    error: String::clone(error),
+                };
+                writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+            }
+        }
+
+        file.sync_data()?;
+        fs::rename(part_path, &self.path)
+    }
+
+    /// Records where the result with `key` ended up
+    fn settle(&mut self, key: &str, state: State) {
+        let entry = match &state {
+            State::Pending => return,
+            State::Sent => Entry::Sent {
+                key: String::from(key),
+            },
+            State::Rejected(error) => Entry::Rejected {
+                key: String::from(key),
+                error: String::clone(error),
+            },
+        };
+
+        if let Err(e) = self.append(&entry) {
+            eprintln!("Could not write to the outbox journal: '{}'", e);
+        }
+
+        if let Some(queued) = self.results.get_mut(key) {
+            queued.state = state;
+        }
+    }
+}
+
+/// Opens the file at `path` so only the current user can read it, as results
+/// hold found passphrases
+fn open_private(path: &Path, append: bool) -> io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .mode(0o600)
+        .open(path)
+}
+
+/// Outbox of results shared between the workers and the flusher
+pub struct Outbox {
+    journal: Mutex<Journal>,
+    pushed: Condvar,
+
+    /// Session logged into again after the one results were being sent with
+    /// expired, used in its place from then on
+    session: Mutex<Option<ZinharoAccess>>,
+}
+
+impl Outbox {
+    /// Opens the outbox journalled in `state_dir`, compacting whatever was
+    /// left in it by previous runs
+    pub fn open(state_dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(state_dir)?;
+
+        let mut journal = Journal {
+            path: state_dir.join(JOURNAL_FILE),
+            results: BTreeMap::new(),
+        };
+
+        match File::open(&journal.path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    // a line torn by a crash mid-write is all that can be lost
+                    match serde_json::from_str(&line?) {
+                        Ok(entry) => journal.replay(entry),
+                        Err(e) => eprintln!("Skipping unreadable outbox entry: '{}'", e),
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e),
+        }
+
+        journal
+            .results
+            .retain(|_, queued| queued.state != State::Sent);
+        journal.compact()?;
+
+        Ok(Outbox {
+            journal: Mutex::new(journal),
+            pushed: Condvar::new(),
+            session: Mutex::new(None),
+        })
+    }
+
+    /// Queues a result of the job `job_id`, journalling it before giving back.
+    /// Gives false if the same result was already queued. Results which
+    /// couldn't be journalled are still sent whilst the client runs
+    pub fn push(&self, job_id: i32, request: Request) -> bool {
+        let key = request.key(job_id);
+        let mut journal = self.journal.lock().unwrap();
+
+        if journal.results.contains_key(&key) {
+            return false;
+        }
+
+        let queued = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let entry = Entry::Queued {
+            key: String::clone(&key),
+            job_id,
+            queued,
+            request: Request::clone(&request),
+        };
+
+        if let Err(e) = journal.append(&entry) {
+            eprintln!(
+                "Could not journal result of job #{}, it will be lost if the client stops before sending it: '{}'",
+                job_id, e
+            );
+        }
+
+        journal.results.insert(
+            key,
+            Queued {
+                job_id,
+                queued,
+                request,
+                state: State::Pending,
+            },
+        );
+
+        self.pushed.notify_all();
+        true
+    }
+
+    /// Gives how many results are waiting to be sent
+    pub fn pending(&self) -> usize {
+        let journal = self.journal.lock().unwrap();
+
+        journal
+            .results
+            .values()
+            .filter(|queued| queued.state == State::Pending)
+            .count()
+    }
+
+    /// Tries to send every pending result, submissions before reports, until
+    /// `cancelled` says to stop, which is checked before each attempt.
+    /// Results that can't be sent for now are kept for later whilst the rest
+    /// are still tried, unless the api is ratelimiting. If the session has
+    /// expired, `login` is used once to log in again. Gives if nothing is
+    /// left pending
+    pub fn deliver(
+        &self,
+        access: &ZinharoAccess,
+        login: &dyn Fn() -> Result<ZinharoAccess, AuthError>,
+        cancelled: &dyn Fn() -> bool,
+    ) -> bool {
+        let pending = self.journal.lock().unwrap().pending_in_order();
+        let mut session = self.session.lock().unwrap();
+        let mut logged_in = false;
+        let mut delivered = true;
+
+        for (key, job_id, request) in pending {
+            if cancelled() {
+                return false;
+            }
+
+            let mut result = request.send(session.as_ref().unwrap_or(access));
+
+            if matches!(
+                result,
+                Err(ZinharoError::UnknownStatusCode(UNAUTHORIZED_STATUS))
+            ) && !logged_in
+            {
+                logged_in = true;
+                eprintln!("Session expired whilst sending results, logging in again..");
+
+                match login() {
+                    Ok(fresh) => {
+                        result = request.send(&fresh);
+                        *session = Some(fresh);
+                    }
+                    Err(e) => eprintln!("Could not log in again: '{}'", e),
+                }
+            }
+
+            let state = match result {
+                Ok(()) => State::Sent,
+                Err(ZinharoError::UnknownStatusCode(code)) if REJECTED_STATUSES.contains(&code) => {
+                    let error = format!("api responded with status {}", code);
+                    eprintln!(
+                        "Result of job #{} was rejected ({}), keeping it in the outbox journal..",
+                        job_id, error
+                    );
+                    State::Rejected(error)
+                }
+                Err(ZinharoError::Ratelimited) => {
+                    eprintln!("Ratelimited whilst sending results, keeping them to retry later..");
+                    return false;
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Could not send result of job #{}, keeping it to retry later..\n{:?}",
+                        job_id, e
+                    );
+                    delivered = false;
+                    continue;
+                }
+            };
+
+            self.journal.lock().unwrap().settle(&key, state);
+        }
+
+        delivered
+    }
+
+    /// Sends results as they are queued, retrying pending ones every so often
+    /// until the client shuts down. Results left over from previous runs are
+    /// sent straight away
+    pub fn run(
+        &self,
+        access: &ZinharoAccess,
+        login: &dyn Fn() -> Result<ZinharoAccess, AuthError>,
+        coordinator: &Coordinator,
+    ) {
+        while !coordinator.is_shutdown() {
+            let delivered = self.deliver(access, login, &|| coordinator.is_shutdown());
+            let mut waited = Duration::from_secs(0);
+            let mut journal = self.journal.lock().unwrap();
+
+            // wait for a new result, or for a while before retrying if some
+            // couldn't be sent
+            while !coordinator.is_shutdown() {
+                if delivered && journal.has_pending() || !delivered && waited >= RETRY_INTERVAL {
+                    break;
+                }
+
+                journal = self.pushed.wait_timeout(journal, WAIT_STEP).unwrap().0;
+                waited += WAIT_STEP;
+            }
+        }
+    }
+}
+
+impl Journal {
+    /// Gives if any result is waiting to be sent
+    fn has_pending(&self) -> bool {
+        self.results
+            .values()
+            .any(|queued| queued.state == State::Pending)
+    }
+
+    /// Gives every pending result in the order they are sent, submissions
+    /// first as they hold found passphrases, each kind oldest job first
+    fn pending_in_order(&self) -> Vec<(String, i32, Request)> {
+        let mut pending: Vec<(String, i32, Request)> = self
+            .results
+            .iter()
+            .filter(|(_, queued)| queued.state == State::Pending)
+            .map(|(key, queued)| {
+                (
+                    String::clone(key),
+                    queued.job_id,
+                    Request::clone(&queued.request),
+                )
+            })
+            .collect();
+
+        pending.sort_by_key(|(_, job_id, request)| {
+            (matches!(request, Request::Report { .. }), *job_id)
+        });
+        pending
+    }
+
+    /// Applies an entry read back from the journal
+    fn replay(&mut self, entry: Entry) {
+        match entry {
+            Entry::Queued {
+                key,
+                job_id,
+                queued,
+                request,
+            } => {
+                self.results.entry(key).or_insert(Queued {
+                    job_id,
+                    queued,
+                    request,
+                    state: State::Pending,
+                });
+            }
+            Entry::Sent { key } => {
+                if let Some(queued) = self.results.get_mut(&key) {
+                    queued.state = State::Sent;
+                }
+            }
+            Entry::Rejected { key, error } => {
+                if let Some(queued) = self.results.get_mut(&key) {
+                    queued.state = State::Rejected(error);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+    use zinharo_rs::ReportReason;
+
+    #[test]
+    fn journalled_deduplicated_and_compacted() {
+        let dir = TestDir::new("outbox");
+        let submit = |job_id| Request::Submit {
+            submission: ZinharoSubmission {
+                job_id,
+                password: String::from("hunter22"),
+                wordlist: None,
+                slice_id: None,
+            },
+        };
+        let report = Request::Report {
+            report: ZinharoReportSubmission {
+                hash_id: 77,
+                info: ReportReason::NoHandshake.to_string(),
+                reason: ReportReason::NoHandshake,
+                diagnostics: None,
+                wordlist: None,
+                slice_id: None,
+            },
+        };
+
+        let outbox = Outbox::open(dir.path()).unwrap();
+        assert!(outbox.push(1, submit(1)));
+        assert!(!outbox.push(1, submit(1)));
+        assert!(outbox.push(1, Request::clone(&report)));
+        assert!(outbox.push(2, submit(2)));
+
+        {
+            let mut journal = outbox.journal.lock().unwrap();
+            journal.settle("submit-1", State::Sent);
+            journal.settle("submit-2", State::Rejected(String::from("gone")));
+        }
+
+        drop(outbox);
+
+        // sent results are compacted away, the rest survive a restart
+        let outbox = Outbox::open(dir.path()).unwrap();
+        let journal = outbox.journal.lock().unwrap();
+        let keys: Vec<&String> = journal.results.keys().collect();
+
+        assert_eq!(keys, vec!["report-1", "submit-2"]);
+        assert_eq!(journal.results["report-1"].request, report);
+        assert_eq!(
+            journal.results["submit-2"].state,
+            State::Rejected(String::from("gone"))
+        );
+        drop(journal);
+        assert_eq!(outbox.pending(), 1);
+
+        // submissions go out before reports, whatever their keys sort as
+        assert!(outbox.push(10, submit(10)));
+        assert!(outbox.push(3, submit(3)));
+
+        let order: Vec<String> = outbox
+            .journal
+            .lock()
+            .unwrap()
+            .pending_in_order()
+            .into_iter()
+            .map(|(key, _, _)| key)
+            .collect();
+
+        assert_eq!(order, vec!["submit-3", "submit-10", "report-1"]);
+    }
+}
@@ -0,0 +1,496 @@
+//! Classifies how a run of aircrack-ng ended from its exit status and
+//! output, so a job the cracker can't handle gets reported instead of taking
+//! the whole client down with it
+
+use crate::checkpoint::AttackStage;
+use crate::progress;
+use crate::supervisor::{Resource, Verdict};
+use std::fmt;
+use std::process::Output;
+use std::time::Duration;
+use zinharo_rs::ReportReason;
+
+/// Encryption of a network, as listed in aircrack-ng's network table
+#[derive(Debug, Clone, PartialEq)]
+pub enum Encryption {
+    /// WPA/WPA2 with the number of handshakes captured for it
+    Wpa { handshakes: u32 },
+
+    /// WEP, which can't be cracked with a wordlist
+    Wep,
+
+    /// An open network
+    None,
+
+    /// Not enough data was captured to tell
+    Unknown,
+}
+
+impl fmt::Display for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Encryption::Wpa { handshakes } => write!(f, "WPA ({} handshakes)", handshakes),
+            Encryption::Wep => write!(f, "WEP"),
+            Encryption::None => write!(f, "no encryption"),
+            Encryption::Unknown => write!(f, "unknown encryption"),
+        }
+    }
+}
+
+/// A network found in a capture
+#[derive(Debug, Clone, PartialEq)]
+pub struct Network {
+    /// MAC address of the access point, used to pick it with `-b`
+    pub bssid: String,
+
+    /// Network name, empty if it was hidden or never captured
+    pub essid: String,
+
+    /// Encryption used by the network
+    pub encryption: Encryption,
+}
+
+impl Network {
+    /// Checks if this network can be cracked with a wordlist
+    pub fn has_handshake(&self) -> bool {
+        matches!(self.encryption, Encryption::Wpa { handshakes } if handshakes > 0)
+    }
+}
+
+/// How a run of the cracker ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum CrackOutcome {
+    /// The key was found and written to the output file
+    KeyFound,
+
+    /// Every candidate was tried without finding the key
+    Exhausted,
+
+    /// The capture couldn't be read or held no networks at all
+    InvalidCapture,
+
+    /// The capture has no usable WPA handshake
+    NoHandshake,
+
+    /// The capture has several networks and the cracker needs to be told
+    /// which one to attack
+    NeedsSelection(Vec<Network>),
+
+    /// The targeted network isn't WPA, so can't be cracked with a wordlist
+    UnsupportedEncryption(Encryption),
+
+    /// The cracker ran past its deadline and was killed
+    TimedOut(Duration),
+
+    /// The cracker went silent for this long and was killed
+    Hung(Duration),
+
+    /// The cracker was stopped by a cpu or memory limit
+    ResourceLimit(Resource),
+
+    /// The cracker failed for a reason unrelated to the job
+    Failed { code: Option<i32>, detail: String },
+}
+
+impl CrackOutcome {
+    /// Classifies a finished run of aircrack-ng given how its supervisor saw
+    /// it end. `key_written` says whether the key output file was written,
+    /// which alone decides if a key was found. The output echoes candidates
+    /// and network names, so only whole lines aircrack-ng prints itself are
+    /// matched, and only on its final screen where that matters
+    pub fn classify(output: &Output, verdict: &Verdict, key_written: bool) -> Self {
+        let stdout = progress::strip_escapes(&output.stdout);
+        let stderr = progress::strip_escapes(&output.stderr);
+        let stdout_lines = lines(&stdout);
+        let all_lines: Vec<&str> = stdout_lines
+            .iter()
+            .chain(lines(&stderr).iter())
+            .copied()
+            .collect();
+        let networks = parse_networks(&stdout);
+
+        if key_written {
+            return CrackOutcome::KeyFound;
+        }
+
+        match verdict {
+            Verdict::TimedOut { limit } => return CrackOutcome::TimedOut(*limit),
+            Verdict::Hung { silent } => return CrackOutcome::Hung(*silent),
+            Verdict::ResourceLimit(resource) => return CrackOutcome::ResourceLimit(*resource),
+            Verdict::Exited | Verdict::Cancelled => (),
+        }
+
+        let final_screen = final_screen(&stdout_lines);
+
+        if has_line(final_screen, &[KEY_FOUND]) {
+            return CrackOutcome::Failed {
+                code: output.status.code(),
+                detail: String::from("aircrack-ng showed a key but never wrote its key file"),
+            };
+        }
+
+        if has_line(
+            &all_lines,
+            &[
+                "Unsupported file format",
+                "Failed to open",
+                "No networks found",
+            ],
+        ) {
+            return CrackOutcome::InvalidCapture;
+        }
+
+        if has_line(
+            &all_lines,
+            &["Index number of target network", "Please specify an ESSID"],
+        ) {
+            return CrackOutcome::NeedsSelection(networks);
+        }
+
+        if has_line(
+            &all_lines,
+            &["Packets contained no EAPOL data", "No valid WPA handshakes"],
+        ) {
+            return CrackOutcome::NoHandshake;
+        }
+
+        // a single network gets picked automatically, so only one matters
+        if let [network] = networks.as_slice() {
+            match &network.encryption {
+                Encryption::Wpa { handshakes: 0 } => return CrackOutcome::NoHandshake,
+                Encryption::Wpa { .. } => (),
+                encryption => return CrackOutcome::UnsupportedEncryption(encryption.clone()),
+            }
+        }
+
+        // the last thing printed when every candidate was tried
+        if output.status.success() || final_screen.last() == Some(&NOT_IN_DICTIONARY) {
+            return CrackOutcome::Exhausted;
+        }
+
+        let detail = stderr
+            .lines()
+            .chain(stdout.lines())
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or("no output")
+            .to_string();
+
+        CrackOutcome::Failed {
+            code: output.status.code(),
+            detail,
+        }
+    }
+
+    /// Picks the one network worth attacking out of several, which is
+    /// possible when exactly one of them has a WPA handshake
+    pub fn only_crackable(networks: &[Network]) -> Option<&Network> {
+        let mut crackable = networks.iter().filter(|network| network.has_handshake());
+
+        match (crackable.next(), crackable.next()) {
+            (Some(network), None) => Some(network),
+            _ => None,
+        }
+    }
+
+    /// Gives the reason a job with this outcome should be reported for, or
+    /// [Option::None] if it shouldn't be reported at all. `stage` is the
+    /// part of the attack plan that was being cracked
+    pub fn report_reason(&self, stage: &AttackStage) -> Option<ReportReason> {
+        match self {
+            CrackOutcome::KeyFound | CrackOutcome::Failed { .. } => None,
+            CrackOutcome::Exhausted => Some(ReportReason::Exhausted {
+                attack_plan: vec![stage.to_string()],
+            }),
+            CrackOutcome::InvalidCapture => Some(ReportReason::InvalidCapture),
+            CrackOutcome::NoHandshake => Some(ReportReason::NoHandshake),
+            CrackOutcome::NeedsSelection(networks) => Some(ReportReason::Other(format!(
+                "Could not pick a target out of {} networks, {} with handshakes",
+                networks.len(),
+                networks
+                    .iter()
+                    .filter(|network| network.has_handshake())
+                    .count()
+            ))),
+            CrackOutcome::UnsupportedEncryption(_) => Some(ReportReason::UnsupportedEncryption),
+            CrackOutcome::TimedOut(limit) => Some(ReportReason::Timeout {
+                limit_secs: limit.as_secs(),
+            }),
+            CrackOutcome::Hung(silent) => Some(ReportReason::Hung {
+                silent_secs: silent.as_secs(),
+            }),
+            CrackOutcome::ResourceLimit(resource) => Some(ReportReason::ResourceLimit {
+                resource: resource.to_string(),
+            }),
+        }
+    }
+
+    /// Describes what the cracker saw beyond the outcome itself, for
+    /// diagnostics sent with reports
+    pub fn detail(&self) -> Option<String> {
+        match self {
+            CrackOutcome::NeedsSelection(networks) => Some(
+                networks
+                    .iter()
+                    .map(|network| {
+                        format!(
+                            "{} `{}`: {}",
+                            network.bssid, network.essid, network.encryption
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            CrackOutcome::UnsupportedEncryption(encryption) => Some(encryption.to_string()),
+            CrackOutcome::Failed { detail, .. } => Some(String::clone(detail)),
+            _ => None,
+        }
+    }
+}
+
+/// Start of the line aircrack-ng shows a found key on
+const KEY_FOUND: &str = "KEY FOUND! [";
+
+/// Line aircrack-ng ends with once every candidate was tried
+const NOT_IN_DICTIONARY: &str = "Passphrase not in dictionary";
+
+/// Splits output into its trimmed, non-empty lines
+fn lines(text: &str) -> Vec<&str> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Gives the lines of the last status screen aircrack-ng drew, starting at
+/// its "keys tested" line, or every line if it never drew one
+fn final_screen<'a>(lines: &'a [&'a str]) -> &'a [&'a str] {
+    let start = lines
+        .iter()
+        .rposition(|line| progress::is_status_line(line))
+        .unwrap_or(0);
+
+    &lines[start..]
+}
+
+/// Checks if any of `lines` starts with one of `prefixes`
+fn has_line(lines: &[&str], prefixes: &[&str]) -> bool {
+    lines
+        .iter()
+        .any(|line| prefixes.iter().any(|prefix| line.starts_with(prefix)))
+}
+
+/// Parses the encryption column of aircrack-ng's network table
+fn parse_encryption(column: &str) -> Encryption {
+    if column.starts_with("WPA") {
+        let handshakes = column
+            .split(|c: char| c == '(' || c.is_whitespace())
+            .find_map(|word| word.parse().ok())
+            .unwrap_or(0);
+
+        Encryption::Wpa { handshakes }
+    } else if column.starts_with("WEP") {
+        Encryption::Wep
+    } else if column.starts_with("None") {
+        Encryption::None
+    } else {
+        Encryption::Unknown
+    }
+}
+
+/// Parses network table rows like
+/// `1  00:11:22:33:44:55  linksys  WPA (1 handshake)` from aircrack-ng output
+pub fn parse_networks(text: &str) -> Vec<Network> {
+    /// Starts of the encryption column, the last one in a row being used so
+    /// ESSIDs containing them don't confuse things
+    const ENCRYPTIONS: &[&str] = &["WPA", "WEP", "None", "Unknown"];
+
+    /// Shown when nothing tells WEP and WPA apart, which contains both
+    const NO_DATA: &str = "No data - WEP or WPA";
+
+    let mut networks = Vec::new();
+
+    for line in text.lines() {
+        let mut words = line.split_whitespace();
+
+        let (index, bssid) = match (words.next(), words.next()) {
+            (Some(index), Some(bssid)) => (index, bssid),
+            _ => continue,
+        };
+
+        if index.parse::<u32>().is_err() || bssid.len() != 17 || bssid.matches(':').count() != 5 {
+            continue;
+        }
+
+        let rest = line[line.find(bssid).unwrap() + bssid.len()..].trim();
+        let column = rest
+            .rfind(NO_DATA)
+            .or_else(|| {
+                ENCRYPTIONS
+                    .iter()
+                    .filter_map(|encryption| rest.rfind(encryption))
+                    .max()
+            })
+            .unwrap_or(rest.len());
+
+        networks.push(Network {
+            bssid: bssid.to_string(),
+            essid: rest[..column].trim().to_string(),
+            encryption: parse_encryption(&rest[column..]),
+        });
+    }
+
+    networks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    const TABLE: &str = "Read 1520 packets.\n\n   #  BSSID              ESSID                     Encryption\n\n   1  00:11:22:33:44:55  WPA house                 WPA (1 handshake)\n   2  66:77:88:99:AA:BB  cafe                      WEP (12 IVs)\n   3  CC:DD:EE:FF:00:11                            WPA (0 handshake)\n\nIndex number of target network ?\n";
+
+    /// Fakes an aircrack-ng run exiting with `code`
+    fn output(code: i32, stdout: &str) -> Output {
+        Output {
+            status: ExitStatus::from_raw(code << 8),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn network_selection() {
+        let outcome = CrackOutcome::classify(&output(1, TABLE), &Verdict::Exited, false);
+
+        let networks = match &outcome {
+            CrackOutcome::NeedsSelection(networks) => networks,
+            other => panic!("unexpected outcome {:?}", other),
+        };
+
+        assert_eq!(networks.len(), 3);
+        assert_eq!(networks[0].essid, "WPA house");
+        assert_eq!(networks[1].encryption, Encryption::Wep);
+        assert_eq!(networks[2].essid, "");
+        assert_eq!(
+            CrackOutcome::only_crackable(networks).map(|network| network.bssid.as_str()),
+            Some("00:11:22:33:44:55")
+        );
+    }
+
+    #[test]
+    fn failure_modes() {
+        let single = |encryption: &str| {
+            format!(
+                "   1  00:11:22:33:44:55  home  {}\n\nChoosing first network as target.\n",
+                encryption
+            )
+        };
+
+        assert_eq!(
+            CrackOutcome::classify(&output(1, &single("WEP (40 IVs)")), &Verdict::Exited, false),
+            CrackOutcome::UnsupportedEncryption(Encryption::Wep)
+        );
+        assert_eq!(
+            CrackOutcome::classify(
+                &output(1, &single("WPA (0 handshake)")),
+                &Verdict::Exited,
+                false
+            ),
+            CrackOutcome::NoHandshake
+        );
+        assert_eq!(
+            CrackOutcome::classify(
+                &output(0, &single("WPA (1 handshake)")),
+                &Verdict::Exited,
+                false
+            ),
+            CrackOutcome::Exhausted
+        );
+        assert_eq!(
+            CrackOutcome::classify(
+                &output(1, "\x1b[2KUnsupported file format"),
+                &Verdict::Exited,
+                false
+            ),
+            CrackOutcome::InvalidCapture
+        );
+        assert_eq!(
+            CrackOutcome::classify(&output(139, ""), &Verdict::Exited, false),
+            CrackOutcome::Failed {
+                code: Some(139),
+                detail: String::from("no output")
+            }
+        );
+        assert_eq!(
+            CrackOutcome::classify(
+                &output(0, &single("WPA (1 handshake)")),
+                &Verdict::ResourceLimit(Resource::Memory),
+                false
+            ),
+            CrackOutcome::ResourceLimit(Resource::Memory)
+        );
+    }
+
+    #[test]
+    fn echoed_text_is_not_trusted() {
+        let screen = |current: &str, last: &str| {
+            format!(
+                "\x1b[2J[00:00:05] 1200/0 keys tested (240.00 k/s)\n\nCurrent passphrase: {}\n\n{}\n",
+                current, last
+            )
+        };
+
+        // a candidate or network name echoed back can't decide the outcome
+        assert_eq!(
+            CrackOutcome::classify(
+                &output(1, &screen("KEY FOUND! [ x ]", "")),
+                &Verdict::Exited,
+                false
+            ),
+            CrackOutcome::Failed {
+                code: Some(1),
+                detail: String::from("[00:00:05] 1200/0 keys tested (240.00 k/s)")
+            }
+        );
+        assert_eq!(
+            CrackOutcome::classify(
+                &output(1, &screen("Passphrase not in dictionary", "")),
+                &Verdict::Exited,
+                false
+            )
+            .report_reason(&AttackStage::Slice { id: 1 }),
+            None
+        );
+
+        // the key file is what says a key was found
+        assert_eq!(
+            CrackOutcome::classify(
+                &output(1, &screen("hunter22", "KEY FOUND! [ hunter22 ]")),
+                &Verdict::Exited,
+                false
+            ),
+            CrackOutcome::Failed {
+                code: Some(1),
+                detail: String::from("aircrack-ng showed a key but never wrote its key file")
+            }
+        );
+        assert_eq!(
+            CrackOutcome::classify(
+                &output(1, &screen("hunter22", "Passphrase not in dictionary")),
+                &Verdict::Exited,
+                true
+            ),
+            CrackOutcome::KeyFound
+        );
+        assert_eq!(
+            CrackOutcome::classify(
+                &output(1, &screen("hunter22", "Passphrase not in dictionary")),
+                &Verdict::Exited,
+                false
+            ),
+            CrackOutcome::Exhausted
+        );
+    }
+}
@@ -0,0 +1,296 @@
+//! Local potfile of keys found for handshakes, kept in hashcat's potfile
+//! format for `-m 22000` of `mic:ap:station:essid:key` lines so it can be
+//! shared with hashcat both ways. Keys are
+//! looked up by network name, as re-captures of a network cracked before hold
+//! new handshakes with the same key, and are always verified against the
+//! capture before being trusted
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use zinharo_rs::HandshakeId;
+
+/// Filename of the potfile inside the state directory
+pub const POTFILE: &str = "zinharo.potfile";
+
+/// Most cached keys verified against a single capture, as every check runs
+/// the cracker
+pub const MAX_CANDIDATES: usize = 8;
+
+/// Prefix hashcat gives keys it writes out in hex
+const HEX_PREFIX: &str = "$HEX[";
+
+/// How many lines an import added and skipped
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct Imported {
+    /// New keys added to the potfile
+    pub added: usize,
+
+    /// Lines already in the potfile or not for `-m 22000`
+    pub skipped: usize,
+}
+
+/// Keys found for handshakes, kept on disk
+pub struct Potfile {
+    path: PathBuf,
+    keys: Mutex<BTreeMap<HandshakeId, Vec<u8>>>,
+}
+
+impl Potfile {
+    /// Opens the potfile at `path`, which is made once a key is added.
+    /// Lines for other hash modes are left alone
+    pub fn open(path: &Path) -> io::Result<Self> {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+
+        let potfile = Potfile {
+            path: PathBuf::from(path),
+            keys: Mutex::new(BTreeMap::new()),
+        };
+
+        match File::open(path) {
+            Ok(file) => {
+                let mut keys = potfile.keys.lock().unwrap();
+
+                for line in BufReader::new(file).lines() {
+                    // later lines win, like with hashcat
+                    if let Some((id, key)) = parse_line(&line?) {
+                        keys.insert(id, key);
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e),
+        }
+
+        Ok(potfile)
+    }
+
+    /// Gives how many handshakes have a key
+    pub fn len(&self) -> usize {
+        self.keys.lock().unwrap().len()
+    }
+
+    /// Gives the keys worth trying on a capture holding `ids`, those of the
+    /// same handshakes first, then of the same access points, then of any
+    /// network with the same name
+    pub fn candidates(&self, ids: &[HandshakeId]) -> Vec<Vec<u8>> {
+        let keys = self.keys.lock().unwrap();
+        let mut ranked: Vec<(u8, &Vec<u8>)> = keys
+            .iter()
+            .filter_map(|(known, key)| {
+                let rank = ids
+                    .iter()
+                    .filter(|id| id.essid == known.essid)
+                    .map(|id| match (id == known, id.ap == known.ap) {
+                        (true, _) => 0,
+                        (false, true) => 1,
+                        (false, false) => 2,
+                    })
+                    .min()?;
+
+                Some((rank, key))
+            })
+            .collect();
+
+        ranked.sort_by_key(|(rank, _)| *rank);
+
+        let mut candidates: Vec<Vec<u8>> = vec![];
+
+        for (_, key) in ranked {
+            if !candidates.contains(key) {
+                candidates.push(Vec::clone(key));
+            }
+        }
+
+        candidates
+    }
+
+    /// Records `key` as the key of every handshake in `ids`, giving how many
+    /// weren't known yet
+    pub fn insert(&self, ids: &[HandshakeId], key: &[u8]) -> io::Result<usize> {
+        let mut keys = self.keys.lock().unwrap();
+        let new: Vec<&HandshakeId> = ids
+            .iter()
+            .filter(|id| keys.get(*id).map(|known| known.as_slice()) != Some(key))
+            .collect();
+
+        if new.is_empty() {
+            return Ok(0);
+        }
+
+        let mut lines = String::new();
+
+        for id in &new {
+            lines.push_str(&format!("{}:{}\n", id, encode_key(key)));
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .mode(0o600)
+            .open(&self.path)?;
+        file.write_all(lines.as_bytes())?;
+        file.sync_data()?;
+
+        for id in &new {
+            keys.insert(HandshakeId::clone(id), key.to_vec());
+        }
+
+        Ok(new.len())
+    }
+
+    /// Adds every `-m 22000` key from the hashcat potfile at `path`
+    pub fn import(&self, path: &Path) -> io::Result<Imported> {
+        let mut imported = Imported::default();
+
+        for line in BufReader::new(File::open(path)?).lines() {
+            match parse_line(&line?) {
+                Some((id, key)) => match self.insert(&[id], &key)? {
+                    0 => imported.skipped += 1,
+                    _ => imported.added += 1,
+                },
+                None => imported.skipped += 1,
+            }
+        }
+
+        Ok(imported)
+    }
+
+    /// Writes every key out in hashcat's potfile format, giving how many
+    pub fn export<W: Write>(&self, mut out: W) -> io::Result<usize> {
+        let keys = self.keys.lock().unwrap();
+
+        for (id, key) in keys.iter() {
+            writeln!(out, "{}:{}", id, encode_key(key))?;
+        }
+
+        out.flush()?;
+        Ok(keys.len())
+    }
+}
+
+/// Parses a potfile line of `mic:ap:station:essid:key`, giving
+/// [Option::None] for lines of other hash modes
+fn parse_line(line: &str) -> Option<(HandshakeId, Vec<u8>)> {
+    // network names holding a colon are written in hex, but keys might
+    // still hold one
+    let at = line.match_indices(':').nth(3)?.0;
+    let (hash, key) = (&line[..at], &line[at + 1..]);
+    let id = HandshakeId::parse(hash)?;
+
+    Some((id, decode_key(key)?))
+}
+
+/// Encodes `key` the way hashcat writes it, in hex if it isn't printable
+fn encode_key(key: &[u8]) -> String {
+    let printable = key.iter().all(|byte| (0x20..0x7f).contains(byte));
+
+    if printable && !key.starts_with(HEX_PREFIX.as_bytes()) {
+        String::from_utf8_lossy(key).into_owned()
+    } else {
+        format!("{}{}]", HEX_PREFIX, hex::encode(key))
+    }
+}
+
+/// Decodes a key as hashcat writes it, giving [Option::None] if its hex is
+/// malformed
+fn decode_key(key: &str) -> Option<Vec<u8>> {
+    match key
+        .strip_prefix(HEX_PREFIX)
+        .and_then(|rest| rest.strip_suffix(']'))
+    {
+        Some(hex) => hex::decode(hex).ok(),
+        None => Some(key.as_bytes().to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn ranked_and_round_tripped() {
+        let dir = TestDir::new("potfile");
+        let id = |digest, ap, essid: &[u8]| HandshakeId {
+            digest: [digest; 16],
+            ap: [ap; 6],
+            station: [9; 6],
+            essid: essid.to_vec(),
+        };
+
+        let potfile = Potfile::open(&dir.join(POTFILE)).unwrap();
+        assert_eq!(
+            potfile
+                .insert(&[id(1, 1, b"home")], b"same network")
+                .unwrap(),
+            1
+        );
+        assert_eq!(potfile.insert(&[id(2, 2, b"home")], b"same ap").unwrap(), 1);
+        assert_eq!(
+            potfile.insert(&[id(3, 3, b"home")], b"\xffexact:").unwrap(),
+            1
+        );
+        assert_eq!(
+            potfile.insert(&[id(3, 3, b"home")], b"\xffexact:").unwrap(),
+            0
+        );
+        assert_eq!(potfile.insert(&[id(4, 4, b"away")], b"other").unwrap(), 1);
+
+        let candidates = potfile.candidates(&[id(3, 3, b"home"), id(5, 2, b"home")]);
+        assert_eq!(
+            candidates,
+            vec![
+                b"\xffexact:".to_vec(),
+                b"same ap".to_vec(),
+                b"same network".to_vec()
+            ]
+        );
+
+        let mut exported = vec![];
+        assert_eq!(potfile.export(&mut exported).unwrap(), 4);
+        let exported = String::from_utf8(exported).unwrap();
+        assert!(exported.contains(&format!(
+            "{}:030303030303:090909090909:home:$HEX[ff65786163743a]\n",
+            "03".repeat(16)
+        )));
+
+        // written by hashcat cracking its `-m 22000` example hash
+        let hashcat =
+            "4d4fe7aac3a2cecab195321ceb99a7d0:fc690c158264:f4747f87f9f4:hashcat-essid:hashcat!";
+        let import_path = dir.join("hashcat.potfile");
+        fs::write(
+            &import_path,
+            format!(
+                "{}{}\n5f4dcc3b5aa765d61d8327deb882cf99:password\n",
+                exported, hashcat
+            ),
+        )
+        .unwrap();
+
+        let reopened = Potfile::open(&dir.join("other.potfile")).unwrap();
+        let imported = reopened.import(&import_path).unwrap();
+        assert_eq!(
+            imported,
+            Imported {
+                added: 5,
+                skipped: 1
+            }
+        );
+        assert_eq!(reopened.candidates(&[id(3, 3, b"home")])[0], b"\xffexact:");
+
+        let (hashcat_id, key) = parse_line(hashcat).unwrap();
+        assert_eq!(hashcat_id.station, [0xf4, 0x74, 0x7f, 0x87, 0xf9, 0xf4]);
+        assert_eq!(reopened.candidates(&[hashcat_id]), vec![key]);
+        assert_eq!(parse_line("4d4fe7aa:fc690c158264:f4747f87f9f4:x:y"), None);
+    }
+}
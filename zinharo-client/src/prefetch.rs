@@ -0,0 +1,127 @@
+//! A bounded queue of jobs fetched and prepared ahead of time, so a worker
+//! finishing one job can start the next straight away instead of waiting on
+//! the api
+
+use crate::coordinator::Coordinator;
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+/// How often workers waiting on an empty queue check if the client is
+/// shutting down
+const POP_STEP: Duration = Duration::from_millis(250);
+
+/// Queue of prepared items shared between a prefetcher and the workers
+pub struct PrefetchQueue<T> {
+    items: Mutex<VecDeque<T>>,
+    ready: Condvar,
+    capacity: usize,
+}
+
+impl<T> PrefetchQueue<T> {
+    /// Creates an empty queue holding at most `capacity` items
+    pub fn new(capacity: usize) -> Self {
+        PrefetchQueue {
+            items: Mutex::new(VecDeque::with_capacity(capacity)),
+            ready: Condvar::new(),
+            capacity,
+        }
+    }
+
+    /// Checks if another item may be prefetched
+    pub fn has_room(&self) -> bool {
+        self.items.lock().unwrap().len() < self.capacity
+    }
+
+    /// Adds a prepared item, waking a waiting worker
+    pub fn push(&self, item: T) {
+        self.items.lock().unwrap().push_back(item);
+        self.ready.notify_one();
+    }
+
+    /// Takes the oldest item, waiting for one if the queue is empty. Gives
+    /// [Option::None] once the client is shutting down
+    pub fn pop(&self, coordinator: &Coordinator) -> Option<T> {
+        let mut items = self.items.lock().unwrap();
+
+        loop {
+            if coordinator.is_shutdown() {
+                return None;
+            }
+
+            if let Some(item) = items.pop_front() {
+                return Some(item);
+            }
+
+            items = self.ready.wait_timeout(items, POP_STEP).unwrap().0;
+        }
+    }
+
+    /// Removes and gives back every item matching `matches`
+    pub fn take_where<F: Fn(&T) -> bool>(&self, matches: F) -> Vec<T> {
+        let mut items = self.items.lock().unwrap();
+        let mut taken = Vec::new();
+
+        for item in items.split_off(0) {
+            if matches(&item) {
+                taken.push(item);
+            } else {
+                items.push_back(item);
+            }
+        }
+
+        taken
+    }
+
+    /// Puts items taken with [PrefetchQueue::take_where] back at the front,
+    /// unless the client is shutting down and the queue may already have
+    /// been drained, in which case they are given back instead
+    pub fn restore(&self, taken: Vec<T>, coordinator: &Coordinator) -> Vec<T> {
+        let mut items = self.items.lock().unwrap();
+
+        if coordinator.is_shutdown() {
+            return taken;
+        }
+
+        for item in taken.into_iter().rev() {
+            items.push_front(item);
+        }
+
+        self.ready.notify_all();
+        Vec::new()
+    }
+
+    /// Empties the queue, used when shutting down
+    pub fn drain(&self) -> Vec<T> {
+        self.items.lock().unwrap().drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounded_and_prunable() {
+        let coordinator = Coordinator::new(Default::default());
+        let queue = PrefetchQueue::new(2);
+
+        queue.push(1);
+        assert!(queue.has_room());
+        queue.push(2);
+        assert!(!queue.has_room());
+
+        assert_eq!(queue.take_where(|item| *item == 1), vec![1]);
+        queue.push(3);
+
+        let taken = queue.take_where(|item| *item == 2);
+        assert!(queue.restore(taken, &coordinator).is_empty());
+
+        assert_eq!(queue.pop(&coordinator), Some(2));
+        assert_eq!(queue.drain(), vec![3]);
+
+        coordinator.shutdown(false);
+        assert_eq!(queue.pop(&coordinator), None);
+        assert_eq!(queue.restore(vec![4], &coordinator), vec![4]);
+    }
+}
@@ -0,0 +1,599 @@
+//! Wordlist preprocessing, turning a raw downloaded list into cracker-ready
+//! candidates. Impossible WPA keys are dropped, duplicates are removed with an
+//! external-memory sort so multi-GB lists never have to fit in memory and the
+//! result is cached on disk keyed by the checksum of the source list
+
+use crate::wordlist;
+use sha2::{Digest, Sha256};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{self, prelude::*, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{self, AtomicUsize};
+use std::time::UNIX_EPOCH;
+
+/// Shortest key a WPA-PSK network accepts
+pub const WPA_MIN_LEN: usize = 8;
+
+/// Longest passphrase a WPA-PSK network accepts (64 characters is a raw PSK)
+pub const WPA_MAX_LEN: usize = 63;
+
+/// Counts [preprocess] calls in this process, so workers preprocessing
+/// different lists at once each get their own scratch directory
+static SCRATCH_DIRS: AtomicUsize = AtomicUsize::new(0);
+
+/// Options for [preprocess], defaulting to WPA length filtering and dedup
+#[derive(Debug, Clone)]
+pub struct PreprocessOptions {
+    /// Candidates shorter than this are dropped
+    pub min_len: usize,
+
+    /// Candidates longer than this are dropped
+    pub max_len: usize,
+
+    /// Removes duplicate candidates, keeping the first occurrence's position
+    pub dedup: bool,
+
+    /// Reorders candidates so the most duplicated come first, implies dedup
+    pub frequency_order: bool,
+
+    /// Keeps the processed list zstd-compressed on disk
+    pub compress: bool,
+
+    /// Approximate bytes of candidates sorted in memory before spilling a run
+    pub chunk_bytes: usize,
+}
+
+impl Default for PreprocessOptions {
+    fn default() -> Self {
+        PreprocessOptions {
+            min_len: WPA_MIN_LEN,
+            max_len: WPA_MAX_LEN,
+            dedup: true,
+            frequency_order: false,
+            compress: false,
+            chunk_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+impl PreprocessOptions {
+    /// Short tag for these options, used in the cache filename so changing
+    /// options never serves a stale list
+    fn tag(&self) -> String {
+        let mode = if self.frequency_order {
+            "freq"
+        } else if self.dedup {
+            "dedup"
+        } else {
+            "raw"
+        };
+        let ext = if self.compress { "txt.zst" } else { "txt" };
+
+        format!("{}-{}-{}.{}", self.min_len, self.max_len, mode, ext)
+    }
+}
+
+/// A single candidate as it moves through the external sort
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Record {
+    /// Candidate bytes without line ending
+    word: Vec<u8>,
+
+    /// Line index the candidate was first seen at
+    first: u64,
+
+    /// Number of times the candidate appeared
+    count: u64,
+}
+
+impl Record {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.first.to_le_bytes())?;
+        writer.write_all(&self.count.to_le_bytes())?;
+        writer.write_all(&(self.word.len() as u32).to_le_bytes())?;
+        writer.write_all(&self.word)
+    }
+
+    /// Reads the next record from a run, giving [Option::None] at the end
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<Option<Self>> {
+        let mut first = [0; 8];
+
+        match reader.read_exact(&mut first) {
+            Ok(_) => (),
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        let mut count = [0; 8];
+        let mut len = [0; 4];
+        reader.read_exact(&mut count)?;
+        reader.read_exact(&mut len)?;
+
+        let mut word = vec![0; u32::from_le_bytes(len) as usize];
+        reader.read_exact(&mut word)?;
+
+        Ok(Some(Record {
+            word,
+            first: u64::from_le_bytes(first),
+            count: u64::from_le_bytes(count),
+        }))
+    }
+}
+
+/// Orderings the external sort can produce
+#[derive(Debug, Clone, Copy)]
+enum SortKey {
+    /// Bytewise by candidate, used to bring duplicates together
+    Word,
+
+    /// Original position in the source list
+    FirstSeen,
+
+    /// Most frequent first, ties broken by original position
+    Frequency,
+}
+
+impl SortKey {
+    fn compare(self, a: &Record, b: &Record) -> Ordering {
+        match self {
+            SortKey::Word => a.word.cmp(&b.word).then(a.first.cmp(&b.first)),
+            SortKey::FirstSeen => a.first.cmp(&b.first),
+            SortKey::Frequency => b.count.cmp(&a.count).then(a.first.cmp(&b.first)),
+        }
+    }
+}
+
+/// Head of a sorted run inside the merge heap
+struct RunHead {
+    record: Record,
+    run: usize,
+    key: SortKey,
+}
+
+impl PartialEq for RunHead {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RunHead {}
+
+impl PartialOrd for RunHead {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RunHead {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key
+            .compare(&self.record, &other.record)
+            .then(self.run.cmp(&other.run))
+    }
+}
+
+/// K-way merge over sorted run files, yielding records in [SortKey] order
+struct RunMerger {
+    readers: Vec<BufReader<File>>,
+    heap: BinaryHeap<Reverse<RunHead>>,
+    key: SortKey,
+}
+
+impl RunMerger {
+    fn new(runs: &[PathBuf], key: SortKey) -> io::Result<Self> {
+        let mut merger = RunMerger {
+            readers: Vec::new(),
+            heap: BinaryHeap::new(),
+            key,
+        };
+
+        for (run, path) in runs.iter().enumerate() {
+            merger.readers.push(BufReader::new(File::open(path)?));
+            merger.advance(run)?;
+        }
+
+        Ok(merger)
+    }
+
+    /// Pulls the next record of `run` into the heap if it has one left
+    fn advance(&mut self, run: usize) -> io::Result<()> {
+        if let Some(record) = Record::read_from(&mut self.readers[run])? {
+            self.heap.push(Reverse(RunHead {
+                record,
+                run,
+                key: self.key,
+            }));
+        }
+
+        Ok(())
+    }
+}
+
+impl Iterator for RunMerger {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse(head) = self.heap.pop()?;
+
+        match self.advance(head.run) {
+            Ok(_) => Some(Ok(head.record)),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Sorts `records` into on-disk runs of roughly `chunk_bytes` each. With
+/// [SortKey::Word], duplicates inside a run are combined before spilling
+fn spill_runs<I: Iterator<Item = io::Result<Record>>>(
+    records: I,
+    scratch: &Path,
+    prefix: &str,
+    chunk_bytes: usize,
+    key: SortKey,
+) -> io::Result<Vec<PathBuf>> {
+    let mut runs = Vec::new();
+    let mut chunk: Vec<Record> = Vec::new();
+    let mut chunk_size = 0;
+
+    let mut spill = |chunk: &mut Vec<Record>| -> io::Result<()> {
+        chunk.sort_by(|a, b| key.compare(a, b));
+
+        if let SortKey::Word = key {
+            chunk.dedup_by(|dupe, kept| {
+                if dupe.word == kept.word {
+                    kept.count += dupe.count;
+                    true
+                } else {
+                    false
+                }
+            });
+        }
+
+        let path = scratch.join(format!("{}-{}.run", prefix, runs.len()));
+        let mut writer = BufWriter::new(File::create(&path)?);
+
+        for record in chunk.drain(..) {
+            record.write_to(&mut writer)?;
+        }
+
+        writer.flush()?;
+        runs.push(path);
+        Ok(())
+    };
+
+    for record in records {
+        let record = record?;
+        chunk_size += record.word.len() + 32;
+        chunk.push(record);
+
+        if chunk_size >= chunk_bytes {
+            spill(&mut chunk)?;
+            chunk_size = 0;
+        }
+    }
+
+    if !chunk.is_empty() {
+        spill(&mut chunk)?;
+    }
+
+    Ok(runs)
+}
+
+/// Collapses adjacent equal candidates of a word-sorted stream into one record
+/// holding the earliest position and the summed count
+fn combine_duplicates<I: Iterator<Item = io::Result<Record>>>(
+    sorted: I,
+) -> impl Iterator<Item = io::Result<Record>> {
+    let mut sorted = sorted.peekable();
+
+    std::iter::from_fn(move || {
+        let mut record = match sorted.next()? {
+            Ok(x) => x,
+            Err(e) => return Some(Err(e)),
+        };
+
+        while let Some(Ok(next)) = sorted.peek() {
+            if next.word != record.word {
+                break;
+            }
+
+            record.first = record.first.min(next.first);
+            record.count += next.count;
+            sorted.next();
+        }
+
+        Some(Ok(record))
+    })
+}
+
+/// Checks if a candidate could possibly be a WPA key under `options`
+pub fn is_candidate(word: &[u8], options: &PreprocessOptions) -> bool {
+    word.len() >= options.min_len && word.len() <= options.max_len
+}
+
+/// Strips a trailing `\n` or `\r\n` from a raw wordlist line
+pub fn trim_line_ending(mut line: &[u8]) -> &[u8] {
+    if line.ends_with(b"\n") {
+        line = &line[..line.len() - 1];
+    }
+
+    if line.ends_with(b"\r") {
+        line = &line[..line.len() - 1];
+    }
+
+    line
+}
+
+/// Streams `source` as records of every line that passes the length filter
+fn filtered_records<'a>(
+    source: Box<dyn BufRead + Send>,
+    options: &'a PreprocessOptions,
+) -> impl Iterator<Item = io::Result<Record>> + 'a {
+    let mut source = source;
+    let mut index = 0;
+
+    std::iter::from_fn(move || loop {
+        let mut line = Vec::new();
+
+        match source.read_until(b'\n', &mut line) {
+            Ok(0) => return None,
+            Ok(_) => (),
+            Err(e) => return Some(Err(e)),
+        }
+
+        let word = trim_line_ending(&line);
+        index += 1;
+
+        if is_candidate(word, options) {
+            return Some(Ok(Record {
+                word: word.to_vec(),
+                first: index - 1,
+                count: 1,
+            }));
+        }
+    })
+}
+
+/// Computes the hex-encoded SHA-256 of the file at `path`. The result is
+/// memoised in a `.sha256` sidecar keyed by size and modification time so huge
+/// lists are only hashed once
+pub fn checksum(path: &Path) -> io::Result<String> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let stamp = format!("{}-{}", metadata.len(), modified);
+
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".sha256");
+    let sidecar = PathBuf::from(sidecar);
+
+    if let Ok(memo) = fs::read_to_string(&sidecar) {
+        if let Some((memo_stamp, memo_hash)) = memo.trim().split_once(' ') {
+            if memo_stamp == stamp {
+                return Ok(memo_hash.to_string());
+            }
+        }
+    }
+
+    let mut hasher = Sha256::new();
+    let mut file = BufReader::new(File::open(path)?);
+    let mut buf = vec![0; 1024 * 1024];
+
+    loop {
+        match file.read(&mut buf)? {
+            0 => break,
+            read => hasher.update(&buf[..read]),
+        }
+    }
+
+    let hash = hex::encode(hasher.finalize());
+    fs::write(&sidecar, format!("{} {}\n", stamp, hash)).ok(); // only a cache
+
+    Ok(hash)
+}
+
+/// Processes the raw wordlist at `source` into a cracker-ready list inside
+/// `cache_dir`, reusing a previous result if the source checksum and options
+/// match. Returns the path of the processed list
+pub fn preprocess(
+    source: &Path,
+    cache_dir: &Path,
+    options: &PreprocessOptions,
+) -> io::Result<PathBuf> {
+    fs::create_dir_all(cache_dir)?;
+
+    let processed_path = cache_dir.join(format!("{}-{}", checksum(source)?, options.tag()));
+
+    if processed_path.exists() {
+        return Ok(processed_path);
+    }
+
+    let scratch = cache_dir.join(format!(
+        ".scratch-{}-{}",
+        process::id(),
+        SCRATCH_DIRS.fetch_add(1, atomic::Ordering::SeqCst)
+    ));
+    fs::create_dir_all(&scratch)?;
+
+    let result = write_processed(source, &scratch, &processed_path, options);
+    fs::remove_dir_all(&scratch).ok();
+
+    result.map(|_| processed_path)
+}
+
+/// Does the heavy lifting for [preprocess], using `scratch` for sort runs and
+/// the partly written list
+fn write_processed(
+    source: &Path,
+    scratch: &Path,
+    processed_path: &Path,
+    options: &PreprocessOptions,
+) -> io::Result<()> {
+    let part_path = scratch.join("processed.part");
+    let part_file = BufWriter::new(File::create(&part_path)?);
+    let mut writer: Box<dyn Write> = if options.compress {
+        Box::new(zstd::stream::write::Encoder::new(part_file, 3)?.auto_finish())
+    } else {
+        Box::new(part_file)
+    };
+
+    let records = filtered_records(wordlist::open_decoded(source)?, options);
+
+    let mut emit = |record: Record| -> io::Result<()> {
+        writer.write_all(&record.word)?;
+        writer.write_all(b"\n")
+    };
+
+    if options.dedup || options.frequency_order {
+        let by_word = spill_runs(records, scratch, "word", options.chunk_bytes, SortKey::Word)?;
+        let unique = combine_duplicates(RunMerger::new(&by_word, SortKey::Word)?);
+
+        let key = if options.frequency_order {
+            SortKey::Frequency
+        } else {
+            SortKey::FirstSeen
+        };
+
+        let ordered = spill_runs(unique, scratch, "order", options.chunk_bytes, key)?;
+
+        for path in &by_word {
+            fs::remove_file(path).ok();
+        }
+
+        for record in RunMerger::new(&ordered, key)? {
+            emit(record?)?;
+        }
+    } else {
+        for record in records {
+            emit(record?)?;
+        }
+    }
+
+    writer.flush()?;
+    drop(writer);
+    fs::rename(part_path, processed_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    /// Preprocesses `input` in a throwaway cache with tiny sort chunks so the
+    /// multi-run merge is exercised, returning the processed lines
+    fn run(input: &str, options: PreprocessOptions) -> Vec<String> {
+        let dir = TestDir::new(&format!("preprocess-{}", options.tag()));
+
+        let source = dir.join("source.txt");
+        fs::write(&source, input).unwrap();
+
+        let options = PreprocessOptions {
+            chunk_bytes: 64,
+            ..options
+        };
+        let processed = preprocess(&source, &dir.join("cache"), &options).unwrap();
+        let mut contents = String::new();
+        wordlist::open_decoded(&processed)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+
+        contents.lines().map(String::from).collect()
+    }
+
+    const INPUT: &str = "short\nhunter22\npassword\r\nletmein123\npassword\n\
+        hunter22\npassword\naaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\n";
+
+    #[test]
+    fn filters_and_dedups_in_order() {
+        assert_eq!(
+            run(INPUT, PreprocessOptions::default()),
+            vec!["hunter22", "password", "letmein123"]
+        );
+    }
+
+    #[test]
+    fn frequency_order() {
+        let options = PreprocessOptions {
+            frequency_order: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            run(INPUT, options),
+            vec!["password", "hunter22", "letmein123"]
+        );
+    }
+
+    #[test]
+    fn filter_only_keeps_duplicates() {
+        let options = PreprocessOptions {
+            dedup: false,
+            compress: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            run(INPUT, options),
+            vec![
+                "hunter22",
+                "password",
+                "letmein123",
+                "password",
+                "hunter22",
+                "password"
+            ]
+        );
+    }
+
+    #[test]
+    fn concurrent_lists_share_a_cache() {
+        let dir = TestDir::new("preprocess-concurrent");
+        let cache = dir.join("cache");
+        let start = Arc::new(Barrier::new(2));
+
+        let workers: Vec<_> = ["first", "second"]
+            .iter()
+            .map(|name| {
+                let words: Vec<String> = (0..2000).map(|i| format!("{}{:05}", name, i)).collect();
+                let source = dir.join(format!("{}.txt", name));
+                fs::write(&source, format!("{}\n{}\n", words.join("\n"), words[7])).unwrap();
+
+                let cache = PathBuf::clone(&cache);
+                let start = Arc::clone(&start);
+                let options = PreprocessOptions {
+                    chunk_bytes: 64,
+                    ..Default::default()
+                };
+
+                thread::spawn(move || {
+                    start.wait();
+                    let processed = preprocess(&source, &cache, &options).unwrap();
+                    let mut contents = String::new();
+                    wordlist::open_decoded(&processed)
+                        .unwrap()
+                        .read_to_string(&mut contents)
+                        .unwrap();
+
+                    assert_eq!(contents.lines().collect::<Vec<_>>(), words);
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        // only the two processed lists are left, without any scratch
+        assert_eq!(fs::read_dir(&cache).unwrap().count(), 2);
+    }
+}
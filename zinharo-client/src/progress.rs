@@ -0,0 +1,243 @@
+//! Parses the live status screen aircrack-ng draws on its stdout into a
+//! stream of [CrackProgress] updates, so a running job can be followed long
+//! before the cracker exits
+
+use std::fmt;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+/// Longest line kept whilst parsing, as status lines are short and anything
+/// longer is only ever the cracker echoing something back
+const MAX_LINE_LEN: usize = 1024;
+
+/// A single snapshot of how far aircrack-ng has got
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CrackProgress {
+    /// Candidates tested by this run of the cracker
+    pub keys_tested: u64,
+
+    /// Total candidates, if the cracker knows it. Never known when
+    /// candidates are streamed through stdin
+    pub keys_total: Option<u64>,
+
+    /// Current cracking speed in keys per second
+    pub keys_per_sec: Option<f64>,
+
+    /// Percentage of candidates tested, as reported by the cracker
+    pub percentage: Option<f64>,
+
+    /// Passphrase the cracker was testing when this snapshot was drawn
+    pub current_passphrase: Option<String>,
+}
+
+impl CrackProgress {
+    /// Estimates how long is left until `total` candidates are tested at the
+    /// current speed
+    pub fn eta(&self, total: u64) -> Option<Duration> {
+        let keys_per_sec = self.keys_per_sec.filter(|speed| *speed > 0.0)?;
+        let remaining = total.saturating_sub(self.keys_tested);
+
+        Some(Duration::from_secs_f64(remaining as f64 / keys_per_sec))
+    }
+}
+
+impl fmt::Display for CrackProgress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.keys_total {
+            Some(total) => write!(f, "{}/{} keys tested", self.keys_tested, total)?,
+            None => write!(f, "{} keys tested", self.keys_tested)?,
+        }
+
+        if let Some(speed) = self.keys_per_sec {
+            write!(f, " ({:.2} k/s)", speed)?;
+        }
+
+        if let Some(percentage) = self.percentage {
+            write!(f, ", {:.2}%", percentage)?;
+        }
+
+        if let Some(passphrase) = &self.current_passphrase {
+            write!(f, ", current passphrase `{}`", passphrase)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Formats a duration the way a human would read out an ETA
+pub fn format_eta(eta: Duration) -> String {
+    let secs = eta.as_secs();
+
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, m, _) => format!("{}h {}m", h, m),
+    }
+}
+
+/// Whether `byte` ends a terminal escape sequence. Sequences are only
+/// dropped up to their final byte in `@..=~`, where the opening `[` is skipped
+fn ends_escape(byte: u8) -> bool {
+    byte != b'[' && (b'@'..=b'~').contains(&byte)
+}
+
+/// Drops terminal escape codes from complete output, turning carriage
+/// returns into newlines
+pub fn strip_escapes(buf: &[u8]) -> String {
+    let mut escape = false;
+    let mut stripped = Vec::with_capacity(buf.len());
+
+    for byte in buf {
+        match (escape, *byte) {
+            (true, byte) => escape = !ends_escape(byte),
+            (false, 0x1b) => escape = true,
+            (false, b'\r') => stripped.push(b'\n'),
+            (false, byte) => stripped.push(byte),
+        }
+    }
+
+    String::from_utf8_lossy(&stripped).into_owned()
+}
+
+/// Incrementally parses aircrack-ng's stdout. Each status screen starts with
+/// a "keys tested" line, so a snapshot is sent once the next screen starts
+/// (or output ends) and every line belonging to it has been seen
+pub struct ProgressParser {
+    line: Vec<u8>,
+    escape: bool,
+    current: Option<CrackProgress>,
+    updates: Sender<CrackProgress>,
+}
+
+impl ProgressParser {
+    /// Creates a parser sending every snapshot it reads to `updates`
+    pub fn new(updates: Sender<CrackProgress>) -> Self {
+        ProgressParser {
+            line: Vec::new(),
+            escape: false,
+            current: None,
+            updates,
+        }
+    }
+
+    /// Feeds more raw output into the parser, dropping terminal escape codes
+    /// and treating carriage returns like newlines
+    pub fn feed(&mut self, buf: &[u8]) {
+        for byte in buf {
+            match (self.escape, *byte) {
+                (true, byte) => self.escape = !ends_escape(byte),
+                (false, 0x1b) => self.escape = true,
+                (false, b'\n') | (false, b'\r') => self.end_line(),
+                (false, _) if self.line.len() >= MAX_LINE_LEN => (),
+                (false, byte) => self.line.push(byte),
+            }
+        }
+    }
+
+    /// Sends the last snapshot once the cracker's output has ended
+    pub fn finish(mut self) {
+        self.end_line();
+        self.send();
+    }
+
+    /// Sends the snapshot being built, if any
+    fn send(&mut self) {
+        if let Some(progress) = self.current.take() {
+            self.updates.send(progress).ok();
+        }
+    }
+
+    /// Parses a complete line into the snapshot being built
+    fn end_line(&mut self) {
+        let line = String::from_utf8_lossy(&self.line).trim().to_string();
+        self.line.clear();
+
+        if let Some((keys_tested, keys_total, keys_per_sec)) = parse_keys_tested(&line) {
+            self.send();
+            self.current = Some(CrackProgress {
+                keys_tested,
+                keys_total,
+                keys_per_sec,
+                ..Default::default()
+            });
+        } else if let Some(current) = &mut self.current {
+            if let Some(rest) = line.strip_prefix("Current passphrase:") {
+                current.current_passphrase = Some(rest.trim().to_string());
+            } else if line.starts_with("Time left:") {
+                current.percentage = line
+                    .split_whitespace()
+                    .last()
+                    .and_then(|last| last.strip_suffix('%'))
+                    .and_then(|percentage| percentage.parse().ok());
+            }
+        }
+    }
+}
+
+/// Checks if `line` is the "keys tested" line each status screen starts with
+pub fn is_status_line(line: &str) -> bool {
+    parse_keys_tested(line).is_some()
+}
+
+/// Parses a status line like `[00:00:05] 1234/5678 keys tested (812.40 k/s)`
+/// into keys tested, keys total and keys per second
+fn parse_keys_tested(line: &str) -> Option<(u64, Option<u64>, Option<f64>)> {
+    let (counts, rest) = line.split_once(" keys tested")?;
+    let counts = counts.rsplit(' ').next()?;
+
+    let (keys_tested, keys_total) = match counts.split_once('/') {
+        Some((tested, total)) => (tested.parse().ok()?, total.parse().ok()),
+        None => (counts.parse().ok()?, None),
+    };
+
+    let keys_per_sec = rest
+        .trim()
+        .strip_prefix('(')
+        .and_then(|speed| speed.split_whitespace().next())
+        .and_then(|speed| speed.parse().ok());
+
+    Some((keys_tested, keys_total, keys_per_sec))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn parses_status_screens() {
+        let (tx, rx) = mpsc::channel();
+        let mut parser = ProgressParser::new(tx);
+
+        parser.feed(b"\x1b[2J\x1b[2;1H\n      [00:00:01] 1200 keys tested (1187.3");
+        parser.feed(b"4 k/s) \n\n      Time left: 7 seconds      12.50%\n\n");
+        parser.feed(b"               Current passphrase: hunter22 \n\r\x1b[2;1H");
+        parser.feed(b"      [00:00:02] 2400/9600 keys tested (1190.01 k/s) \r");
+        parser.finish();
+
+        let updates: Vec<_> = rx.iter().collect();
+
+        assert_eq!(
+            updates,
+            vec![
+                CrackProgress {
+                    keys_tested: 1200,
+                    keys_total: None,
+                    keys_per_sec: Some(1187.34),
+                    percentage: Some(12.5),
+                    current_passphrase: Some(String::from("hunter22")),
+                },
+                CrackProgress {
+                    keys_tested: 2400,
+                    keys_total: Some(9600),
+                    keys_per_sec: Some(1190.01),
+                    ..Default::default()
+                },
+            ]
+        );
+        assert_eq!(
+            updates[1].eta(9600).map(format_eta),
+            Some(String::from("6s"))
+        );
+    }
+}
@@ -0,0 +1,430 @@
+//! Supervises the cracker process, so a pathological capture can't hang a
+//! worker forever. The cracker runs in its own process group under optional
+//! cpu/memory limits and scheduling priorities, and is killed as a whole once
+//! it runs past its deadline or goes silent for too long
+
+use std::fmt;
+use std::io;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Child, Command, ExitStatus};
+use std::time::{Duration, Instant};
+
+/// How long the cracker may go without output or consuming candidates before
+/// it is presumed hung, unless configured otherwise
+const DEFAULT_SILENCE: Duration = Duration::from_secs(600);
+
+/// Seconds past the cpu limit before the kernel kills the cracker outright,
+/// giving it time to die from `SIGXCPU` first
+const CPU_GRACE_SECS: u64 = 5;
+
+/// Scheduling class for the cracker's disk io, like `ionice -c`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IoPriority {
+    /// Only gets disk time when nothing else wants it
+    Idle,
+
+    /// Normal scheduling at a level from 0 (highest) to 7 (lowest)
+    BestEffort(u8),
+}
+
+impl IoPriority {
+    /// Parses `idle`, `best-effort` or `best-effort:N`
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.split_once(':') {
+            None if value == "idle" => Some(IoPriority::Idle),
+            None if value == "best-effort" => Some(IoPriority::BestEffort(4)),
+            Some(("best-effort", level)) => match level.parse() {
+                Ok(level) if level <= 7 => Some(IoPriority::BestEffort(level)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Packs the priority the way `ioprio_set` takes it
+    fn ioprio(self) -> i32 {
+        /// Shift of the class above the level, from `linux/ioprio.h`
+        const CLASS_SHIFT: i32 = 13;
+
+        match self {
+            IoPriority::Idle => 3 << CLASS_SHIFT,
+            IoPriority::BestEffort(level) => 2 << CLASS_SHIFT | level as i32,
+        }
+    }
+}
+
+/// A limit the kernel enforces on the cracker
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resource {
+    /// Cpu time
+    Cpu,
+
+    /// Address space
+    Memory,
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Resource::Cpu => write!(f, "cpu"),
+            Resource::Memory => write!(f, "memory"),
+        }
+    }
+}
+
+/// Limits the cracker is run under
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    /// Wall-clock time a single run of the cracker may take
+    pub deadline: Option<Duration>,
+
+    /// How long the cracker may go without output or consuming candidates
+    pub silence: Option<Duration>,
+
+    /// Cpu time the cracker may use, in seconds
+    pub cpu_secs: Option<u64>,
+
+    /// Address space the cracker may use, in bytes
+    pub memory_bytes: Option<u64>,
+
+    /// Niceness the cracker runs at
+    pub nice: Option<i32>,
+
+    /// Scheduling class of the cracker's disk io
+    pub ionice: Option<IoPriority>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            deadline: None,
+            silence: Some(DEFAULT_SILENCE),
+            cpu_secs: None,
+            memory_bytes: None,
+            nice: None,
+            ionice: None,
+        }
+    }
+}
+
+impl Limits {
+    /// Makes `command` start in its own process group, so it can be killed
+    /// along with anything it spawns, with these limits applied
+    pub fn apply(&self, command: &mut Command) {
+        let limits = Limits::clone(self);
+
+        command.process_group(0);
+
+        // only async-signal-safe calls are made between fork and exec
+        unsafe {
+            command.pre_exec(move || limits.restrict());
+        }
+    }
+
+    /// Applies the kernel enforced limits to the current process
+    fn restrict(&self) -> io::Result<()> {
+        /// Turns a libc return code into an [io::Result]
+        fn check(code: libc::c_int) -> io::Result<()> {
+            match code {
+                0 => Ok(()),
+                _ => Err(io::Error::last_os_error()),
+            }
+        }
+
+        if let Some(secs) = self.cpu_secs {
+            let limit = libc::rlimit {
+                rlim_cur: secs as libc::rlim_t,
+                rlim_max: secs.saturating_add(CPU_GRACE_SECS) as libc::rlim_t,
+            };
+            check(unsafe { libc::setrlimit(libc::RLIMIT_CPU, &limit) })?;
+        }
+
+        if let Some(bytes) = self.memory_bytes {
+            let limit = libc::rlimit {
+                rlim_cur: bytes as libc::rlim_t,
+                rlim_max: bytes as libc::rlim_t,
+            };
+            check(unsafe { libc::setrlimit(libc::RLIMIT_AS, &limit) })?;
+        }
+
+        if let Some(nice) = self.nice {
+            check(unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) })?;
+        }
+
+        #[cfg(target_os = "linux")]
+        if let Some(ionice) = self.ionice {
+            /// `IOPRIO_WHO_PROCESS` from `linux/ioprio.h`
+            const WHO_PROCESS: libc::c_int = 1;
+
+            let code =
+                unsafe { libc::syscall(libc::SYS_ioprio_set, WHO_PROCESS, 0, ionice.ioprio()) };
+            check(code as libc::c_int)?;
+        }
+
+        Ok(())
+    }
+
+    /// Works out if a cracker which exited by itself was stopped by one of
+    /// the kernel enforced limits. How it died is only trusted alongside
+    /// what it used, as the same signals come from crashes, the OOM killer or
+    /// a user killing it by hand
+    pub fn limit_hit(&self, exit: &Exit, stderr: &str) -> Option<Resource> {
+        let signal = exit.status.signal();
+
+        if let Some(secs) = self.cpu_secs {
+            // the soft limit sends SIGXCPU, the hard limit SIGKILL after it
+            let over = exit.cpu >= Duration::from_secs(secs);
+
+            match signal {
+                Some(libc::SIGXCPU) => return Some(Resource::Cpu),
+                Some(libc::SIGKILL) if over => return Some(Resource::Cpu),
+                _ => (),
+            }
+        }
+
+        if let Some(bytes) = self.memory_bytes {
+            // allocations failing under the limit make the cracker bail out
+            // with an error, or crash if it doesn't check, in which case it
+            // must have been close to the limit already
+            let reported =
+                stderr.contains("Cannot allocate memory") || stderr.contains("out of memory");
+            let crashed = matches!(
+                signal,
+                Some(libc::SIGSEGV) | Some(libc::SIGABRT) | Some(libc::SIGBUS)
+            );
+            let near_limit = exit.max_rss_bytes >= bytes / 10 * 9;
+
+            if reported || crashed && near_limit {
+                return Some(Resource::Memory);
+            }
+        }
+
+        None
+    }
+}
+
+/// How a supervised process ended, alongside what it used
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exit {
+    /// Exit status of the process
+    pub status: ExitStatus,
+
+    /// Cpu time used, in user and kernel mode together
+    pub cpu: Duration,
+
+    /// Peak resident memory, in bytes
+    pub max_rss_bytes: u64,
+}
+
+/// Reaps `child` if it has exited, giving its exit alongside its resource
+/// usage. The [Child] mustn't be waited on or killed afterwards
+pub fn try_reap(child: &Child) -> io::Result<Option<Exit>> {
+    wait4(child, libc::WNOHANG)
+}
+
+/// Waits for `child` to exit and reaps it like [try_reap]
+pub fn reap(child: &Child) -> io::Result<Exit> {
+    loop {
+        if let Some(exit) = wait4(child, 0)? {
+            return Ok(exit);
+        }
+    }
+}
+
+/// Calls `wait4` on `child`, giving [Option::None] if it hasn't exited or
+/// the call was interrupted
+fn wait4(child: &Child, options: libc::c_int) -> io::Result<Option<Exit>> {
+    let mut status = 0;
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    let pid = unsafe { libc::wait4(child.id() as libc::pid_t, &mut status, options, &mut usage) };
+
+    match pid {
+        0 => Ok(None),
+        -1 => match io::Error::last_os_error() {
+            e if e.kind() == io::ErrorKind::Interrupted => Ok(None),
+            e => Err(e),
+        },
+        _ => {
+            let time = |tv: libc::timeval| {
+                Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+            };
+
+            Ok(Some(Exit {
+                status: ExitStatus::from_raw(status),
+                cpu: time(usage.ru_utime) + time(usage.ru_stime),
+                // reported in kilobytes on linux
+                max_rss_bytes: usage.ru_maxrss as u64 * 1024,
+            }))
+        }
+    }
+}
+
+/// Why a supervised run of the cracker ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    /// The cracker exited by itself
+    Exited,
+
+    /// The cracker was killed because it was asked to stop
+    Cancelled,
+
+    /// The cracker was killed for running past its deadline
+    TimedOut { limit: Duration },
+
+    /// The cracker was killed for going silent
+    Hung { silent: Duration },
+
+    /// The cracker was stopped by a kernel enforced limit
+    ResourceLimit(Resource),
+}
+
+/// Keeps track of a running cracker's deadline and activity
+pub struct Watchdog<'a> {
+    limits: &'a Limits,
+    started: Instant,
+    activity: u64,
+    last_active: Instant,
+}
+
+impl<'a> Watchdog<'a> {
+    /// Starts watching a cracker that was just spawned
+    pub fn new(limits: &'a Limits) -> Self {
+        Watchdog::started_at(limits, Instant::now())
+    }
+
+    /// Starts watching a cracker spawned at `started`
+    fn started_at(limits: &'a Limits, started: Instant) -> Self {
+        Watchdog {
+            limits,
+            started,
+            activity: 0,
+            last_active: started,
+        }
+    }
+
+    /// Checks on the cracker given a count which grows whilst it is doing
+    /// anything, like bytes of output plus candidates consumed. Gives why it
+    /// should be killed, if it should
+    pub fn check(&mut self, activity: u64) -> Option<Verdict> {
+        self.check_at(activity, Instant::now())
+    }
+
+    /// Same as [Watchdog::check] at a given point in time
+    fn check_at(&mut self, activity: u64, now: Instant) -> Option<Verdict> {
+        if activity != self.activity {
+            self.activity = activity;
+            self.last_active = now;
+        }
+
+        if let Some(limit) = self.limits.deadline {
+            if now.saturating_duration_since(self.started) >= limit {
+                return Some(Verdict::TimedOut { limit });
+            }
+        }
+
+        let silent = now.saturating_duration_since(self.last_active);
+
+        match self.limits.silence {
+            Some(limit) if silent >= limit => Some(Verdict::Hung { silent }),
+            _ => None,
+        }
+    }
+}
+
+/// Kills the cracker's whole process group, falling back to just the cracker
+/// if the group is already gone
+pub fn kill_group(child: &mut Child) {
+    let killed = unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) };
+
+    if killed != 0 {
+        child.kill().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watchdog_deadline_and_silence() {
+        let limits = Limits {
+            deadline: Some(Duration::from_secs(60)),
+            silence: Some(Duration::from_secs(10)),
+            ..Default::default()
+        };
+        let started = Instant::now();
+        let mut watchdog = Watchdog::started_at(&limits, started);
+        let at = |secs| started + Duration::from_secs(secs);
+
+        assert_eq!(watchdog.check_at(0, at(9)), None);
+        assert_eq!(watchdog.check_at(5, at(15)), None);
+        assert_eq!(
+            watchdog.check_at(5, at(25)),
+            Some(Verdict::Hung {
+                silent: Duration::from_secs(10)
+            })
+        );
+        assert_eq!(watchdog.check_at(6, at(30)), None);
+        assert_eq!(
+            watchdog.check_at(7, at(60)),
+            Some(Verdict::TimedOut {
+                limit: Duration::from_secs(60)
+            })
+        );
+
+        assert_eq!(
+            IoPriority::parse("best-effort:7"),
+            Some(IoPriority::BestEffort(7))
+        );
+        assert_eq!(IoPriority::parse("best-effort:8"), None);
+        assert_eq!(IoPriority::Idle.ioprio(), 0x6000);
+    }
+
+    #[test]
+    fn limits_confirmed_by_usage() {
+        let limits = Limits {
+            cpu_secs: Some(60),
+            memory_bytes: Some(1000 * 1024),
+            ..Default::default()
+        };
+        let exit = |signal, cpu_secs, max_rss_bytes| Exit {
+            status: ExitStatus::from_raw(signal),
+            cpu: Duration::from_secs(cpu_secs),
+            max_rss_bytes,
+        };
+
+        assert_eq!(
+            limits.limit_hit(&exit(libc::SIGXCPU, 60, 0), ""),
+            Some(Resource::Cpu)
+        );
+        assert_eq!(
+            limits.limit_hit(&exit(libc::SIGKILL, 64, 0), ""),
+            Some(Resource::Cpu)
+        );
+        assert_eq!(limits.limit_hit(&exit(libc::SIGKILL, 3, 0), ""), None);
+        assert_eq!(limits.limit_hit(&exit(libc::SIGSEGV, 3, 4096), ""), None);
+        assert_eq!(
+            limits.limit_hit(&exit(libc::SIGSEGV, 3, 990 * 1024), ""),
+            Some(Resource::Memory)
+        );
+        assert_eq!(
+            limits.limit_hit(&exit(0x100, 3, 4096), "read: Cannot allocate memory"),
+            Some(Resource::Memory)
+        );
+        assert_eq!(
+            Limits::default().limit_hit(&exit(libc::SIGXCPU, 60, 0), ""),
+            None
+        );
+    }
+
+    #[test]
+    // reaped by `reap` itself, which clippy can't see
+    #[allow(clippy::zombie_processes)]
+    fn reaped_with_usage() {
+        let child = Command::new("true").spawn().unwrap();
+        let exit = reap(&child).unwrap();
+
+        assert!(exit.status.success());
+        assert!(exit.max_rss_bytes > 0);
+    }
+}
@@ -0,0 +1,52 @@
+//! Throwaway directories for tests, each unique to the test making it and
+//! removed again once dropped, even if the test fails
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts directories made by this process, so tests running at once never
+/// share one
+static MADE: AtomicUsize = AtomicUsize::new(0);
+
+/// An empty directory removed with everything in it once dropped
+pub struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    /// Makes an empty directory named after `name` which no other test, or
+    /// other run of the tests, is using
+    pub fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!(
+            "zinharo-{}-{}-{}",
+            name,
+            process::id(),
+            MADE.fetch_add(1, Ordering::SeqCst)
+        ));
+
+        // left behind by an earlier run which was killed with the same pid
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        TestDir { path }
+    }
+
+    /// Gives where the directory is
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Gives the path of `name` inside the directory
+    pub fn join<P: AsRef<Path>>(&self, name: P) -> PathBuf {
+        self.path.join(name)
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
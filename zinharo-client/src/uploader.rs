@@ -0,0 +1,879 @@
+//! Bulk uploading of captures, optionally watching a directory capture rigs
+//! write into. Files are only picked up once they stop changing, are checked
+//! to look like a capture and are deduplicated by content, with every upload
+//! recorded in a local manifest so the same capture is never sent twice.
+//! Captures holding a handshake already uploaded from another file are found
+//! by their [Fingerprint] instead of being uploaded again
+
+use crate::coordinator::Coordinator;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use zinharo_rs::{Fingerprint, Ledger, UploadOptions, ZinharoAccess, ZinharoError, ZinharoHash};
+
+/// Filename of the manifest inside the state directory, unless configured
+/// otherwise
+pub const MANIFEST_FILE: &str = "uploads.json";
+
+/// Filename of the fingerprint [Ledger], kept alongside the manifest
+pub const LEDGER_FILE: &str = "fingerprints";
+
+/// Captures at least this large have their upload progress logged
+const LOG_PROGRESS_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Extensions of files picked up when watching a directory
+const EXTENSIONS: &[&str] = &["cap", "pcap", "pcapng"];
+
+/// Container formats captures may come in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaptureFormat {
+    /// Classic libpcap, as written by airodump-ng
+    Pcap,
+
+    /// pcapng, as written by hcxdumptool and wireshark
+    Pcapng,
+}
+
+/// Sniffs the format of a capture from its header, giving [Option::None] if
+/// it isn't a pcap or pcapng capture at all
+pub fn capture_format(cap: &[u8]) -> Option<CaptureFormat> {
+    match cap.get(..4)? {
+        // microsecond and nanosecond pcap, in either byte order
+        [0xd4, 0xc3, 0xb2, 0xa1]
+        | [0xa1, 0xb2, 0xc3, 0xd4]
+        | [0x4d, 0x3c, 0xb2, 0xa1]
+        | [0xa1, 0xb2, 0x3c, 0x4d]
+            if cap.len() >= 24 =>
+        {
+            Some(CaptureFormat::Pcap)
+        }
+        [0x0a, 0x0d, 0x0d, 0x0a] if cap.len() >= 28 => Some(CaptureFormat::Pcapng),
+        _ => None,
+    }
+}
+
+/// A capture recorded in the manifest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// ID of the hash the capture was uploaded as
+    pub hash_id: i32,
+
+    /// Every file found holding this capture
+    pub paths: Vec<PathBuf>,
+
+    /// Unix timestamp of when the capture was uploaded
+    pub uploaded: u64,
+}
+
+/// Local record of every capture uploaded, keyed by the hex-encoded SHA-256
+/// of its contents
+pub struct Manifest {
+    path: PathBuf,
+    entries: BTreeMap<String, ManifestEntry>,
+}
+
+impl Manifest {
+    /// Loads the manifest at `path`, starting an empty one if it doesn't
+    /// exist yet. An unreadable manifest is an error rather than being
+    /// overwritten
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let entries = match fs::read(path) {
+            Ok(contents) => serde_json::from_slice(&contents)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Manifest {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    /// Finds the upload of the capture with the given checksum
+    pub fn lookup(&self, sha256: &str) -> Option<&ManifestEntry> {
+        self.entries.get(sha256)
+    }
+
+    /// Gives every file already handled
+    pub fn paths(&self) -> impl Iterator<Item = &PathBuf> {
+        self.entries.values().flat_map(|entry| entry.paths.iter())
+    }
+
+    /// Records `path` as holding the capture uploaded as `hash_id`, then
+    /// atomically saves the manifest
+    pub fn record(&mut self, sha256: &str, path: &Path, hash_id: i32) -> io::Result<()> {
+        let entry = self
+            .entries
+            .entry(sha256.to_string())
+            .or_insert_with(|| ManifestEntry {
+                hash_id,
+                paths: Vec::new(),
+                uploaded: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default(),
+            });
+
+        if !entry.paths.iter().any(|known| known == path) {
+            entry.paths.push(path.to_path_buf());
+        }
+
+        self.save()
+    }
+
+    /// Atomically writes the manifest to its path
+    fn save(&self) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let part_path = self.path.with_extension("part");
+
+        fs::write(&part_path, serde_json::to_vec_pretty(&self.entries)?)?;
+        fs::rename(part_path, &self.path)
+    }
+}
+
+/// How a file last looked whilst waiting for it to stop changing
+struct Observation {
+    len: u64,
+    modified: Option<SystemTime>,
+    since: Instant,
+}
+
+/// Polls a directory for captures, handing out each one once it has stopped
+/// changing for a while so half-written files are never uploaded. Files are
+/// only left alone for good once the manifest has recorded them or they can
+/// never be uploaded, so any that fail are handed out again after settling
+/// once more
+pub struct Watcher {
+    dir: PathBuf,
+    settle: Duration,
+    pending: HashMap<PathBuf, Observation>,
+    in_flight: HashSet<PathBuf>,
+    taken: HashSet<PathBuf>,
+}
+
+impl Watcher {
+    /// Watches `dir`, considering files complete once unchanged for `settle`.
+    /// Files in `handled` are never handed out
+    pub fn new<'a, I>(dir: &Path, settle: Duration, handled: I) -> Self
+    where
+        I: IntoIterator<Item = &'a PathBuf>,
+    {
+        Watcher {
+            dir: dir.to_path_buf(),
+            settle,
+            pending: HashMap::new(),
+            in_flight: HashSet::new(),
+            taken: handled.into_iter().cloned().collect(),
+        }
+    }
+
+    /// Marks a file handed out by [Watcher::poll] as dealt with. It is
+    /// watched again from scratch if it should be tried again with `retry`,
+    /// otherwise it is never handed out again
+    pub fn finished(&mut self, path: &Path, retry: bool) {
+        self.in_flight.remove(path);
+
+        if !retry {
+            self.taken.insert(path.to_path_buf());
+        }
+    }
+
+    /// Gives the captures that have become complete since the last poll
+    pub fn poll(&mut self) -> io::Result<Vec<PathBuf>> {
+        self.poll_at(Instant::now())
+    }
+
+    /// Same as [Watcher::poll] at a given point in time
+    fn poll_at(&mut self, now: Instant) -> io::Result<Vec<PathBuf>> {
+        let mut seen = HashSet::new();
+        let mut complete = Vec::new();
+
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+
+            let is_capture = path
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+                .is_some_and(|extension| EXTENSIONS.contains(&extension.as_str()));
+
+            // rigs often write to a hidden file before renaming it into place
+            let handed_out = self.taken.contains(&path) || self.in_flight.contains(&path);
+
+            if !is_capture || name.starts_with('.') || handed_out {
+                continue;
+            }
+
+            let metadata = match entry.metadata() {
+                Ok(x) if x.is_file() => x,
+                _ => continue,
+            };
+
+            let (len, modified) = (metadata.len(), metadata.modified().ok());
+            seen.insert(PathBuf::clone(&path));
+
+            match self.pending.get_mut(&path) {
+                Some(last) if last.len == len && last.modified == modified => {
+                    if len > 0 && now.saturating_duration_since(last.since) >= self.settle {
+                        self.pending.remove(&path);
+                        self.in_flight.insert(PathBuf::clone(&path));
+                        complete.push(path);
+                    }
+                }
+                _ => {
+                    self.pending.insert(
+                        path,
+                        Observation {
+                            len,
+                            modified,
+                            since: now,
+                        },
+                    );
+                }
+            }
+        }
+
+        // files deleted or renamed away whilst settling
+        self.pending.retain(|path, _| seen.contains(path));
+
+        complete.sort();
+        Ok(complete)
+    }
+}
+
+/// What happened to a single capture
+#[derive(Debug, Serialize)]
+pub struct Uploaded {
+    /// File the capture was read from
+    pub path: PathBuf,
+
+    /// Hex-encoded SHA-256 of the capture, if it could be read
+    pub sha256: Option<String>,
+
+    /// Fingerprint of the handshake in the capture, if it has one
+    pub fingerprint: Option<String>,
+
+    /// ID of the hash the capture is uploaded as, if known
+    pub hash_id: Option<i32>,
+
+    /// Whether the same capture or handshake was already uploaded from
+    /// another file
+    pub duplicate: bool,
+
+    /// Why the capture wasn't uploaded, if it wasn't
+    pub error: Option<String>,
+
+    /// Whether the capture can never be uploaded as it is, like a file too
+    /// large or that isn't a capture, so trying again is pointless
+    pub rejected: bool,
+}
+
+impl Uploaded {
+    /// Creates the outcome of a capture that couldn't be uploaded
+    fn failed(path: PathBuf, sha256: Option<String>, error: String) -> Self {
+        Uploaded {
+            path,
+            sha256,
+            fingerprint: None,
+            hash_id: None,
+            duplicate: false,
+            error: Some(error),
+            rejected: false,
+        }
+    }
+
+    /// Creates the outcome of a capture that can never be uploaded as it is
+    fn rejected(path: PathBuf, sha256: Option<String>, error: String) -> Self {
+        Uploaded {
+            rejected: true,
+            ..Uploaded::failed(path, sha256, error)
+        }
+    }
+}
+
+/// A capture read and checked, ready to upload
+struct Prepared {
+    path: PathBuf,
+    len: u64,
+    sha256: String,
+    fingerprint: Option<Fingerprint>,
+
+    /// What the capture is known by whilst on its way, see [Uploader::prepare]
+    key: String,
+}
+
+/// Uploads captures with a bounded number at once, sharing backoff through a
+/// [Coordinator] so a ratelimit holds back every upload
+pub struct Uploader<'a> {
+    access: &'a ZinharoAccess,
+    coordinator: &'a Coordinator,
+    manifest: Mutex<Manifest>,
+    ledger: Mutex<Ledger>,
+    options: UploadOptions,
+    max_attempts: u32,
+}
+
+impl<'a> Uploader<'a> {
+    /// Creates an uploader sending captures as `options` say, recording files
+    /// into `manifest` and handshakes into `ledger`. A capture is given up on
+    /// after `max_attempts` failed uploads not caused by ratelimiting
+    pub fn new(
+        access: &'a ZinharoAccess,
+        coordinator: &'a Coordinator,
+        manifest: Manifest,
+        ledger: Ledger,
+        options: UploadOptions,
+        max_attempts: u32,
+    ) -> Self {
+        Uploader {
+            access,
+            coordinator,
+            manifest: Mutex::new(manifest),
+            ledger: Mutex::new(ledger),
+            options,
+            max_attempts,
+        }
+    }
+
+    /// Gives every file already recorded in the manifest
+    pub fn handled(&self) -> Vec<PathBuf> {
+        self.manifest.lock().unwrap().paths().cloned().collect()
+    }
+
+    /// Uploads every capture in `paths` with at most `concurrency` at once,
+    /// calling `done` as each one is dealt with
+    pub fn upload_all(
+        &self,
+        paths: Vec<PathBuf>,
+        concurrency: usize,
+        done: &(dyn Fn(Uploaded) + Sync),
+    ) {
+        let mut paths = Some(paths);
+        self.run(|| paths.take(), concurrency, done);
+    }
+
+    /// Uploads captures as they appear in `watcher`'s directory, checking
+    /// every `interval` until the client shuts down
+    pub fn watch(
+        &self,
+        mut watcher: Watcher,
+        interval: Duration,
+        concurrency: usize,
+        done: &(dyn Fn(Uploaded) + Sync),
+    ) {
+        // outcomes from the upload threads, passed back to the watcher
+        let finished = Mutex::new(Vec::new());
+        let mut first = true;
+
+        let watched_done = |uploaded: Uploaded| {
+            let recorded = uploaded.error.is_none() && uploaded.hash_id.is_some();
+            let retry = !recorded && !uploaded.rejected;
+
+            finished
+                .lock()
+                .unwrap()
+                .push((PathBuf::clone(&uploaded.path), retry));
+            done(uploaded);
+        };
+
+        let next = || {
+            if !first && !self.coordinator.wait(interval) {
+                return None;
+            }
+
+            first = false;
+
+            for (path, retry) in finished.lock().unwrap().drain(..) {
+                watcher.finished(&path, retry);
+            }
+
+            match watcher.poll() {
+                Ok(paths) => Some(paths),
+                Err(e) => {
+                    eprintln!("Could not read watched directory, retrying later: '{}'", e);
+                    Some(Vec::new())
+                }
+            }
+        };
+
+        self.run(next, concurrency, &watched_done);
+    }
+
+    /// Hands every batch of paths `next` gives until it gives [Option::None]
+    /// to `concurrency` threads, which read and upload them. At most
+    /// `concurrency` paths wait on the threads at once, so only the captures
+    /// being worked on are ever held in memory
+    fn run<F>(&self, mut next: F, concurrency: usize, done: &(dyn Fn(Uploaded) + Sync))
+    where
+        F: FnMut() -> Option<Vec<PathBuf>>,
+    {
+        let concurrency = concurrency.max(1);
+        let (tx, rx) = mpsc::sync_channel::<PathBuf>(concurrency);
+        let rx = Mutex::new(rx);
+        let queued = Mutex::new(HashSet::new());
+
+        thread::scope(|s| {
+            for _ in 0..concurrency {
+                s.spawn(|| loop {
+                    let path = match rx.lock().unwrap().recv() {
+                        Ok(x) => x,
+                        Err(_) => break,
+                    };
+
+                    match self.prepare(path, &queued) {
+                        Ok(prepared) => {
+                            let key = String::clone(&prepared.key);
+                            let uploaded = self.send(prepared);
+
+                            // the manifest and ledger know of it once sent,
+                            // and a failed capture may be tried again
+                            queued.lock().unwrap().remove(&key);
+                            done(uploaded);
+                        }
+                        Err(uploaded) => done(uploaded),
+                    }
+                });
+            }
+
+            while let Some(paths) = next() {
+                for path in paths {
+                    tx.send(path).unwrap();
+                }
+            }
+
+            drop(tx);
+        });
+    }
+
+    /// Reads and checks the capture at `path`, giving its outcome straight
+    /// away if it shouldn't be uploaded. `queued` holds the fingerprints, or
+    /// checksums without one, of captures on their way until they are sent
+    fn prepare(
+        &self,
+        path: PathBuf,
+        queued: &Mutex<HashSet<String>>,
+    ) -> Result<Prepared, Uploaded> {
+        let mut file = match File::open(&path) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Uploaded::failed(
+                    path,
+                    None,
+                    format!("could not read: {}", e),
+                ))
+            }
+        };
+
+        // checked before reading, so huge files are never read through
+        let len = match file.metadata() {
+            Ok(metadata) if metadata.len() > self.options.max_size => {
+                let error = format!(
+                    "larger than the {} byte upload limit",
+                    self.options.max_size
+                );
+                return Err(Uploaded::rejected(path, None, error));
+            }
+            Ok(metadata) => metadata.len(),
+            Err(e) => {
+                return Err(Uploaded::failed(
+                    path,
+                    None,
+                    format!("could not read: {}", e),
+                ))
+            }
+        };
+
+        let (sha256, format) = match checksum(&mut file) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(Uploaded::failed(
+                    path,
+                    None,
+                    format!("could not read: {}", e),
+                ))
+            }
+        };
+
+        if format.is_none() {
+            return Err(Uploaded::rejected(
+                path,
+                Some(sha256),
+                String::from("not a pcap or pcapng capture"),
+            ));
+        }
+
+        let mut manifest = self.manifest.lock().unwrap();
+
+        if let Some(hash_id) = manifest.lookup(&sha256).map(|entry| entry.hash_id) {
+            if let Err(e) = manifest.record(&sha256, &path, hash_id) {
+                eprintln!("Could not save upload manifest: '{}'", e);
+            }
+
+            return Err(Uploaded {
+                path,
+                sha256: Some(sha256),
+                fingerprint: None,
+                hash_id: Some(hash_id),
+                duplicate: true,
+                error: None,
+                rejected: false,
+            });
+        }
+
+        let fingerprint = file
+            .seek(SeekFrom::Start(0))
+            .ok()
+            .and_then(|_| Fingerprint::from_reader(&mut file).ok());
+        let known = fingerprint
+            .as_ref()
+            .and_then(|fingerprint| self.ledger.lock().unwrap().get(fingerprint));
+
+        if let Some(hash_id) = known {
+            if let Err(e) = manifest.record(&sha256, &path, hash_id) {
+                eprintln!("Could not save upload manifest: '{}'", e);
+            }
+        }
+
+        let key = match &fingerprint {
+            Some(fingerprint) => fingerprint.to_string(),
+            None => String::clone(&sha256),
+        };
+
+        if known.is_some() || !queued.lock().unwrap().insert(String::clone(&key)) {
+            return Err(Uploaded {
+                path,
+                sha256: Some(sha256),
+                fingerprint: fingerprint.map(|fingerprint| fingerprint.to_string()),
+                hash_id: known,
+                duplicate: true,
+                error: None,
+                rejected: false,
+            });
+        }
+
+        Ok(Prepared {
+            path,
+            len,
+            sha256,
+            fingerprint,
+            key,
+        })
+    }
+
+    /// Finds the hash already uploaded with the same handshake as a capture,
+    /// uploading the capture if there isn't one. Gives the ID of the hash and
+    /// whether it already existed. The capture is uploaded from the file
+    /// again, which must still be the `len` bytes it was when checked
+    fn find_or_upload(
+        &self,
+        path: &Path,
+        len: u64,
+        fingerprint: Option<&Fingerprint>,
+    ) -> Result<(i32, bool), ZinharoError> {
+        if let Some(fingerprint) = fingerprint {
+            match ZinharoHash::from_fingerprint(self.access, fingerprint) {
+                Ok(Some(hash)) => return Ok((hash.id, true)),
+                // servers without fingerprint lookups turn the query away
+                Ok(None) | Err(ZinharoError::UnknownStatusCode(_)) => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        let name = path.display().to_string();
+        let mut logged = 0;
+
+        let progress = move |sent: u64, total: u64| {
+            let percent = sent * 100 / total.max(1);
+
+            if total >= LOG_PROGRESS_SIZE && percent >= logged + 25 {
+                logged = percent - percent % 25;
+                eprintln!("Uploading `{}`, {}% sent..", name, logged);
+            }
+        };
+
+        let cap = File::open(path)?;
+
+        if cap.metadata()?.len() != len {
+            let error = io::Error::other("changed since it was checked");
+            return Err(ZinharoError::IOError(error));
+        }
+
+        ZinharoHash::upload_reader(self.access, cap, len, &self.options, progress)
+            .map(|hash| (hash.id, false))
+    }
+
+    /// Uploads a prepared capture, waiting out ratelimits and retrying other
+    /// failures, then records it in the manifest and ledger
+    fn send(&self, prepared: Prepared) -> Uploaded {
+        let Prepared {
+            path,
+            len,
+            sha256,
+            fingerprint,
+            ..
+        } = prepared;
+        let mut attempts = 0;
+
+        let (hash_id, duplicate) = loop {
+            if !self.coordinator.wait_turn() {
+                return Uploaded::failed(
+                    path,
+                    Some(sha256),
+                    String::from("shut down before uploading"),
+                );
+            }
+
+            attempts += 1;
+
+            match self.find_or_upload(&path, len, fingerprint.as_ref()) {
+                Ok(found) => break found,
+                Err(ZinharoError::CaptureTooLarge(size)) => {
+                    let error = format!("the api refused it as too large at {} bytes", size);
+                    return Uploaded::rejected(path, Some(sha256), error);
+                }
+                Err(ZinharoError::Ratelimited) => {
+                    let waiting = self.coordinator.ratelimited();
+                    eprintln!(
+                        "Ratelimited whilst uploading `{}`, retrying in {} seconds..",
+                        path.display(),
+                        waiting.as_secs()
+                    );
+                }
+                Err(e) if attempts < self.max_attempts => {
+                    let waiting = self.coordinator.errored();
+                    eprintln!(
+                        "Could not upload `{}`, retrying in {} seconds..\n{:?}",
+                        path.display(),
+                        waiting.as_secs(),
+                        e
+                    );
+                }
+                Err(e) => {
+                    return Uploaded::failed(
+                        path,
+                        Some(sha256),
+                        format!("could not upload: {:?}", e),
+                    )
+                }
+            }
+        };
+
+        if let Err(e) = self
+            .manifest
+            .lock()
+            .unwrap()
+            .record(&sha256, &path, hash_id)
+        {
+            eprintln!("Could not save upload manifest: '{}'", e);
+        }
+
+        if let Some(fingerprint) = &fingerprint {
+            let recorded = self
+                .ledger
+                .lock()
+                .unwrap()
+                .insert(Fingerprint::clone(fingerprint), hash_id);
+
+            if let Err(e) = recorded {
+                eprintln!("Could not save fingerprint ledger: {:?}", e);
+            }
+        }
+
+        Uploaded {
+            path,
+            sha256: Some(sha256),
+            fingerprint: fingerprint.map(|fingerprint| fingerprint.to_string()),
+            hash_id: Some(hash_id),
+            duplicate,
+            error: None,
+            rejected: false,
+        }
+    }
+}
+
+/// Gives the hex-encoded SHA-256 of a capture file read through, alongside
+/// the format its header says it is in
+fn checksum(file: &mut File) -> io::Result<(String, Option<CaptureFormat>)> {
+    let mut header = Vec::new();
+    let mut hasher = Sha256::new();
+
+    file.take(28).read_to_end(&mut header)?;
+    hasher.update(&header);
+    io::copy(file, &mut hasher)?;
+
+    Ok((hex::encode(hasher.finalize()), capture_format(&header)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coordinator::Backoff;
+    use crate::testdir::TestDir;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    /// Starts a mock api answering each request in turn with the given status
+    /// and json body, giving its api prefix
+    fn mock_api(responses: Vec<(u16, &'static str)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let prefix = format!("http://{}/", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            for (status, body) in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut content_length = 0;
+
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+
+                    if header.trim().is_empty() {
+                        break;
+                    }
+
+                    let lower = header.to_ascii_lowercase();
+
+                    if let Some(len) = lower.strip_prefix("content-length:") {
+                        content_length = len.trim().parse().unwrap();
+                    }
+                }
+
+                let mut request_body = vec![0; content_length];
+                reader.read_exact(&mut request_body).unwrap();
+
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {} OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+
+        prefix
+    }
+
+    #[test]
+    fn watcher_waits_for_files_to_settle() {
+        let dir = TestDir::new("watch");
+
+        let done = dir.join("done.cap");
+        let growing = dir.join("growing.pcapng");
+        fs::write(&done, b"\xd4\xc3\xb2\xa1").unwrap();
+        fs::write(&growing, b"\x0a\x0d").unwrap();
+        fs::write(dir.join(".partial.cap"), b"\xd4\xc3").unwrap();
+        fs::write(dir.join("notes.txt"), b"rig 2").unwrap();
+
+        let settle = Duration::from_secs(10);
+        let handled = vec![dir.join("old.cap")];
+        fs::write(&handled[0], b"\xd4\xc3\xb2\xa1").unwrap();
+
+        let mut watcher = Watcher::new(dir.path(), settle, &handled);
+        let started = Instant::now();
+
+        assert!(watcher.poll_at(started).unwrap().is_empty());
+
+        fs::write(&growing, b"\x0a\x0d\x0d\x0a").unwrap();
+        let settled = started + settle;
+
+        assert_eq!(
+            watcher.poll_at(settled).unwrap(),
+            vec![PathBuf::clone(&done)]
+        );
+        assert!(watcher.poll_at(settled + settle / 2).unwrap().is_empty());
+        assert_eq!(
+            watcher.poll_at(settled + settle).unwrap(),
+            vec![PathBuf::clone(&growing)]
+        );
+        assert!(watcher.poll_at(settled + settle * 2).unwrap().is_empty());
+
+        // only files the manifest recorded are left alone for good
+        watcher.finished(&done, true);
+        watcher.finished(&growing, false);
+
+        let retried = settled + settle * 3;
+        assert!(watcher.poll_at(retried).unwrap().is_empty());
+        assert_eq!(watcher.poll_at(retried + settle).unwrap(), vec![done]);
+        assert!(watcher.poll_at(retried + settle * 2).unwrap().is_empty());
+
+        let mut pcap = vec![0xd4, 0xc3, 0xb2, 0xa1];
+        pcap.resize(24, 0);
+        assert_eq!(capture_format(&pcap), Some(CaptureFormat::Pcap));
+        assert_eq!(capture_format(&pcap[..20]), None);
+        assert_eq!(capture_format(b"PK\x03\x04 not a capture at all"), None);
+    }
+
+    #[test]
+    fn watch_retries_failed_uploads() {
+        let dir = TestDir::new("watch-retry");
+        let caps = dir.join("caps");
+        fs::create_dir_all(&caps).unwrap();
+
+        let mut pcap = vec![0xd4, 0xc3, 0xb2, 0xa1];
+        pcap.resize(24, 0);
+        fs::write(caps.join("rig.cap"), &pcap).unwrap();
+        fs::write(caps.join("notes.cap"), b"not a capture at all").unwrap();
+
+        let hash_json = r#"{"status": "success", "body": {"hash": {"id": 77, "cap": "", "created": "2020-06-01T12:00:00+00:00", "jobs": [], "reports": []}}}"#;
+        let access = ZinharoAccess {
+            token: String::from("mocktoken"),
+            client: reqwest::blocking::Client::new(),
+            api_prefix: mock_api(vec![(500, "{}"), (200, hash_json)]),
+        };
+        let coordinator = Coordinator::new(Backoff::default());
+        let options = UploadOptions {
+            streaming: Some(false),
+            ..Default::default()
+        };
+        let uploader = Uploader::new(
+            &access,
+            &coordinator,
+            Manifest::load(&dir.join(MANIFEST_FILE)).unwrap(),
+            Ledger::open(&dir.join(LEDGER_FILE)).unwrap(),
+            options,
+            1,
+        );
+
+        let outcomes = Mutex::new(Vec::new());
+        let done = |uploaded: Uploaded| {
+            let mut outcomes = outcomes.lock().unwrap();
+            let name = uploaded.path.file_name().unwrap().to_string_lossy();
+
+            outcomes.push((name.into_owned(), uploaded.hash_id, uploaded.rejected));
+
+            // stopped early if retries are turned away, rather than hanging
+            if uploaded.hash_id.is_some() || outcomes.len() > 6 {
+                coordinator.shutdown(false);
+            }
+        };
+
+        let watcher = Watcher::new(&caps, Duration::from_secs(0), &Vec::new());
+        uploader.watch(watcher, Duration::from_millis(10), 1, &done);
+
+        let outcomes = outcomes.into_inner().unwrap();
+        assert_eq!(
+            outcomes,
+            vec![
+                (String::from("notes.cap"), None, true),
+                (String::from("rig.cap"), None, false),
+                (String::from("rig.cap"), Some(77), false),
+            ]
+        );
+        assert_eq!(uploader.handled(), vec![caps.join("rig.cap")]);
+    }
+}
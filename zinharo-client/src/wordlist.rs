@@ -0,0 +1,510 @@
+//! Wordlist fetching and streaming decompression. Wordlists are resolved
+//! through the server's manifest and compression is sniffed from magic bytes,
+//! so a mirror may serve gzip, xz, zstd or bzip2 without the decompressed list
+//! ever being held in memory
+
+use crate::cracker::Candidates;
+use crate::preprocess::{checksum, preprocess, PreprocessOptions};
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, prelude::*, BufReader, BufWriter, Cursor};
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use xz2::read::XzDecoder;
+use zinharo_rs::{
+    ZinharoAccess, ZinharoManifest, ZinharoQueuedJob, ZinharoRange, ZinharoSlice, ZinharoWordlist,
+};
+
+/// Mirror of the standardised wordlist, served bzip2-compressed
+pub const DEFAULT_WORDLIST_URL: &str = "http://downloads.skullsecurity.org/passwords/cain.txt.bz2";
+
+/// Directory raw downloaded wordlists are kept in, one file per identifier
+pub const DEFAULT_WORDLIST_DIR: &str = "./wordlists";
+
+/// Directory preprocessed wordlists are cached in
+pub const DEFAULT_CACHE_DIR: &str = "./wordlist-cache";
+
+/// Length of the longest magic number sniffed for, which is xz's 6 bytes
+const MAGIC_LEN: u64 = 6;
+
+/// Compression formats a wordlist may be stored or served in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Xz,
+    Zstd,
+    Bzip2,
+
+    /// Not compressed (or an unrecognised format), passed through as-is
+    Plain,
+}
+
+impl Compression {
+    /// Detects compression from the leading magic bytes of a stream
+    pub fn detect(magic: &[u8]) -> Self {
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if magic.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else {
+            Compression::Plain
+        }
+    }
+}
+
+/// A wordlist on disk, ready to be handed to the cracker
+#[derive(Debug, Clone)]
+pub enum Wordlist {
+    /// Plaintext list that aircrack-ng can read directly
+    Plain(PathBuf),
+
+    /// Compressed list which gets decoded on the fly into the cracker's stdin
+    Compressed(PathBuf),
+}
+
+/// Sniffs the magic bytes of `reader` and wraps it in the matching streaming
+/// decoder, returning the detected [Compression] alongside the decoded stream
+pub fn decode<R: Read + Send + 'static>(
+    mut reader: R,
+) -> io::Result<(Compression, Box<dyn BufRead + Send>)> {
+    let mut magic = Vec::new();
+    (&mut reader).take(MAGIC_LEN).read_to_end(&mut magic)?;
+
+    let compression = Compression::detect(&magic);
+    let stream = Cursor::new(magic).chain(reader);
+
+    let decoded: Box<dyn Read + Send> = match compression {
+        Compression::Gzip => Box::new(MultiGzDecoder::new(stream)),
+        Compression::Xz => Box::new(XzDecoder::new_multi_decoder(stream)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(stream)?),
+        Compression::Bzip2 => Box::new(MultiBzDecoder::new(stream)),
+        Compression::Plain => Box::new(stream),
+    };
+
+    Ok((compression, Box::new(BufReader::new(decoded))))
+}
+
+/// Opens a wordlist file of any supported compression as a decoded stream
+pub fn open_decoded(path: &Path) -> io::Result<Box<dyn BufRead + Send>> {
+    Ok(decode(File::open(path)?)?.1)
+}
+
+/// Streams `reader` into `path` as-is. Writes to a `.part` sibling first so an
+/// interrupted download is never mistaken for a complete wordlist
+pub fn save_stream<R: Read>(reader: R, path: &Path) -> io::Result<u64> {
+    let part_path = path.with_extension("part");
+    let mut part_file = BufWriter::new(File::create(&part_path)?);
+    let written = io::copy(&mut BufReader::new(reader), &mut part_file)?;
+
+    part_file.flush()?;
+    drop(part_file);
+    fs::rename(part_path, path)?;
+
+    Ok(written)
+}
+
+/// Errors whilst resolving or downloading a wordlist for a job
+#[derive(Debug)]
+pub enum WordlistError {
+    /// Neither the manifest nor the built-in fallback know this identifier
+    Unknown(String),
+
+    /// The downloaded wordlist did not match the checksum in the manifest
+    ChecksumMismatch(String),
+
+    /// Encapsulates a [reqwest::Error] from downloading the wordlist
+    ReqwestError(reqwest::Error),
+
+    /// Encapsulates an [std::io]-based error from saving or preprocessing
+    IOError(io::Error),
+}
+
+impl fmt::Display for WordlistError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WordlistError::Unknown(id) => write!(f, "unknown wordlist `{}`", id),
+            WordlistError::ChecksumMismatch(id) => {
+                write!(f, "wordlist `{}` did not match its checksum", id)
+            }
+            WordlistError::ReqwestError(e) => write!(f, "could not download wordlist: {}", e),
+            WordlistError::IOError(e) => write!(f, "could not save wordlist: {}", e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for WordlistError {
+    fn from(error: reqwest::Error) -> Self {
+        WordlistError::ReqwestError(error)
+    }
+}
+
+impl From<io::Error> for WordlistError {
+    fn from(error: io::Error) -> Self {
+        WordlistError::IOError(error)
+    }
+}
+
+/// Where wordlists are downloaded from and kept
+#[derive(Debug, Clone)]
+pub struct WordlistLocations {
+    /// Directory raw downloaded wordlists are kept in
+    pub dir: PathBuf,
+
+    /// Directory preprocessed wordlists are cached in
+    pub cache_dir: PathBuf,
+
+    /// Url of the wordlist used when the server has no manifest
+    pub fallback_url: String,
+}
+
+impl Default for WordlistLocations {
+    fn default() -> Self {
+        WordlistLocations {
+            dir: PathBuf::from(DEFAULT_WORDLIST_DIR),
+            cache_dir: PathBuf::from(DEFAULT_CACHE_DIR),
+            fallback_url: String::from(DEFAULT_WORDLIST_URL),
+        }
+    }
+}
+
+/// Built-in wordlist used when the server has no manifest and a job names
+/// none, downloaded from `url`
+fn fallback_wordlist(url: &str) -> ZinharoWordlist {
+    ZinharoWordlist {
+        id: String::from("cain"),
+        url: String::from(url),
+        sha256: None,
+    }
+}
+
+/// Turns a wordlist identifier into something safe to use as a filename
+fn file_name(id: &str) -> String {
+    id.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Resolves server-provided wordlist identifiers into preprocessed local
+/// wordlists, downloading each one the first time a job asks for it. Shared
+/// between workers, it is only locked to look entries up and claim them, so
+/// one worker's hours of downloading never hold up the others
+pub struct WordlistStore {
+    /// Options every downloaded wordlist is preprocessed with
+    options: PreprocessOptions,
+
+    /// Where wordlists are downloaded from and kept
+    locations: WordlistLocations,
+
+    /// What is known about wordlists so far
+    state: Mutex<StoreState>,
+
+    /// Notified whenever a claim on an identifier is let go
+    released: Condvar,
+}
+
+/// Part of a [WordlistStore] guarded by its lock
+struct StoreState {
+    /// Manifest from the server, [Option::None] if it doesn't provide one
+    manifest: Option<ZinharoManifest>,
+
+    /// Wordlists already downloaded and preprocessed, keyed by identifier
+    prepared: HashMap<String, Wordlist>,
+
+    /// Identifiers being downloaded or preprocessed by a worker right now
+    claimed: HashSet<String>,
+}
+
+/// A worker's claim on downloading and preprocessing a wordlist, let go
+/// when dropped
+struct Claim<'a> {
+    store: &'a WordlistStore,
+    id: String,
+}
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        self.store.state.lock().unwrap().claimed.remove(&self.id);
+        self.store.released.notify_all();
+    }
+}
+
+impl WordlistStore {
+    /// Creates a new store, fetching the server's manifest if it offers one
+    pub fn new(
+        access: &ZinharoAccess,
+        options: PreprocessOptions,
+        locations: WordlistLocations,
+    ) -> Self {
+        let manifest = match ZinharoManifest::fetch(access) {
+            Ok(x) => Some(x),
+            Err(_) => {
+                eprintln!("Could not fetch wordlist manifest, using built-in wordlist..");
+                None
+            }
+        };
+
+        WordlistStore {
+            options,
+            locations,
+            state: Mutex::new(StoreState {
+                manifest,
+                prepared: HashMap::new(),
+                claimed: HashSet::new(),
+            }),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Finds the manifest entry for `id`, or the default if [Option::None]
+    fn lookup(&self, id: Option<&str>) -> Option<ZinharoWordlist> {
+        let fallback = fallback_wordlist(&self.locations.fallback_url);
+        let resolved = self
+            .state
+            .lock()
+            .unwrap()
+            .manifest
+            .as_ref()
+            .and_then(|manifest| manifest.resolve(id).cloned());
+
+        match (resolved, id) {
+            (Some(entry), _) => Some(entry),
+            (None, None) => Some(fallback),
+            (None, Some(id)) if id == fallback.id => Some(fallback),
+            (None, Some(_)) => None,
+        }
+    }
+
+    /// Finds the manifest entry for `id` like [WordlistStore::lookup], but
+    /// refetches the manifest once if it's unknown in case the server has
+    /// published a new wordlist since
+    fn entry(
+        &self,
+        access: &ZinharoAccess,
+        id: Option<&str>,
+    ) -> Result<ZinharoWordlist, WordlistError> {
+        if let Some(entry) = self.lookup(id) {
+            return Ok(entry);
+        }
+
+        let manifest = ZinharoManifest::fetch(access).ok();
+        self.state.lock().unwrap().manifest = manifest;
+
+        match self.lookup(id) {
+            Some(entry) => Ok(entry),
+            None => Err(WordlistError::Unknown(id.unwrap_or_default().into())),
+        }
+    }
+
+    /// Claims the wordlist `id` for this worker, waiting for any other
+    /// worker to finish with it first. Gives the wordlist instead if it has
+    /// been prepared in the meantime
+    fn claim(&self, id: &str) -> Result<Claim<'_>, Wordlist> {
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            if let Some(wordlist) = state.prepared.get(id) {
+                return Err(Wordlist::clone(wordlist));
+            }
+
+            if state.claimed.insert(String::from(id)) {
+                return Ok(Claim {
+                    store: self,
+                    id: String::from(id),
+                });
+            }
+
+            state = self.released.wait(state).unwrap();
+        }
+    }
+
+    /// Gets a ready-to-crack wordlist for the given identifier, or the default
+    /// one if [Option::None]
+    pub fn get(&self, access: &ZinharoAccess, id: Option<&str>) -> Result<Wordlist, WordlistError> {
+        let entry = self.entry(access, id)?;
+
+        let _claim = match self.claim(&entry.id) {
+            Ok(x) => x,
+            Err(wordlist) => return Ok(wordlist),
+        };
+
+        let raw_path = download(&self.locations.dir, access, &entry)?;
+
+        println!(
+            "Preprocessing wordlist `{}`, this may take a while..",
+            entry.id
+        );
+
+        let processed = preprocess(&raw_path, &self.locations.cache_dir, &self.options)?;
+        let wordlist = if self.options.compress {
+            Wordlist::Compressed(processed)
+        } else {
+            Wordlist::Plain(processed)
+        };
+
+        self.state
+            .lock()
+            .unwrap()
+            .prepared
+            .insert(entry.id, Wordlist::clone(&wordlist));
+        Ok(wordlist)
+    }
+
+    /// Works out the candidates a job should be cracked with. Sliced jobs
+    /// index into the raw wordlist as distributed, so they skip preprocessing
+    pub fn candidates(
+        &self,
+        access: &ZinharoAccess,
+        job: &ZinharoQueuedJob,
+    ) -> Result<Candidates, WordlistError> {
+        let slice = match &job.slice {
+            Some(x) => ZinharoSlice::clone(x),
+            None => {
+                return Ok(Candidates::Wordlist(
+                    self.get(access, job.wordlist.as_deref())?,
+                ))
+            }
+        };
+
+        let wordlist = match slice.range {
+            ZinharoRange::Mask { .. } => None,
+            _ => {
+                let entry = self.entry(access, job.wordlist.as_deref())?;
+
+                // held so two workers never download the same file at once
+                let _claim = self.claim(&entry.id);
+                Some(download(&self.locations.dir, access, &entry)?)
+            }
+        };
+
+        Ok(Candidates::Slice {
+            slice,
+            wordlist,
+            options: self.options.clone(),
+        })
+    }
+}
+
+/// Downloads the raw (still compressed) wordlist for `entry` into
+/// `dir` unless a copy matching the manifest checksum already exists
+fn download(
+    dir: &Path,
+    access: &ZinharoAccess,
+    entry: &ZinharoWordlist,
+) -> Result<PathBuf, WordlistError> {
+    fs::create_dir_all(dir)?;
+
+    let raw_path = dir.join(file_name(&entry.id));
+    let matches_manifest = |path: &Path| -> io::Result<bool> {
+        match &entry.sha256 {
+            Some(expected) => Ok(checksum(path)?.eq_ignore_ascii_case(expected)),
+            None => Ok(true),
+        }
+    };
+
+    if raw_path.exists() && matches_manifest(&raw_path)? {
+        return Ok(raw_path);
+    }
+
+    println!("Downloading wordlist `{}`..", entry.id);
+
+    let resp = access.client.get(&entry.url).send()?.error_for_status()?;
+    save_stream(resp, &raw_path)?;
+
+    if !matches_manifest(&raw_path)? {
+        fs::remove_file(&raw_path).ok();
+        return Err(WordlistError::ChecksumMismatch(String::clone(&entry.id)));
+    }
+
+    Ok(raw_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sample wordlist used for round-trips
+    const WORDS: &[u8] = b"password\nletmein123\nhunter22\n";
+
+    /// Decodes a compressed copy of [WORDS] and ensures it comes back intact
+    fn round_trip(compressed: Vec<u8>, expected: Compression) {
+        let (compression, mut decoded) = decode(Cursor::new(compressed)).unwrap();
+        let mut contents = Vec::new();
+        decoded.read_to_end(&mut contents).unwrap();
+
+        assert_eq!(compression, expected);
+        assert_eq!(contents, WORDS);
+    }
+
+    #[test]
+    fn gzip_round_trip() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(WORDS).unwrap();
+        round_trip(encoder.finish().unwrap(), Compression::Gzip);
+    }
+
+    #[test]
+    fn xz_round_trip() {
+        let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+        encoder.write_all(WORDS).unwrap();
+        round_trip(encoder.finish().unwrap(), Compression::Xz);
+    }
+
+    #[test]
+    fn zstd_round_trip() {
+        round_trip(zstd::encode_all(WORDS, 3).unwrap(), Compression::Zstd);
+    }
+
+    #[test]
+    fn bzip2_round_trip() {
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(WORDS).unwrap();
+        round_trip(encoder.finish().unwrap(), Compression::Bzip2);
+    }
+
+    #[test]
+    fn plain_passthrough() {
+        round_trip(WORDS.to_vec(), Compression::Plain);
+    }
+
+    #[test]
+    fn claims_wait_for_each_other() {
+        let store = WordlistStore {
+            options: PreprocessOptions::default(),
+            locations: WordlistLocations::default(),
+            state: Mutex::new(StoreState {
+                manifest: None,
+                prepared: HashMap::new(),
+                claimed: HashSet::new(),
+            }),
+            released: Condvar::new(),
+        };
+        let claim = store.claim("rockyou").unwrap();
+
+        std::thread::scope(|s| {
+            let waiting = s.spawn(|| store.claim("rockyou").map(|_| ()));
+
+            // other wordlists can still be claimed meanwhile
+            assert!(store.claim("cain").is_ok());
+
+            store
+                .state
+                .lock()
+                .unwrap()
+                .prepared
+                .insert(String::from("rockyou"), Wordlist::Plain(PathBuf::from("x")));
+            drop(claim);
+
+            assert!(matches!(waiting.join().unwrap(), Err(Wordlist::Plain(_))));
+        });
+    }
+}
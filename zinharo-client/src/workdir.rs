@@ -0,0 +1,131 @@
+//! Per-job working directories, so captures and cracked keys are only ever
+//! written somewhere private to one job and are removed once it is done with,
+//! whether it succeeded, failed or panicked
+
+use std::env;
+use std::fs::{self, DirBuilder};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Prefix of every job directory inside the work root
+const JOB_DIR_PREFIX: &str = "job-";
+
+/// Gets the root job directories are made in unless configured otherwise, a
+/// `zinharo` directory in the system's temp directory
+pub fn default_work_root() -> PathBuf {
+    env::temp_dir().join("zinharo")
+}
+
+/// Creates `path` (and any missing parents) readable only by this user
+fn create_private_dir(path: &Path) -> io::Result<()> {
+    DirBuilder::new().recursive(true).mode(0o700).create(path)?;
+
+    // the directory may already have existed with looser permissions
+    fs::set_permissions(path, fs::Permissions::from_mode(0o700))
+}
+
+/// Removes job directories left behind by client processes that are no
+/// longer running, for example after being killed outright
+pub fn sweep(root: &Path) {
+    let entries = match fs::read_dir(root) {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let owner = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(JOB_DIR_PREFIX))
+            .and_then(|rest| rest.split('-').next())
+            .and_then(|pid| pid.parse::<u32>().ok());
+
+        match owner {
+            Some(pid)
+                if pid != process::id() && !Path::new("/proc").join(pid.to_string()).exists() =>
+            {
+                fs::remove_dir_all(entry.path()).ok();
+            }
+            _ => (),
+        }
+    }
+}
+
+/// A private directory for a single job, removed with everything inside it
+/// when dropped
+#[derive(Debug)]
+pub struct JobDir {
+    path: PathBuf,
+}
+
+impl JobDir {
+    /// Creates a fresh directory for `job_id` inside `root`
+    pub fn create(root: &Path, job_id: i32) -> io::Result<Self> {
+        create_private_dir(root)?;
+
+        let path = root.join(format!("{}{}-{}", JOB_DIR_PREFIX, process::id(), job_id));
+
+        // leftovers of an earlier attempt at the same job are of no use
+        if path.exists() {
+            fs::remove_dir_all(&path)?;
+        }
+
+        DirBuilder::new().mode(0o700).create(&path)?;
+
+        Ok(JobDir { path })
+    }
+
+    /// Gets the path of a file called `name` inside this directory
+    pub fn file(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+}
+
+impl Drop for JobDir {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.path) {
+            if e.kind() != io::ErrorKind::NotFound {
+                eprintln!(
+                    "Could not clean up job directory '{}': '{}'",
+                    self.path.display(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+    use std::panic;
+
+    #[test]
+    fn job_dirs_are_private_and_cleaned_up() {
+        let root = TestDir::new("workdir");
+        let job_path;
+
+        {
+            let job_dir = JobDir::create(root.path(), 4).unwrap();
+            job_path = job_dir.path.clone();
+            fs::write(job_dir.file("out.txt"), b"hunter22").unwrap();
+
+            let mode = fs::metadata(&job_path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+
+        assert!(!job_path.exists());
+
+        let panicked = panic::catch_unwind(|| {
+            let job_dir = JobDir::create(root.path(), 5).unwrap();
+            fs::write(job_dir.file("inprogress.cap"), b"cap").unwrap();
+            panic!("cracker blew up");
+        });
+
+        assert!(panicked.is_err());
+        assert_eq!(fs::read_dir(root.path()).unwrap().count(), 0);
+    }
+}
@@ -29,6 +29,11 @@ mod tests {
     use super::*;
     use rand::distributions::Alphanumeric;
     use rand::{thread_rng, Rng};
+    use std::io::{prelude::*, BufReader};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::{self, Receiver};
+    use std::thread;
 
     /// Debug admin username
     const USERNAME: &str = "coolman";
@@ -36,6 +41,495 @@ mod tests {
     /// Debug admin password
     const PASSWORD: &str = "englandismycity";
 
+    /// A job as the api hands it out, for job 12 of hash 77
+    const MOCK_JOB: &str = r#"{"status": "success", "body": {"queued": {"cap": "BAUrS4Y=", "id": 12, "hash_id": 77, "essid": "linksys", "created": "2020-06-01T12:00:00+00:00", "wordlist": null, "slice": null, "lease_expires": null}}}"#;
+
+    /// Starts a mock api answering each request in turn with the given status
+    /// and json body, giving its api prefix and a receiver of every request
+    /// line and body it was sent
+    fn mock_api(responses: Vec<(u16, &'static str)>) -> (String, Receiver<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let prefix = format!("http://{}/", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for (status, body) in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                let mut content_length = 0;
+
+                reader.read_line(&mut request_line).unwrap();
+
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+
+                    if header.trim().is_empty() {
+                        break;
+                    }
+
+                    let lower = header.to_ascii_lowercase();
+
+                    if let Some(len) = lower.strip_prefix("content-length:") {
+                        content_length = len.trim().parse().unwrap();
+                    }
+                }
+
+                let mut request_body = vec![0; content_length];
+                reader.read_exact(&mut request_body).unwrap();
+
+                tx.send((
+                    request_line.trim().to_string(),
+                    String::from_utf8(request_body).unwrap(),
+                ))
+                .unwrap();
+
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {} OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+
+        (prefix, rx)
+    }
+
+    /// Creates access to a mock api without logging in
+    fn mock_access(api_prefix: String) -> ZinharoAccess {
+        ZinharoAccess {
+            token: String::from("mocktoken"),
+            client: reqwest::blocking::Client::new(),
+            api_prefix,
+        }
+    }
+
+    /// A path unique to the test using it, removed again once dropped even if
+    /// the test fails
+    struct TestFile(std::path::PathBuf);
+
+    impl TestFile {
+        /// Gives a path named after `name` which nothing else is using
+        fn new(name: &str) -> Self {
+            static MADE: AtomicUsize = AtomicUsize::new(0);
+
+            let path = std::env::temp_dir().join(format!(
+                "zinharo-{}-{}-{}",
+                name,
+                std::process::id(),
+                MADE.fetch_add(1, Ordering::SeqCst)
+            ));
+            std::fs::remove_file(&path).ok();
+
+            TestFile(path)
+        }
+    }
+
+    impl Drop for TestFile {
+        fn drop(&mut self) {
+            std::fs::remove_file(&self.0).ok();
+        }
+    }
+
+    /// Makes sure fetched jobs know which hash they crack
+    #[test]
+    fn mock_fetch_job_hash() {
+        let (prefix, requests) = mock_api(vec![(200, MOCK_JOB)]);
+        let job = ZinharoQueuedJob::new(&mock_access(prefix)).unwrap();
+
+        assert_eq!(requests.recv().unwrap().0, "GET /job/ HTTP/1.1");
+        assert_eq!(job.id, 12);
+        assert_eq!(job.hash_id, 77);
+        assert_eq!(job.essid.as_deref(), Some("linksys"));
+        assert_eq!(job.cap, vec![4, 5, 43, 75, 134]);
+    }
+
+    /// Makes sure malformed lease timestamps give an error rather than panic
+    #[test]
+    fn mock_malformed_lease() {
+        let (prefix, _requests) = mock_api(vec![
+            (200, MOCK_JOB),
+            (
+                200,
+                r#"{"status": "success", "body": {"lease_expires": "soon"}}"#,
+            ),
+        ]);
+        let access = mock_access(prefix);
+        let job = ZinharoQueuedJob::new(&access).unwrap();
+
+        match job.heartbeat(&access, &ZinharoProgress::default()) {
+            Err(ZinharoError::MalformedResponse(_)) => (),
+            other => panic!("expected a malformed response, got {:?}", other),
+        }
+    }
+
+    /// Makes sure versions the api reports are parsed without panicking
+    #[test]
+    fn version_parsing() {
+        let version = utils::Version::from_str("2.4.23").unwrap();
+        assert_eq!((version.major, version.minor, version.patch), (2, 4, 23));
+
+        for malformed in &["2.4", "2.4.23.1", "2.x.23", ""] {
+            match utils::Version::from_str(malformed) {
+                Err(ZinharoError::MalformedResponse(_)) => (),
+                _ => panic!("`{}` should not parse as a version", malformed),
+            }
+        }
+    }
+
+    /// Makes sure reports are filed against the job's hash, not the job
+    #[test]
+    fn mock_report_targets_hash() {
+        let (prefix, requests) = mock_api(vec![
+            (200, MOCK_JOB),
+            (200, r#"{"status": "success", "body": {}}"#),
+        ]);
+        let access = mock_access(prefix);
+        let job = ZinharoQueuedJob::new(&access).unwrap();
+
+        job.report(&access, &ReportReason::NoHandshake, None)
+            .unwrap();
+
+        requests.recv().unwrap();
+        let (request_line, body) = requests.recv().unwrap();
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+
+        assert_eq!(request_line, "POST /report/ HTTP/1.1");
+        assert_eq!(body["hash_id"], 77);
+        assert_eq!(body["reason"], "no_handshake");
+    }
+
+    /// Fetches a hash by ID, with the cap and reports filed against it
+    #[test]
+    fn mock_hash_from_id() {
+        let (prefix, requests) = mock_api(vec![
+            (
+                200,
+                r#"{"status": "success", "body": {"hash": {"id": 77, "cap": "BAUrS4Y=", "created": "2020-06-01T12:00:00+00:00", "jobs": [], "reports": [{"id": 3, "info": "No WPA handshake in capture", "reason": "no_handshake", "client_id": 9, "created": "2020-06-02T12:00:00+00:00"}]}}}"#,
+            ),
+            (404, r#"{"status": "error", "body": {}}"#),
+            (
+                200,
+                r#"{"status": "success", "body": {"hash": {"id": 79, "cap": "not base64!", "created": "2020-06-01T12:00:00+00:00", "jobs": [], "reports": []}}}"#,
+            ),
+        ]);
+        let access = mock_access(prefix);
+        let hash = ZinharoHash::from_id(&access, 77).unwrap();
+
+        assert_eq!(requests.recv().unwrap().0, "GET /hash/?id=77 HTTP/1.1");
+        assert_eq!(hash.cap, vec![4, 5, 43, 75, 134]);
+        assert_eq!(hash.reports[0].hash_id, 77);
+        assert_eq!(hash.reports[0].reason, Some(ReportReason::NoHandshake));
+        assert!(matches!(
+            ZinharoHash::from_id(&access, 78),
+            Err(ZinharoError::HashNotFound)
+        ));
+        assert!(matches!(
+            ZinharoHash::from_id(&access, 79),
+            Err(ZinharoError::MalformedResponse(_))
+        ));
+    }
+
+    /// Access point used in mock captures
+    const MOCK_AP: [u8; 6] = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55];
+
+    /// Station used in mock captures
+    const MOCK_STATION: [u8; 6] = [0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb];
+
+    /// Builds an 802.11 data frame from the access point to the station
+    /// carrying an EAPOL-Key frame, with a trailing fcs
+    fn mock_eapol(nonce: u8) -> Vec<u8> {
+        let mut frame = vec![0x08, 0x02, 0, 0];
+        frame.extend(&MOCK_STATION);
+        frame.extend(&MOCK_AP);
+        frame.extend(&MOCK_AP);
+        frame.extend(&[0, 0, 0xaa, 0xaa, 0x03, 0, 0, 0, 0x88, 0x8e]);
+        frame.extend(&[2, 3, 0, 5]);
+        frame.extend(&[nonce; 5]);
+        frame.extend(&[0xde, 0xad, 0xbe, 0xef]);
+        frame
+    }
+
+    /// Builds a beacon from the access point announcing `essid`
+    fn mock_beacon(essid: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x80, 0, 0, 0];
+        frame.extend(&[0xff; 6]);
+        frame.extend(&MOCK_AP);
+        frame.extend(&MOCK_AP);
+        frame.extend(&[0; 14]);
+        frame.extend(&[0, essid.len() as u8]);
+        frame.extend(essid);
+        frame
+    }
+
+    /// Builds a little-endian pcap of radiotap frames
+    fn mock_pcap(frames: &[Vec<u8>]) -> Vec<u8> {
+        let mut cap = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0];
+        cap.extend(&[0; 8]);
+        cap.extend(&65535u32.to_le_bytes());
+        cap.extend(&127u32.to_le_bytes());
+
+        for frame in frames {
+            let len = (frame.len() + 8) as u32;
+
+            cap.extend(&[0; 8]);
+            cap.extend(&len.to_le_bytes());
+            cap.extend(&len.to_le_bytes());
+            cap.extend(&[0, 0, 8, 0, 0, 0, 0, 0]);
+            cap.extend(frame);
+        }
+
+        cap
+    }
+
+    /// Builds a big-endian pcapng of raw 802.11 frames
+    fn mock_pcapng(frames: &[Vec<u8>]) -> Vec<u8> {
+        let mut cap = vec![0x0a, 0x0d, 0x0d, 0x0a, 0, 0, 0, 28, 0x1a, 0x2b, 0x3c, 0x4d];
+        cap.extend(&[0, 1, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        cap.extend(&[0, 0, 0, 28]);
+        cap.extend(&[
+            0, 0, 0, 1, 0, 0, 0, 20, 0, 105, 0, 0, 0, 0, 0, 0, 0, 0, 0, 20,
+        ]);
+
+        for frame in frames {
+            let padded = frame.len().div_ceil(4) * 4;
+            let len = (padded + 32) as u32;
+
+            cap.extend(&6u32.to_be_bytes());
+            cap.extend(&len.to_be_bytes());
+            cap.extend(&[0; 12]);
+            cap.extend(&(frame.len() as u32).to_be_bytes());
+            cap.extend(&(frame.len() as u32).to_be_bytes());
+            cap.extend(frame);
+            cap.extend(vec![0; padded - frame.len()]);
+            cap.extend(&len.to_be_bytes());
+        }
+
+        cap
+    }
+
+    /// Makes sure the same handshake fingerprints the same however it was
+    /// captured, and differently once any of it changes
+    #[test]
+    fn fingerprint_canonical() {
+        let noise = vec![0x08, 0x01, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8];
+        let pcap = mock_pcap(&[mock_beacon(b"linksys"), mock_eapol(1), mock_eapol(2)]);
+        let pcapng = mock_pcapng(&[
+            mock_eapol(2),
+            noise,
+            mock_eapol(1),
+            mock_eapol(2),
+            mock_beacon(b"linksys"),
+        ]);
+
+        let fingerprint = Fingerprint::from_cap(&pcap).unwrap();
+
+        assert_eq!(Fingerprint::from_cap(&pcapng).unwrap(), fingerprint);
+
+        /// Gives a stream back a byte per read, like a slow pipe
+        struct Trickle<'a>(&'a [u8]);
+
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let len = self.0.len().min(buf.len()).min(1);
+                buf[..len].copy_from_slice(&self.0[..len]);
+                self.0 = &self.0[len..];
+                Ok(len)
+            }
+        }
+
+        assert_eq!(
+            Fingerprint::from_reader(Trickle(&pcapng)).unwrap(),
+            fingerprint
+        );
+        assert_eq!(
+            Fingerprint::parse(fingerprint.as_str()),
+            Some(Fingerprint::clone(&fingerprint))
+        );
+        assert_ne!(
+            Fingerprint::from_cap(&mock_pcap(&[mock_beacon(b"linksys"), mock_eapol(1)])).unwrap(),
+            fingerprint
+        );
+        assert_ne!(
+            Fingerprint::from_cap(&mock_pcap(&[
+                mock_beacon(b"netgear"),
+                mock_eapol(1),
+                mock_eapol(2)
+            ]))
+            .unwrap(),
+            fingerprint
+        );
+        assert!(Fingerprint::from_cap(&pcap[..pcap.len() - 3]).is_ok());
+        assert!(matches!(
+            Fingerprint::from_cap(&mock_pcap(&[mock_beacon(b"linksys")])),
+            Err(ZinharoError::NoHandshake)
+        ));
+        assert!(matches!(
+            Fingerprint::from_cap(&[4, 5, 43, 75, 134]),
+            Err(ZinharoError::CaptureInvalid)
+        ));
+    }
+
+    #[test]
+    fn handshake_ids() {
+        let mut m2 = vec![0x08, 0x01, 0, 0];
+        m2.extend(&MOCK_AP);
+        m2.extend(&MOCK_STATION);
+        m2.extend(&MOCK_AP);
+        m2.extend(&[0, 0, 0xaa, 0xaa, 0x03, 0, 0, 0, 0x88, 0x8e]);
+        m2.extend(&[1, 3, 0, 95, 2, 0x01, 0x0a]);
+        m2.extend(&[0; 74]);
+        m2.extend(&[0x42; 16]);
+        m2.extend(&[0, 0]);
+
+        let ids = HandshakeId::from_cap(&mock_pcap(&[mock_beacon(b"linksys"), m2, mock_eapol(1)]))
+            .unwrap();
+
+        assert_eq!(ids.len(), 1);
+        assert_eq!((ids[0].ap, ids[0].station), (MOCK_AP, MOCK_STATION));
+        assert_eq!(ids[0].essid, b"linksys");
+
+        let hash = ids[0].to_string();
+        assert!(hash.starts_with(&format!("{}:", "42".repeat(16))));
+        assert!(hash.ends_with(":linksys"));
+        assert_eq!(HandshakeId::parse(&hash), Some(ids[0].clone()));
+        let upper = hash.replacen("42", "4A", 1);
+        assert_eq!(
+            HandshakeId::parse(&upper).map(|id| id.digest[0]),
+            Some(0x4a)
+        );
+        assert_eq!(HandshakeId::parse(&hash[1..]), None);
+        assert_eq!(HandshakeId::parse(&format!("{}:00", hash)), None);
+
+        // as hashcat writes them for `-m 22000`, names in hex if need be
+        let written = "4d4fe7aac3a2cecab195321ceb99a7d0:fc690c158264:f4747f87f9f4:hashcat-essid";
+        let id = HandshakeId::parse(written).unwrap();
+        assert_eq!(id.ap, [0xfc, 0x69, 0x0c, 0x15, 0x82, 0x64]);
+        assert_eq!(id.essid, b"hashcat-essid");
+        assert_eq!(id.to_string(), written);
+
+        let odd = HandshakeId {
+            essid: b"caf\xe9:2".to_vec(),
+            ..id
+        };
+        assert!(odd.to_string().ends_with(":$HEX[636166e93a32]"));
+        assert_eq!(HandshakeId::parse(&odd.to_string()), Some(odd));
+        assert!(matches!(
+            HandshakeId::from_cap(&mock_pcap(&[mock_beacon(b"linksys"), mock_eapol(1)])),
+            Err(ZinharoError::NoHandshake)
+        ));
+    }
+
+    /// Uploads a capture once, then finds it through the ledger instead of
+    /// uploading it again
+    #[test]
+    fn mock_hash_deduped() {
+        let hash_json = r#"{"status": "success", "body": {"hash": {"id": 77, "cap": "BAUrS4Y=", "created": "2020-06-01T12:00:00+00:00", "jobs": [], "reports": []}}}"#;
+        let (prefix, requests) = mock_api(vec![
+            (404, r#"{"status": "error", "body": {}}"#),
+            (200, hash_json),
+            (200, hash_json),
+        ]);
+        let access = mock_access(prefix);
+        let path = TestFile::new("ledger");
+        let cap = mock_pcap(&[mock_beacon(b"linksys"), mock_eapol(1)]);
+        let fingerprint = Fingerprint::from_cap(&cap).unwrap();
+
+        let mut ledger = Ledger::open(&path.0).unwrap();
+        let uploaded =
+            ZinharoHash::from_cap_deduped(&access, Vec::clone(&cap), &mut ledger).unwrap();
+
+        assert_eq!(
+            requests.recv().unwrap().0,
+            format!("GET /hash/?fingerprint={} HTTP/1.1", fingerprint)
+        );
+        assert_eq!(requests.recv().unwrap().0, "POST /hash/ HTTP/1.1");
+        assert_eq!(uploaded.id, 77);
+
+        let mut ledger = Ledger::open(&path.0).unwrap();
+        assert_eq!(ledger.get(&fingerprint), Some(77));
+
+        let existing = ZinharoHash::from_cap_deduped(&access, cap, &mut ledger).unwrap();
+
+        assert_eq!(requests.recv().unwrap().0, "GET /hash/?id=77 HTTP/1.1");
+        assert_eq!(existing.id, 77);
+    }
+
+    /// Streams a capture as multipart to apis taking it, falling back to
+    /// JSON for older ones, and refuses captures over the size limit
+    #[test]
+    fn mock_hash_upload_reader() {
+        let hash_json = r#"{"status": "success", "body": {"hash": {"id": 77, "cap": "cGNhcGRhdGE=", "created": "2020-06-01T12:00:00+00:00", "jobs": [], "reports": []}}}"#;
+        let (prefix, requests) = mock_api(vec![
+            (
+                200,
+                r#"{"status": "success", "body": {"min_version": "0.0.1", "version": "0.2.0"}}"#,
+            ),
+            (200, hash_json),
+            (
+                200,
+                r#"{"status": "success", "body": {"min_version": "0.0.1"}}"#,
+            ),
+            (200, hash_json),
+        ]);
+        let access = mock_access(prefix);
+        let options = UploadOptions {
+            compress: false,
+            ..Default::default()
+        };
+        let progress = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = std::sync::Arc::clone(&progress);
+
+        let hash = ZinharoHash::upload_reader(
+            &access,
+            std::io::Cursor::new(b"pcapdata".to_vec()),
+            8,
+            &options,
+            move |sent, total| recorded.lock().unwrap().push((sent, total)),
+        )
+        .unwrap();
+
+        assert_eq!(requests.recv().unwrap().0, "GET /min_version/ HTTP/1.1");
+        let (request_line, body) = requests.recv().unwrap();
+        assert_eq!(request_line, "POST /hash/ HTTP/1.1");
+        assert!(body.contains(r#"name="cap"; filename="capture.cap""#));
+        assert!(body.contains("pcapdata"));
+        assert_eq!(progress.lock().unwrap().last(), Some(&(8, 8)));
+        assert_eq!(hash.cap, b"pcapdata".to_vec());
+
+        ZinharoHash::upload_reader(
+            &access,
+            std::io::Cursor::new(b"pcapdata".to_vec()),
+            8,
+            &options,
+            |_, _| (),
+        )
+        .unwrap();
+
+        requests.recv().unwrap();
+        let body: serde_json::Value = serde_json::from_str(&requests.recv().unwrap().1).unwrap();
+        assert_eq!(body["cap"], "cGNhcGRhdGE=");
+
+        let limited = UploadOptions {
+            max_size: 4,
+            ..options
+        };
+        assert!(matches!(
+            ZinharoHash::upload_reader(
+                &access,
+                std::io::Cursor::new(b"pcapdata".to_vec()),
+                8,
+                &limited,
+                |_, _| ()
+            ),
+            Err(ZinharoError::CaptureTooLarge(8))
+        ));
+    }
+
     /// Attempts to login using the debug admin credentials
     #[test]
     fn admin_login() {
@@ -49,13 +543,64 @@ mod tests {
         ZinharoQueuedJob::new(&access).unwrap();
     }
 
+    /// Fetches a job then refetches it by id as if resuming after a restart
+    #[test]
+    fn fetch_leased_job() {
+        let access = ZinharoAccess::login(USERNAME, PASSWORD).unwrap();
+        let job = ZinharoQueuedJob::new(&access).unwrap();
+
+        assert_eq!(
+            ZinharoQueuedJob::fetch(&access, job.id).unwrap().cap,
+            job.cap
+        );
+    }
+
+    /// Fetches a job, heartbeats it once then releases it back to the queue
+    #[test]
+    fn heartbeat_and_release_job() {
+        let access = ZinharoAccess::login(USERNAME, PASSWORD).unwrap();
+        let job = ZinharoQueuedJob::new(&access).unwrap();
+
+        job.heartbeat(&access, &ZinharoProgress::default()).unwrap();
+        job.release(&access).unwrap();
+    }
+
+    /// Fetches the wordlist manifest and resolves the default wordlist
+    #[test]
+    fn fetch_manifest() {
+        let access = ZinharoAccess::login(USERNAME, PASSWORD).unwrap();
+        let manifest = ZinharoManifest::fetch(&access).unwrap();
+
+        manifest.resolve(None).unwrap();
+    }
+
     /// Fetches a job then submits it with dummy password
     #[test]
     fn submit_job() {
         let access = ZinharoAccess::login(USERNAME, PASSWORD).unwrap();
         let job = ZinharoQueuedJob::new(&access).unwrap();
 
-        job.submit(&access, "dummypassword").unwrap();
+        job.submit(&access, b"dummypassword").unwrap();
+    }
+
+    /// Makes sure unprintable passphrases survive a `$HEX[...]` round-trip
+    #[test]
+    fn passphrase_encoding() {
+        assert_eq!(encode_passphrase(b"hunter22"), "hunter22");
+        assert_eq!(
+            encode_passphrase(b"caf\xc3\xa9 22\n"),
+            "$HEX[636166c3a92032320a]"
+        );
+        assert_eq!(encode_passphrase(b"$HEX[41]"), "$HEX[244845585b34315d]");
+
+        for passphrase in &[&b"hunter22"[..], b"\x00\xff:pass", b"$HEX[41]"] {
+            assert_eq!(
+                decode_passphrase(&encode_passphrase(passphrase)).unwrap(),
+                passphrase.to_vec()
+            );
+        }
+
+        assert_eq!(decode_passphrase("$HEX[4g]"), None);
     }
 
     /// Gets a job then submits a report on it
@@ -66,11 +611,78 @@ mod tests {
 
         job.report(
             &access,
-            Some("This is some infomation on why this job was reported"),
+            &ReportReason::Other(String::from(
+                "This is some infomation on why this job was reported",
+            )),
+            None,
         )
         .unwrap();
     }
 
+    /// Makes sure report reasons keep the schema the server aggregates on
+    #[test]
+    fn report_reason_schema() {
+        let exhausted = ReportReason::Exhausted {
+            attack_plan: vec![String::from("rockyou")],
+        };
+
+        assert_eq!(
+            serde_json::to_value(&exhausted).unwrap(),
+            serde_json::json!({"reason": "exhausted", "detail": {"attack_plan": ["rockyou"]}})
+        );
+        assert_eq!(
+            serde_json::to_value(&ReportReason::NoHandshake).unwrap(),
+            serde_json::json!({"reason": "no_handshake"})
+        );
+        assert_eq!(
+            serde_json::from_value::<ReportReason>(
+                serde_json::json!({"reason": "other", "detail": "odd capture"})
+            )
+            .unwrap(),
+            ReportReason::Other(String::from("odd capture"))
+        );
+        assert_eq!(
+            serde_json::to_value(&ReportReason::Timeout { limit_secs: 3600 }).unwrap(),
+            serde_json::json!({"reason": "timeout", "detail": {"limit_secs": 3600}})
+        );
+    }
+
+    /// Makes sure results kept for later still serialize the way the api
+    /// takes them, and come back the same
+    #[test]
+    fn submission_schema() {
+        let submission = ZinharoSubmission {
+            job_id: 12,
+            password: String::from("hunter22"),
+            wordlist: None,
+            slice_id: Some(3),
+        };
+        let report = ZinharoReportSubmission {
+            hash_id: 77,
+            info: ReportReason::NoHandshake.to_string(),
+            reason: ReportReason::NoHandshake,
+            diagnostics: Some(ZinharoDiagnostics::default()),
+            wordlist: Some(String::from("rockyou")),
+            slice_id: None,
+        };
+
+        assert_eq!(
+            serde_json::to_value(&submission).unwrap(),
+            serde_json::json!({"id": 12, "password": "hunter22", "wordlist": null, "slice_id": 3})
+        );
+        assert_eq!(
+            serde_json::to_value(&report).unwrap()["reason"],
+            "no_handshake"
+        );
+        assert_eq!(
+            serde_json::from_str::<ZinharoReportSubmission>(
+                &serde_json::to_string(&report).unwrap()
+            )
+            .unwrap(),
+            report
+        );
+    }
+
     /// Attempts to sign into the api
     #[test]
     fn signup() {
@@ -13,19 +13,33 @@ pub struct ZinharoAccess {
 
     /// Reqwest client
     pub client: reqwest::blocking::Client,
+
+    /// Prefix of every api route, [API_PREFIX] unless logged in with
+    /// [ZinharoAccess::login_at] or [ZinharoAccess::signup_at]
+    pub api_prefix: String,
 }
 
 impl ZinharoAccess {
     /// Attempts to log into api and returns the reqwest client and the API token.
     /// May provide [ZinharoError::BadCredentials] or [ZinharoError::ApiVersionInadequate]
     pub fn login(username: &str, password: &str) -> Result<Self, ZinharoError> {
+        ZinharoAccess::login_at(API_PREFIX, username, password)
+    }
+
+    /// Same as [ZinharoAccess::login] but against the api at `api_prefix`,
+    /// for example a self-hosted or testing server
+    pub fn login_at(
+        api_prefix: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<Self, ZinharoError> {
         let client = reqwest::blocking::Client::new();
 
-        err_min_version(&client)?;
+        err_min_version(&client, api_prefix)?;
 
         let params = [("username", username), ("password", password)];
         let login_resp = client
-            .get(&format!("{}auth/", API_PREFIX))
+            .get(&format!("{}auth/", api_prefix))
             .query(&params)
             .send()?;
 
@@ -39,8 +53,9 @@ impl ZinharoAccess {
                 let token = login_resp.json::<ApiJson<Token>>()?.body.token;
 
                 Ok(ZinharoAccess {
-                    token: token,
-                    client: client,
+                    token,
+                    client,
+                    api_prefix: String::from(api_prefix),
                 })
             }
             403 => Err(ZinharoError::BadCredentials),
@@ -53,9 +68,18 @@ impl ZinharoAccess {
     /// a new account. Beware when using this method as it is heavily ratelimited
     /// to prevent spam
     pub fn signup(username: &str, password: &str) -> Result<Self, ZinharoError> {
+        ZinharoAccess::signup_at(API_PREFIX, username, password)
+    }
+
+    /// Same as [ZinharoAccess::signup] but against the api at `api_prefix`
+    pub fn signup_at(
+        api_prefix: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<Self, ZinharoError> {
         let client = reqwest::blocking::Client::new();
 
-        err_min_version(&client)?;
+        err_min_version(&client, api_prefix)?;
 
         /// Internal structure for sending signup params
         #[derive(Debug, Serialize)]
@@ -67,7 +91,7 @@ impl ZinharoAccess {
         let signup_json = SignupJson { username, password };
 
         let signup_resp = client
-            .post(&format!("{}auth/", API_PREFIX))
+            .post(&format!("{}auth/", api_prefix))
             .json(&signup_json)
             .send()?;
 
@@ -81,8 +105,9 @@ impl ZinharoAccess {
                 let token = signup_resp.json::<ApiJson<Token>>()?.body.token;
 
                 Ok(ZinharoAccess {
-                    token: token,
-                    client: client,
+                    token,
+                    client,
+                    api_prefix: String::from(api_prefix),
                 })
             }
             403 => Err(ZinharoError::UsernameTaken),
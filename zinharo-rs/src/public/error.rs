@@ -15,6 +15,10 @@ pub enum ZinharoError {
     /// The API returned an unknown response when interacting with it
     UnknownStatusCode(u16),
 
+    /// The API responded with a body this client couldn't make sense of, like
+    /// a malformed timestamp, holding what was wrong with it
+    MalformedResponse(String),
+
     /// When the API issues a ratelimit notice on how many requests can be sent
     Ratelimited,
 
@@ -24,6 +28,23 @@ pub enum ZinharoError {
     /// When no jobs are currently avalible whilst fetching
     NoJobsAvailable,
 
+    /// The job asked for is no longer leased to this client, as it has been
+    /// finished, reassigned or its lease has expired
+    JobNotLeased,
+
+    /// No hash exists with the ID asked for
+    HashNotFound,
+
+    /// The `.cap` stream given isn't a pcap or pcapng capture
+    CaptureInvalid,
+
+    /// The `.cap` stream given holds no EAPOL-Key frames to fingerprint
+    NoHandshake,
+
+    /// The `.cap` stream given is larger than [crate::UploadOptions::max_size]
+    /// or the api allows, holding its size in bytes
+    CaptureTooLarge(u64),
+
     /// The unique username given (commonly to [ZinharoAccess::signup]) has
     /// already been taken by another user
     UsernameTaken,
@@ -0,0 +1,559 @@
+//! Contains [Fingerprint], a canonical digest of the handshake material in a
+//! capture, and the [Ledger] remembering which hash each one was uploaded as.
+//! The same handshake fingerprints the same however it was captured, be it
+//! pcap or pcapng, in whatever order, repeated or surrounded by other traffic
+
+use crate::ZinharoError;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Raw 802.11 frames
+const LINKTYPE_IEEE802_11: u32 = 105;
+
+/// 802.11 frames behind a prism monitor header
+const LINKTYPE_PRISM: u32 = 119;
+
+/// 802.11 frames behind a radiotap header
+const LINKTYPE_RADIOTAP: u32 = 127;
+
+/// 802.11 frames behind an AVS monitor header
+const LINKTYPE_AVS: u32 = 163;
+
+/// 802.11 frames behind a per-packet information header
+const LINKTYPE_PPI: u32 = 192;
+
+/// Magic of a pcapng section header block, the same in either byte order
+const PCAPNG_MAGIC: [u8; 4] = [0x0a, 0x0d, 0x0d, 0x0a];
+
+/// pcapng interface description block
+const BLOCK_INTERFACE: u32 = 1;
+
+/// pcapng simple packet block
+const BLOCK_SIMPLE_PACKET: u32 = 3;
+
+/// pcapng enhanced packet block
+const BLOCK_ENHANCED_PACKET: u32 = 6;
+
+/// LLC/SNAP header announcing an 802.1X frame
+const EAPOL_SNAP: [u8; 8] = [0xaa, 0xaa, 0x03, 0x00, 0x00, 0x00, 0x88, 0x8e];
+
+/// 802.1X packet type of EAPOL-Key frames
+const EAPOL_KEY: u8 = 3;
+
+/// Longest pcap record or pcapng block held in memory, far longer than any
+/// 802.11 frame so a corrupt length can't exhaust memory
+const MAX_RECORD_LEN: usize = 1024 * 1024;
+
+/// A MAC address
+pub(super) type Mac = [u8; 6];
+
+/// A hex-encoded SHA-256 of the handshake material in a capture: every
+/// EAPOL-Key frame along with the access point and station exchanging it,
+/// plus the network names those access points announced
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Fingerprint(String);
+
+impl Fingerprint {
+    /// Fingerprints a pcap or pcapng `.cap` stream. Gives
+    /// [ZinharoError::CaptureInvalid] if it is neither, or
+    /// [ZinharoError::NoHandshake] if it holds no EAPOL-Key frames
+    pub fn from_cap(cap: &[u8]) -> Result<Self, ZinharoError> {
+        Fingerprint::from_reader(cap)
+    }
+
+    /// Same as [Fingerprint::from_cap] for a stream read a frame at a time,
+    /// so a capture never has to be held in memory whole. Gives
+    /// [ZinharoError::IOError] if reading fails
+    pub fn from_reader<R: Read>(cap: R) -> Result<Self, ZinharoError> {
+        Material::collect(cap)?.fingerprint()
+    }
+
+    /// Parses a fingerprint as given by [Fingerprint::as_str], giving
+    /// [Option::None] if it isn't one
+    pub fn parse(hex: &str) -> Option<Self> {
+        let valid = hex.len() == 64 && hex.bytes().all(|c| matches!(c, b'0'..=b'9' | b'a'..=b'f'));
+
+        if valid {
+            Some(Fingerprint(String::from(hex)))
+        } else {
+            None
+        }
+    }
+
+    /// Gives the fingerprint as lowercase hex
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Handshake material collected from a capture, kept sorted and deduplicated
+/// so neither order nor repeats change the fingerprint
+#[derive(Default)]
+pub(super) struct Material {
+    /// EAPOL-Key frames as `(access point, station, frame)`
+    pub(super) keys: BTreeSet<(Mac, Mac, Vec<u8>)>,
+
+    /// Network names announced by each access point
+    pub(super) essids: BTreeMap<Mac, BTreeSet<Vec<u8>>>,
+}
+
+impl Material {
+    /// Collects the handshake material from a pcap or pcapng `.cap` stream
+    pub(super) fn collect<R: Read>(cap: R) -> Result<Self, ZinharoError> {
+        let mut material = Material::default();
+
+        for_each_frame(cap, |linktype, frame| {
+            if let Some(frame) = strip_link_header(linktype, frame) {
+                material.add(frame);
+            }
+        })?;
+
+        Ok(material)
+    }
+
+    /// Collects anything of interest from a raw 802.11 frame
+    fn add(&mut self, frame: &[u8]) {
+        let (control, flags) = match frame {
+            [control, flags, ..] => (*control, *flags),
+            _ => return,
+        };
+
+        let subtype = control >> 4;
+
+        match (control >> 2) & 0b11 {
+            // beacons and probe responses
+            0 if subtype == 8 || subtype == 5 => self.add_essid(frame),
+            2 => self.add_key(frame, subtype, flags),
+            _ => None,
+        };
+    }
+
+    /// Collects the network name from a beacon or probe response
+    fn add_essid(&mut self, frame: &[u8]) -> Option<()> {
+        let bssid = mac_at(frame, 16)?;
+
+        // tagged parameters follow the timestamp, interval and capabilities
+        let mut tags = frame.get(36..)?;
+
+        while let [id, len, rest @ ..] = tags {
+            let value = rest.get(..*len as usize)?;
+
+            // hidden networks announce an empty or zeroed name
+            if *id == 0 {
+                if value.iter().any(|byte| *byte != 0) {
+                    self.essids.entry(bssid).or_default().insert(value.to_vec());
+                }
+
+                return Some(());
+            }
+
+            tags = &rest[value.len()..];
+        }
+
+        None
+    }
+
+    /// Collects the EAPOL-Key frame carried in a data frame, if any
+    fn add_key(&mut self, frame: &[u8], subtype: u8, flags: u8) -> Option<()> {
+        let to_ds = flags & 0x01 != 0;
+        let from_ds = flags & 0x02 != 0;
+
+        // handshakes are never encrypted
+        if flags & 0x40 != 0 {
+            return None;
+        }
+
+        let (addr1, addr2, addr3) = (mac_at(frame, 4)?, mac_at(frame, 10)?, mac_at(frame, 16)?);
+
+        let (ap, station) = match (to_ds, from_ds) {
+            (false, true) => (addr2, addr1),
+            (true, false) => (addr1, addr2),
+            (false, false) if addr2 == addr3 => (addr3, addr1),
+            (false, false) => (addr3, addr2),
+            (true, true) => return None,
+        };
+
+        let mut header_len = 24;
+
+        // qos data carries a control field, and an ht control field if ordered
+        if subtype & 0x08 != 0 {
+            header_len += 2;
+
+            if flags & 0x80 != 0 {
+                header_len += 4;
+            }
+        }
+
+        let eapol = frame.get(header_len..)?.strip_prefix(&EAPOL_SNAP[..])?;
+
+        if *eapol.get(1)? != EAPOL_KEY {
+            return None;
+        }
+
+        // cut to the length it gives, dropping padding and any trailing fcs
+        let len = read_u16(eapol, 2, true)? as usize;
+        let eapol = eapol.get(..4 + len)?;
+
+        self.keys.insert((ap, station, eapol.to_vec()));
+        Some(())
+    }
+
+    /// Digests the collected material
+    fn fingerprint(&self) -> Result<Fingerprint, ZinharoError> {
+        if self.keys.is_empty() {
+            return Err(ZinharoError::NoHandshake);
+        }
+
+        let mut hasher = Sha256::new();
+        let mut aps = BTreeSet::new();
+
+        for (ap, station, eapol) in &self.keys {
+            hasher.update(b"key");
+            hasher.update(ap);
+            hasher.update(station);
+            hasher.update((eapol.len() as u32).to_be_bytes());
+            hasher.update(eapol);
+
+            aps.insert(*ap);
+        }
+
+        // names of networks no handshake was seen for don't matter
+        for ap in aps {
+            for essid in self.essids.get(&ap).into_iter().flatten() {
+                hasher.update(b"essid");
+                hasher.update(ap);
+                hasher.update((essid.len() as u32).to_be_bytes());
+                hasher.update(essid);
+            }
+        }
+
+        let hex = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        Ok(Fingerprint(hex))
+    }
+}
+
+/// Reads the MAC address at `at`
+fn mac_at(bytes: &[u8], at: usize) -> Option<Mac> {
+    let mut mac = [0; 6];
+    mac.copy_from_slice(bytes.get(at..at + 6)?);
+    Some(mac)
+}
+
+/// Reads a `u16` at `at` in the given byte order
+fn read_u16(bytes: &[u8], at: usize, big_endian: bool) -> Option<u16> {
+    let raw = [*bytes.get(at)?, *bytes.get(at + 1)?];
+
+    Some(if big_endian {
+        u16::from_be_bytes(raw)
+    } else {
+        u16::from_le_bytes(raw)
+    })
+}
+
+/// Reads a `u32` at `at` in the given byte order
+fn read_u32(bytes: &[u8], at: usize, big_endian: bool) -> Option<u32> {
+    let mut raw = [0; 4];
+    raw.copy_from_slice(bytes.get(at..at + 4)?);
+
+    Some(if big_endian {
+        u32::from_be_bytes(raw)
+    } else {
+        u32::from_le_bytes(raw)
+    })
+}
+
+/// Strips the monitor header off a captured frame, giving the raw 802.11
+/// frame or [Option::None] if the link-layer isn't 802.11
+fn strip_link_header(linktype: u32, frame: &[u8]) -> Option<&[u8]> {
+    let header_len = match linktype {
+        LINKTYPE_IEEE802_11 => 0,
+        LINKTYPE_RADIOTAP | LINKTYPE_PPI => read_u16(frame, 2, false)? as usize,
+        LINKTYPE_PRISM => read_u32(frame, 4, false)? as usize,
+        LINKTYPE_AVS => read_u32(frame, 4, true)? as usize,
+        _ => return None,
+    };
+
+    frame.get(header_len..)
+}
+
+/// Calls `each` with the link-layer type and data of every frame in a pcap
+/// or pcapng `.cap` stream, read a record at a time. A truncated final
+/// frame, as left behind by an interrupted capture, is skipped
+fn for_each_frame<R, F>(cap: R, mut each: F) -> Result<(), ZinharoError>
+where
+    R: Read,
+    F: FnMut(u32, &[u8]),
+{
+    let mut cap = BufReader::new(cap);
+    let mut magic = [0; 4];
+
+    if !fill(&mut cap, &mut magic)? {
+        return Err(ZinharoError::CaptureInvalid);
+    }
+
+    if magic == PCAPNG_MAGIC {
+        return pcapng_frames(cap, magic, &mut each);
+    }
+
+    let big_endian = match magic {
+        [0xa1, 0xb2, 0xc3, 0xd4] | [0xa1, 0xb2, 0x3c, 0x4d] => true,
+        [0xd4, 0xc3, 0xb2, 0xa1] | [0x4d, 0x3c, 0xb2, 0xa1] => false,
+        _ => return Err(ZinharoError::CaptureInvalid),
+    };
+
+    let mut header = [0; 20];
+
+    if !fill(&mut cap, &mut header)? {
+        return Err(ZinharoError::CaptureInvalid);
+    }
+
+    // the upper bits hold fcs details rather than the link-layer type
+    let linktype = read_u32(&header, 16, big_endian).ok_or(ZinharoError::CaptureInvalid)? & 0xffff;
+    let mut record = [0; 16];
+    let mut frame = Vec::new();
+
+    while fill(&mut cap, &mut record)? {
+        let len = read_u32(&record, 8, big_endian).ok_or(ZinharoError::CaptureInvalid)?;
+
+        match read_record(&mut cap, len as usize, &mut frame)? {
+            Some(true) => each(linktype, &frame),
+            Some(false) => (),
+            None => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// Same as [for_each_frame] for pcapng streams, which may hold several
+/// sections and interfaces of differing byte orders and link-layer types.
+/// `magic` is the already read type of the first block
+fn pcapng_frames<R, F>(mut cap: R, magic: [u8; 4], each: &mut F) -> Result<(), ZinharoError>
+where
+    R: Read,
+    F: FnMut(u32, &[u8]),
+{
+    let mut big_endian = false;
+    let mut linktypes = Vec::new();
+    let mut block = [0; 8];
+    let mut body = Vec::new();
+    let mut first = true;
+
+    block[..4].copy_from_slice(&magic);
+
+    loop {
+        let whole = if first {
+            fill(&mut cap, &mut block[4..])?
+        } else {
+            fill(&mut cap, &mut block)?
+        };
+
+        let section = block[..4] == PCAPNG_MAGIC;
+
+        if section {
+            // a section header's byte order follows its length
+            let mut byte_order = [0; 4];
+
+            if !(whole && fill(&mut cap, &mut byte_order)?) {
+                if first {
+                    return Err(ZinharoError::CaptureInvalid);
+                }
+
+                break;
+            }
+
+            big_endian = match byte_order {
+                [0x1a, 0x2b, 0x3c, 0x4d] => true,
+                [0x4d, 0x3c, 0x2b, 0x1a] => false,
+                _ => return Err(ZinharoError::CaptureInvalid),
+            };
+
+            // interfaces are numbered afresh in every section
+            linktypes.clear();
+        }
+
+        let min_len = if section { 16 } else { 12 };
+
+        let (block_type, len) = match (
+            read_u32(&block, 0, big_endian),
+            read_u32(&block, 4, big_endian),
+        ) {
+            (Some(block_type), Some(len)) if whole && len >= min_len && len % 4 == 0 => {
+                (block_type, len as usize)
+            }
+            _ if first => return Err(ZinharoError::CaptureInvalid),
+            _ => break,
+        };
+
+        first = false;
+
+        // section headers had their byte order read already, and every
+        // block ends with its length again
+        let rest = if section { len - 16 } else { len - 12 };
+
+        match read_record(&mut cap, rest, &mut body)? {
+            Some(true) => (),
+            Some(false) => {
+                skip(&mut cap, 4)?;
+                continue;
+            }
+            None => break,
+        }
+
+        match block_type {
+            BLOCK_INTERFACE => {
+                if let Some(linktype) = read_u16(&body, 0, big_endian) {
+                    linktypes.push(linktype as u32);
+                }
+            }
+            BLOCK_ENHANCED_PACKET => {
+                let interface = read_u32(&body, 0, big_endian).map(|id| linktypes.get(id as usize));
+                let captured = read_u32(&body, 12, big_endian)
+                    .and_then(|len| body.get(20..).and_then(|rest| rest.get(..len as usize)));
+
+                if let (Some(Some(linktype)), Some(frame)) = (interface, captured) {
+                    each(*linktype, frame);
+                }
+            }
+            BLOCK_SIMPLE_PACKET => {
+                // simple packets are always from the first interface, and
+                // only say how long the original frame was
+                let frame = read_u32(&body, 0, big_endian).and_then(|len| {
+                    body.get(4..)
+                        .map(|rest| &rest[..rest.len().min(len as usize)])
+                });
+
+                if let (Some(linktype), Some(frame)) = (linktypes.first(), frame) {
+                    each(*linktype, frame);
+                }
+            }
+            _ => (),
+        }
+
+        skip(&mut cap, 4)?;
+    }
+
+    Ok(())
+}
+
+/// Reads a record `len` bytes long into `buf`. Records over [MAX_RECORD_LEN]
+/// are skipped rather than held in memory, giving `Some(false)`. Gives
+/// [Option::None] if the stream ends first
+fn read_record<R: Read>(
+    cap: &mut R,
+    len: usize,
+    buf: &mut Vec<u8>,
+) -> Result<Option<bool>, ZinharoError> {
+    if len > MAX_RECORD_LEN {
+        return Ok(if skip(cap, len)? { Some(false) } else { None });
+    }
+
+    buf.resize(len, 0);
+
+    Ok(if fill(cap, buf)? { Some(true) } else { None })
+}
+
+/// Fills `buf` from `cap`, giving `false` if the stream ends first
+fn fill<R: Read>(cap: &mut R, buf: &mut [u8]) -> Result<bool, ZinharoError> {
+    match cap.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(ZinharoError::IOError(e)),
+    }
+}
+
+/// Skips `len` bytes of `cap`, giving `false` if the stream ends first
+fn skip<R: Read>(cap: &mut R, len: usize) -> Result<bool, ZinharoError> {
+    let skipped = io::copy(&mut cap.take(len as u64), &mut io::sink())?;
+
+    Ok(skipped == len as u64)
+}
+
+/// A local record of which hash each [Fingerprint] was uploaded as, so a
+/// handshake already uploaded isn't uploaded again. When opened from a file,
+/// it is kept as `<fingerprint> <hash id>` lines which are only ever
+/// appended to, later lines winning
+#[derive(Debug, Default)]
+pub struct Ledger {
+    /// File the ledger is kept in, if any
+    path: Option<PathBuf>,
+
+    /// ID of the hash each fingerprint was uploaded as
+    hashes: BTreeMap<Fingerprint, i32>,
+}
+
+impl Ledger {
+    /// Creates an empty ledger only kept in memory
+    pub fn new() -> Self {
+        Ledger::default()
+    }
+
+    /// Opens the ledger kept at `path`, which is created once something is
+    /// recorded if it doesn't exist yet. Lines that can't be understood are
+    /// skipped
+    pub fn open(path: &Path) -> Result<Self, ZinharoError> {
+        let mut ledger = Ledger {
+            path: Some(path.to_path_buf()),
+            hashes: BTreeMap::new(),
+        };
+
+        let contents = match fs::read_to_string(path) {
+            Ok(x) => x,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(ledger),
+            Err(e) => return Err(ZinharoError::IOError(e)),
+        };
+
+        for line in contents.lines() {
+            let mut fields = line.split_whitespace();
+
+            let fingerprint = fields.next().and_then(Fingerprint::parse);
+            let hash_id = fields.next().and_then(|id| id.parse().ok());
+
+            if let (Some(fingerprint), Some(hash_id)) = (fingerprint, hash_id) {
+                ledger.hashes.insert(fingerprint, hash_id);
+            }
+        }
+
+        Ok(ledger)
+    }
+
+    /// Gives the ID of the hash `fingerprint` was uploaded as, if known
+    pub fn get(&self, fingerprint: &Fingerprint) -> Option<i32> {
+        self.hashes.get(fingerprint).copied()
+    }
+
+    /// Records `fingerprint` as uploaded as the hash `hash_id`, appending it
+    /// to the ledger's file if it has one
+    pub fn insert(&mut self, fingerprint: Fingerprint, hash_id: i32) -> Result<(), ZinharoError> {
+        if self.get(&fingerprint) == Some(hash_id) {
+            return Ok(());
+        }
+
+        if let Some(path) = &self.path {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{} {}", fingerprint, hash_id)?;
+        }
+
+        self.hashes.insert(fingerprint, hash_id);
+        Ok(())
+    }
+}
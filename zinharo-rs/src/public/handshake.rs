@@ -0,0 +1,202 @@
+//! Contains [HandshakeId], what identifies a crackable handshake the same way
+//! hashcat's potfile does for `-m 22000`. Keys only depend on a network's
+//! name, so a key found for one handshake can be tried on any other
+//! handshake of the same network without cracking it again
+
+use super::fingerprint::Material;
+use crate::ZinharoError;
+use std::fmt;
+
+/// Bit of the key information field set when a frame carries a MIC
+const KEY_INFO_MIC: u16 = 0x0100;
+
+/// Offset of the key information field in an EAPOL-Key frame
+const KEY_INFO_OFFSET: usize = 5;
+
+/// Offset of the MIC in an EAPOL-Key frame
+const MIC_OFFSET: usize = 81;
+
+/// Offset of the key data length in an EAPOL-Key frame, followed by the data
+const KEY_DATA_OFFSET: usize = 97;
+
+/// Header of the key data element carrying a PMKID
+const PMKID_KDE: [u8; 6] = [0xdd, 0x14, 0x00, 0x0f, 0xac, 0x04];
+
+/// Length of a MIC or PMKID
+const DIGEST_LEN: usize = 16;
+
+/// Prefix hashcat gives network names it writes out in hex
+const HEX_PREFIX: &str = "$HEX[";
+
+/// A handshake as hashcat identifies it in its potfile: the MIC of an
+/// EAPOL-Key frame or a PMKID, alongside the access point, station and
+/// network name it belongs to
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HandshakeId {
+    /// MIC of the EAPOL-Key frame, or the PMKID
+    pub digest: [u8; DIGEST_LEN],
+
+    /// MAC address of the access point
+    pub ap: [u8; 6],
+
+    /// MAC address of the station
+    pub station: [u8; 6],
+
+    /// Name of the network, which is all a key depends on
+    pub essid: Vec<u8>,
+}
+
+impl HandshakeId {
+    /// Lists every handshake in a pcap or pcapng `.cap` stream which could be
+    /// cracked, being those with a MIC or PMKID whose access point announced
+    /// its network name. Gives [ZinharoError::CaptureInvalid] if the capture
+    /// is neither, or [ZinharoError::NoHandshake] if none could be cracked
+    pub fn from_cap(cap: &[u8]) -> Result<Vec<Self>, ZinharoError> {
+        let material = Material::collect(cap)?;
+        let mut ids = vec![];
+
+        for (ap, station, eapol) in &material.keys {
+            let digest = match digest_of(eapol) {
+                Some(x) => x,
+                None => continue,
+            };
+
+            for essid in material.essids.get(ap).into_iter().flatten() {
+                ids.push(HandshakeId {
+                    digest,
+                    ap: *ap,
+                    station: *station,
+                    essid: essid.clone(),
+                });
+            }
+        }
+
+        if ids.is_empty() {
+            return Err(ZinharoError::NoHandshake);
+        }
+
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    /// Parses the hash part of a hashcat potfile line, formatted as
+    /// `digest:ap:station:essid` with everything but the network name in hex,
+    /// giving [Option::None] if it isn't one
+    pub fn parse(hash: &str) -> Option<Self> {
+        let mut parts = hash.split(':');
+        let digest = decode_hex(parts.next()?)?;
+        let ap = decode_hex(parts.next()?)?;
+        let station = decode_hex(parts.next()?)?;
+        let essid = decode_essid(parts.next()?)?;
+
+        let sized = digest.len() == DIGEST_LEN && ap.len() == 6 && station.len() == 6;
+
+        if parts.next().is_some() || !sized || essid.is_empty() {
+            return None;
+        }
+
+        let mut id = HandshakeId {
+            digest: [0; DIGEST_LEN],
+            ap: [0; 6],
+            station: [0; 6],
+            essid,
+        };
+
+        id.digest.copy_from_slice(&digest);
+        id.ap.copy_from_slice(&ap);
+        id.station.copy_from_slice(&station);
+        Some(id)
+    }
+}
+
+impl fmt::Display for HandshakeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}:{}",
+            encode_hex(&self.digest),
+            encode_hex(&self.ap),
+            encode_hex(&self.station),
+            encode_essid(&self.essid)
+        )
+    }
+}
+
+/// Gives the MIC of an EAPOL-Key frame, or else the PMKID it carries, if
+/// either is there
+fn digest_of(eapol: &[u8]) -> Option<[u8; DIGEST_LEN]> {
+    let info = u16::from_be_bytes([
+        *eapol.get(KEY_INFO_OFFSET)?,
+        *eapol.get(KEY_INFO_OFFSET + 1)?,
+    ]);
+    let mut digest = [0; DIGEST_LEN];
+
+    if info & KEY_INFO_MIC != 0 {
+        digest.copy_from_slice(eapol.get(MIC_OFFSET..MIC_OFFSET + DIGEST_LEN)?);
+    } else {
+        let len = u16::from_be_bytes([
+            *eapol.get(KEY_DATA_OFFSET)?,
+            *eapol.get(KEY_DATA_OFFSET + 1)?,
+        ]) as usize;
+        let data = eapol.get(KEY_DATA_OFFSET + 2..KEY_DATA_OFFSET + 2 + len)?;
+        let start = data
+            .windows(PMKID_KDE.len())
+            .position(|window| window == PMKID_KDE)?
+            + PMKID_KDE.len();
+
+        digest.copy_from_slice(data.get(start..start + DIGEST_LEN)?);
+    }
+
+    // zeroed digests come from frames that can't be cracked
+    if digest.iter().all(|byte| *byte == 0) {
+        None
+    } else {
+        Some(digest)
+    }
+}
+
+/// Encodes `bytes` as lowercase hex
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Decodes hex of either case, giving [Option::None] if it isn't valid
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    let digit = |c: u8| (c as char).to_digit(16);
+
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [high, low] => Some((digit(*high)? << 4 | digit(*low)?) as u8),
+            // odd lengths leave a lone digit at the end
+            _ => None,
+        })
+        .collect()
+}
+
+/// Encodes a network name the way hashcat writes it, in hex if it isn't
+/// printable or would be mistaken for another field
+fn encode_essid(essid: &[u8]) -> String {
+    let printable = essid
+        .iter()
+        .all(|byte| (0x20..0x7f).contains(byte) && *byte != b':');
+
+    if printable && !essid.starts_with(HEX_PREFIX.as_bytes()) {
+        String::from_utf8_lossy(essid).into_owned()
+    } else {
+        format!("{}{}]", HEX_PREFIX, encode_hex(essid))
+    }
+}
+
+/// Decodes a network name as hashcat writes it, giving [Option::None] if its
+/// hex is malformed
+fn decode_essid(essid: &str) -> Option<Vec<u8>> {
+    match essid
+        .strip_prefix(HEX_PREFIX)
+        .and_then(|rest| rest.strip_suffix(']'))
+    {
+        Some(hex) => decode_hex(hex),
+        None => Some(essid.as_bytes().to_vec()),
+    }
+}
@@ -1,11 +1,77 @@
 //! Contains [ZinharoHash] and implamentations of it. Used for uploading a `.cap`
 //! stream and getting infomation on said `.cap`s
 
-use crate::utils::ApiJson;
-use crate::{ZinharoAccess, ZinharoError, ZinharoJob, ZinharoReport, API_PREFIX};
+use crate::utils::{api_version, decode_base64, parse_time, ApiJson, Version};
+use crate::{
+    Fingerprint, Ledger, ReportReason, ZinharoAccess, ZinharoError, ZinharoJob, ZinharoReport,
+};
 use chrono::{DateTime, Utc};
+use reqwest::blocking::multipart;
 use serde::Deserialize;
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Largest `.cap` stream uploaded unless told otherwise, in bytes
+pub const DEFAULT_MAX_CAP_SIZE: u64 = 64 * 1024 * 1024;
+
+/// First api version taking streamed multipart uploads
+const STREAMING_VERSION: Version = Version {
+    major: 0,
+    minor: 2,
+    patch: 0,
+};
+
+/// Level `.cap` streams are compressed at whilst uploading, favouring speed
+const UPLOAD_ZSTD_LEVEL: i32 = 3;
+
+/// How [ZinharoHash::upload] and [ZinharoHash::upload_reader] send a `.cap`
+/// stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct UploadOptions {
+    /// Compress the `.cap` stream with zstd as it is sent, only done for apis
+    /// taking streamed uploads
+    pub compress: bool,
+
+    /// Largest `.cap` stream that may be uploaded, in bytes
+    pub max_size: u64,
+
+    /// Whether the api takes streamed uploads, asked of it through
+    /// `min_version` if [Option::None]. Apis that don't are sent the `.cap`
+    /// stream base64-encoded in JSON, like [ZinharoHash::from_cap]
+    pub streaming: Option<bool>,
+}
+
+impl Default for UploadOptions {
+    fn default() -> Self {
+        UploadOptions {
+            compress: true,
+            max_size: DEFAULT_MAX_CAP_SIZE,
+            streaming: None,
+        }
+    }
+}
+
+/// Reader calling back with how much of a `.cap` stream has been read, and so
+/// sent, out of its total size
+struct ProgressReader<R, P> {
+    inner: R,
+    read: u64,
+    total: u64,
+    progress: P,
+}
+
+impl<R: Read, P: FnMut(u64, u64)> Read for ProgressReader<R, P> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+
+        self.read += read as u64;
+        (self.progress)(self.read, self.total);
+
+        Ok(read)
+    }
+}
 
 /// A representation of a hash/`.cap` file in zinharo, used mainly for applications
 /// uploading hashes and getting info back (like a desktop application gui)
@@ -34,108 +100,283 @@ impl ZinharoHash {
 
         let resp = access
             .client
-            .post(&format!("{}hash/", API_PREFIX))
+            .post(&format!("{}hash/", access.api_prefix))
             .json(&payload)
             .send()?;
 
         match resp.status().as_u16() {
-            200 => {
-                /// Partial representation of [ZinharoJob]
-                #[derive(Debug, Deserialize)]
-                struct JsonJob {
-                    id: i32,
-                    password: String,
-                    client_id: i32,
-                    created: String,
-                }
-
-                /// Partial representation of [ZinharoReport]
-                #[derive(Debug, Deserialize)]
-                struct JsonReport {
-                    id: i32,
-                    info: Option<String>,
-                    client_id: i32,
-                    created: String,
-                }
-
-                /// Representation of large json response
-                #[derive(Debug, Deserialize)]
-                struct JsonHash {
-                    id: i32,
-                    cap: String,
-                    created: String,
-                    jobs: Vec<JsonJob>,
-                    reports: Vec<JsonReport>,
-                }
-
-                /// Wrapper for internal [JsonHash] to fit with api schema
-                #[derive(Debug, Deserialize)]
-                struct JsonHashWrapper {
-                    hash: JsonHash,
-                }
-
-                let resp_json = resp.json::<ApiJson<JsonHashWrapper>>()?;
-
-                let final_created = DateTime::parse_from_rfc3339(&resp_json.body.hash.created)
-                    .unwrap()
-                    .with_timezone(&Utc);
-
-                let mut final_jobs: Vec<ZinharoJob> = Vec::new();
-
-                for job in resp_json.body.hash.jobs {
-                    let job_created = DateTime::parse_from_rfc3339(&job.created)
-                        .unwrap()
-                        .with_timezone(&Utc);
-
-                    let hash_id = resp_json.body.hash.id;
-
-                    final_jobs.push(ZinharoJob {
-                        id: job.id,
-                        password: job.password,
-                        client_id: job.client_id,
-                        hash_id: hash_id,
-                        created: job_created,
-                    })
-                }
-
-                let mut final_reports: Vec<ZinharoReport> = Vec::new();
-
-                for report in resp_json.body.hash.reports {
-                    let report_created = DateTime::parse_from_rfc3339(&report.created)
-                        .unwrap()
-                        .with_timezone(&Utc);
-
-                    let hash_id = resp_json.body.hash.id;
-
-                    final_reports.push(ZinharoReport {
-                        id: report.id,
-                        info: report.info,
-                        client_id: report.client_id,
-                        hash_id: hash_id,
-                        created: report_created,
-                    })
-                }
-
-                Ok(ZinharoHash {
-                    id: resp_json.body.hash.id,
-                    cap: cap,
-                    jobs: final_jobs,
-                    reports: final_reports,
-                    created: final_created,
-                })
-            }
+            200 => ZinharoHash::from_resp(resp, Some(cap)),
+            429 => Err(ZinharoError::Ratelimited),
+            e => Err(ZinharoError::UnknownStatusCode(e)),
+        }
+    }
+
+    /// Streams the `.cap` file at `path` to the api, see
+    /// [ZinharoHash::upload_reader]
+    pub fn upload<P>(
+        access: &ZinharoAccess,
+        path: &Path,
+        options: &UploadOptions,
+        progress: P,
+    ) -> Result<Self, ZinharoError>
+    where
+        P: FnMut(u64, u64) + Send + 'static,
+    {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+
+        ZinharoHash::upload_reader(access, file, len, options, progress)
+    }
+
+    /// Streams a `.cap` stream `len` bytes long to the api as a multipart
+    /// upload, rather than holding it in memory base64-encoded like
+    /// [ZinharoHash::from_cap]. `progress` is called with the bytes sent so
+    /// far and `len` as the upload goes. Gives [ZinharoError::CaptureTooLarge]
+    /// without uploading anything if `len` is over [UploadOptions::max_size].
+    /// The resulting hash's `cap` is as the api gives it back
+    pub fn upload_reader<R, P>(
+        access: &ZinharoAccess,
+        reader: R,
+        len: u64,
+        options: &UploadOptions,
+        progress: P,
+    ) -> Result<Self, ZinharoError>
+    where
+        R: Read + Send + 'static,
+        P: FnMut(u64, u64) + Send + 'static,
+    {
+        if len > options.max_size {
+            return Err(ZinharoError::CaptureTooLarge(len));
+        }
+
+        let streaming = match options.streaming {
+            Some(x) => x,
+            None => ZinharoHash::supports_streaming(access)?,
+        };
+
+        let mut reader = ProgressReader {
+            inner: reader.take(len),
+            read: 0,
+            total: len,
+            progress,
+        };
+
+        if !streaming {
+            let mut cap = Vec::with_capacity(len as usize);
+            reader.read_to_end(&mut cap)?;
+
+            return ZinharoHash::from_cap(access, cap);
+        }
+
+        let (encoding, part) = if options.compress {
+            let encoder = zstd::stream::read::Encoder::new(reader, UPLOAD_ZSTD_LEVEL)?;
+            let part = multipart::Part::reader(encoder)
+                .file_name("capture.cap.zst")
+                .mime_str("application/zstd")?;
+
+            ("zstd", part)
+        } else {
+            let part = multipart::Part::reader_with_length(reader, len)
+                .file_name("capture.cap")
+                .mime_str("application/vnd.tcpdump.pcap")?;
+
+            ("identity", part)
+        };
+
+        let form = multipart::Form::new()
+            .text("encoding", encoding)
+            .part("cap", part);
+
+        let resp = access
+            .client
+            .post(&format!("{}hash/", access.api_prefix))
+            .bearer_auth(String::clone(&access.token))
+            .multipart(form)
+            .send()?;
+
+        match resp.status().as_u16() {
+            200 => ZinharoHash::from_resp(resp, None),
+            413 => Err(ZinharoError::CaptureTooLarge(len)),
+            429 => Err(ZinharoError::Ratelimited),
+            e => Err(ZinharoError::UnknownStatusCode(e)),
+        }
+    }
+
+    /// Checks if the api takes streamed uploads, going by the version it
+    /// reports for itself. Apis too old to report one don't
+    pub fn supports_streaming(access: &ZinharoAccess) -> Result<bool, ZinharoError> {
+        let version = api_version(&access.client, &access.api_prefix)?;
+
+        Ok(version.is_some_and(|version| version.at_least(&STREAMING_VERSION)))
+    }
+
+    /// Fetches a previously uploaded hash by its ID, along with the jobs and
+    /// reports filed against it so far. Gives [ZinharoError::HashNotFound] if
+    /// no such hash exists
+    pub fn from_id(access: &ZinharoAccess, id: i32) -> Result<Self, ZinharoError> {
+        let params = [("id", id)];
+        let resp = access
+            .client
+            .get(&format!("{}hash/", access.api_prefix))
+            .query(&params)
+            .bearer_auth(String::clone(&access.token))
+            .send()?;
+
+        match resp.status().as_u16() {
+            200 => ZinharoHash::from_resp(resp, None),
+            404 => Err(ZinharoError::HashNotFound),
+            429 => Err(ZinharoError::Ratelimited),
+            e => Err(ZinharoError::UnknownStatusCode(e)),
+        }
+    }
+
+    /// Finds the hash uploaded from a capture holding the same handshake as
+    /// `fingerprint`, giving [Option::None] if there isn't one
+    pub fn from_fingerprint(
+        access: &ZinharoAccess,
+        fingerprint: &Fingerprint,
+    ) -> Result<Option<Self>, ZinharoError> {
+        let params = [("fingerprint", fingerprint.as_str())];
+        let resp = access
+            .client
+            .get(&format!("{}hash/", access.api_prefix))
+            .query(&params)
+            .bearer_auth(String::clone(&access.token))
+            .send()?;
+
+        match resp.status().as_u16() {
+            200 => ZinharoHash::from_resp(resp, None).map(Some),
+            404 => Ok(None),
             429 => Err(ZinharoError::Ratelimited),
             e => Err(ZinharoError::UnknownStatusCode(e)),
         }
     }
 
-    // /// Updates infomation like [ZinharoHash::jobs] and [ZinharoHash::reports]
-    // pub fn update_info(&self, access: &ZinharoAccess) -> Result<(), ZinharoError> {
-    //     let params = [("id", self.id)];
-    //     let resp = access
-    //         .client
-    //         .get(&format!("{}hash/", API_PREFIX))
-    //         .query(&params)
-    //         .send()?;
-    // }
+    /// Uploads a `.cap` vec stream unless a capture holding the same handshake
+    /// has been uploaded before, giving back the existing hash instead. The
+    /// `ledger` is checked first, then the api, and records the hash either
+    /// way. Captures which can't be fingerprinted are uploaded as-is
+    pub fn from_cap_deduped(
+        access: &ZinharoAccess,
+        cap: Vec<u8>,
+        ledger: &mut Ledger,
+    ) -> Result<Self, ZinharoError> {
+        let fingerprint = match Fingerprint::from_cap(&cap) {
+            Ok(x) => x,
+            Err(_) => return ZinharoHash::from_cap(access, cap),
+        };
+
+        if let Some(id) = ledger.get(&fingerprint) {
+            match ZinharoHash::from_id(access, id) {
+                Err(ZinharoError::HashNotFound) => (),
+                other => return other,
+            }
+        }
+
+        let hash = match ZinharoHash::from_fingerprint(access, &fingerprint) {
+            Ok(Some(hash)) => hash,
+            // servers without fingerprint lookups turn the query away
+            Ok(None) | Err(ZinharoError::UnknownStatusCode(_)) => {
+                ZinharoHash::from_cap(access, cap)?
+            }
+            Err(e) => return Err(e),
+        };
+
+        ledger.insert(fingerprint, hash.id)?;
+        Ok(hash)
+    }
+
+    /// Creates a [ZinharoHash] from a successful hash api response. The `.cap`
+    /// stream is taken from the response unless already known as `cap`
+    fn from_resp(
+        resp: reqwest::blocking::Response,
+        cap: Option<Vec<u8>>,
+    ) -> Result<Self, ZinharoError> {
+        /// Partial representation of [ZinharoJob]
+        #[derive(Debug, Deserialize)]
+        struct JsonJob {
+            id: i32,
+            password: String,
+            client_id: i32,
+            created: String,
+        }
+
+        /// Partial representation of [ZinharoReport]
+        #[derive(Debug, Deserialize)]
+        struct JsonReport {
+            id: i32,
+            info: Option<String>,
+            #[serde(flatten)]
+            reason: Option<ReportReason>,
+            client_id: i32,
+            created: String,
+        }
+
+        /// Representation of large json response
+        #[derive(Debug, Deserialize)]
+        struct JsonHash {
+            id: i32,
+            cap: Option<String>,
+            created: String,
+            jobs: Vec<JsonJob>,
+            reports: Vec<JsonReport>,
+        }
+
+        /// Wrapper for internal [JsonHash] to fit with api schema
+        #[derive(Debug, Deserialize)]
+        struct JsonHashWrapper {
+            hash: JsonHash,
+        }
+
+        let resp_json = resp.json::<ApiJson<JsonHashWrapper>>()?;
+
+        let final_created = parse_time("created", &resp_json.body.hash.created)?;
+
+        let final_cap = match (cap, resp_json.body.hash.cap) {
+            (Some(cap), _) => cap,
+            (None, Some(encoded)) => decode_base64("cap", &encoded)?,
+            (None, None) => Vec::new(),
+        };
+
+        let mut final_jobs: Vec<ZinharoJob> = Vec::new();
+
+        for job in resp_json.body.hash.jobs {
+            let job_created = parse_time("jobs.created", &job.created)?;
+
+            let hash_id = resp_json.body.hash.id;
+
+            final_jobs.push(ZinharoJob {
+                id: job.id,
+                password: job.password,
+                client_id: job.client_id,
+                hash_id,
+                created: job_created,
+            })
+        }
+
+        let mut final_reports: Vec<ZinharoReport> = Vec::new();
+
+        for report in resp_json.body.hash.reports {
+            let report_created = parse_time("reports.created", &report.created)?;
+
+            let hash_id = resp_json.body.hash.id;
+
+            final_reports.push(ZinharoReport {
+                id: report.id,
+                info: report.info,
+                reason: report.reason,
+                client_id: report.client_id,
+                hash_id,
+                created: report_created,
+            })
+        }
+
+        Ok(ZinharoHash {
+            id: resp_json.body.hash.id,
+            cap: final_cap,
+            jobs: final_jobs,
+            reports: final_reports,
+            created: final_created,
+        })
+    }
 }
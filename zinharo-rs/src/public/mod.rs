@@ -4,14 +4,26 @@
 
 mod access;
 mod error;
+mod fingerprint;
+mod handshake;
 mod job;
+mod passphrase;
 mod queued_job;
 mod report;
+mod slice;
+mod submission;
+mod wordlist;
 mod hash;
 
 pub use access::*;
 pub use error::*;
+pub use fingerprint::*;
+pub use handshake::*;
 pub use job::*;
+pub use passphrase::*;
 pub use queued_job::*;
 pub use report::*;
+pub use slice::*;
+pub use submission::*;
+pub use wordlist::*;
 pub use hash::*;
@@ -0,0 +1,48 @@
+//! Encoding of WPA passphrases for the api. Passphrases are arbitrary bytes,
+//! so any that can't be sent as plain printable text are wrapped in hashcat's
+//! `$HEX[...]` convention instead
+
+/// Prefix of a hex-encoded passphrase
+const HEX_PREFIX: &str = "$HEX[";
+
+/// Suffix of a hex-encoded passphrase
+const HEX_SUFFIX: &str = "]";
+
+/// Encodes a passphrase for submission. Printable ASCII passphrases are kept
+/// as-is, anything else (or something that would be mistaken for an encoded
+/// passphrase) becomes `$HEX[...]` with lowercase hex digits
+pub fn encode_passphrase(passphrase: &[u8]) -> String {
+    let printable = passphrase.iter().all(|byte| (b' '..=b'~').contains(byte));
+
+    if printable && !passphrase.starts_with(HEX_PREFIX.as_bytes()) {
+        return String::from_utf8_lossy(passphrase).into_owned();
+    }
+
+    let hex: String = passphrase
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    format!("{}{}{}", HEX_PREFIX, hex, HEX_SUFFIX)
+}
+
+/// Decodes a passphrase made by [encode_passphrase] back into its raw bytes,
+/// giving [Option::None] if a `$HEX[...]` passphrase holds invalid hex
+pub fn decode_passphrase(encoded: &str) -> Option<Vec<u8>> {
+    let hex = match encoded
+        .strip_prefix(HEX_PREFIX)
+        .and_then(|rest| rest.strip_suffix(HEX_SUFFIX))
+    {
+        Some(x) => x,
+        None => return Some(encoded.as_bytes().to_vec()),
+    };
+
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
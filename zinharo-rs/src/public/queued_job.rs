@@ -1,11 +1,15 @@
 //! Contains a rich [ZinharoQueuedJob] queued job representation and
 //! implamentations around it
 
-use crate::utils::ApiJson;
-use crate::{ZinharoAccess, ZinharoError, API_PREFIX};
+use crate::utils::{decode_base64, parse_time, ApiJson};
+use crate::{
+    encode_passphrase, ReportReason, ZinharoAccess, ZinharoDiagnostics, ZinharoError,
+    ZinharoReportSubmission, ZinharoSlice, ZinharoSubmission,
+};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::fs::File;
+use std::collections::HashMap;
+use std::fs::OpenOptions;
 use std::io::prelude::*;
 use std::path::PathBuf;
 
@@ -17,8 +21,40 @@ pub struct ZinharoQueuedJob {
     /// Job ID
     pub id: i32,
 
+    /// ID of the hash this job is cracking, which reports are filed against
+    pub hash_id: i32,
+
+    /// Name of the network the hash was captured from, if known
+    pub essid: Option<String>,
+
     /// UTC creation date
     pub created: DateTime<Utc>,
+
+    /// Identifier of the wordlist this job should be cracked with, resolved
+    /// through a [crate::ZinharoManifest]. If [Option::None], the manifest's
+    /// default should be used
+    pub wordlist: Option<String>,
+
+    /// Portion of the keyspace this job covers. If [Option::None], the whole
+    /// wordlist should be cracked
+    pub slice: Option<ZinharoSlice>,
+
+    /// When this client's lease on the job runs out, after which the server
+    /// may hand it to someone else. Kept alive with [ZinharoQueuedJob::heartbeat]
+    pub lease_expires: Option<DateTime<Utc>>,
+}
+
+/// Progress on a job, sent with each [ZinharoQueuedJob::heartbeat]
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ZinharoProgress {
+    /// Candidates tried so far
+    pub keys_tested: u64,
+
+    /// Total candidates the job covers, if known
+    pub keys_total: Option<u64>,
+
+    /// Current cracking speed in keys per second, if known
+    pub keys_per_sec: Option<f64>,
 }
 
 impl ZinharoQueuedJob {
@@ -26,90 +62,145 @@ impl ZinharoQueuedJob {
     pub fn new(access: &ZinharoAccess) -> Result<Self, ZinharoError> {
         let resp = access
             .client
-            .get(&format!("{}job/", API_PREFIX))
+            .get(&format!("{}job/", access.api_prefix))
             .bearer_auth(String::clone(&access.token))
             .send()?;
 
         match resp.status().as_u16() {
-            200 => {
-                /// Holder for [JsonJobQueued]
-                #[derive(Debug, Deserialize)]
-                struct JsonJob {
-                    queued: JsonJobQueued,
-                }
-
-                /// Similar to [ZinharoQueuedJob] but without some parts converted
-                #[derive(Debug, Deserialize)]
-                struct JsonJobQueued {
-                    cap: String,
-                    id: i32,
-                    created: String,
-                }
-
-                let resp_json = resp.json::<ApiJson<JsonJob>>()?;
-
-                let final_cap = base64::decode(resp_json.body.queued.cap).unwrap();
-                let final_created = DateTime::parse_from_rfc3339(&resp_json.body.queued.created)
-                    .unwrap()
-                    .with_timezone(&Utc);
-
-                Ok(ZinharoQueuedJob {
-                    cap: final_cap,
-                    id: resp_json.body.queued.id,
-                    created: final_created,
-                })
-            }
+            200 => ZinharoQueuedJob::from_resp(resp),
             429 => Err(ZinharoError::Ratelimited),
             404 => Err(ZinharoError::NoJobsAvailable),
             e => Err(ZinharoError::UnknownStatusCode(e)),
         }
     }
 
-    /// Submits job when finished
-    pub fn submit(&self, access: &ZinharoAccess, password: &str) -> Result<(), ZinharoError> {
-        /// Temp payload used to send job info
+    /// Refetches a job that was previously handed to this client, for example
+    /// to resume it after a restart. Gives [ZinharoError::JobNotLeased] if the
+    /// job has since been reassigned, finished or has expired
+    pub fn fetch(access: &ZinharoAccess, id: i32) -> Result<Self, ZinharoError> {
+        let params = [("id", id)];
+        let resp = access
+            .client
+            .get(&format!("{}job/", access.api_prefix))
+            .query(&params)
+            .bearer_auth(String::clone(&access.token))
+            .send()?;
+
+        match resp.status().as_u16() {
+            200 => ZinharoQueuedJob::from_resp(resp),
+            429 => Err(ZinharoError::Ratelimited),
+            403 | 404 | 410 => Err(ZinharoError::JobNotLeased),
+            e => Err(ZinharoError::UnknownStatusCode(e)),
+        }
+    }
+
+    /// Creates a [ZinharoQueuedJob] from a successful job api response
+    fn from_resp(resp: reqwest::blocking::Response) -> Result<Self, ZinharoError> {
+        /// Holder for [JsonJobQueued]
+        #[derive(Debug, Deserialize)]
+        struct JsonJob {
+            queued: JsonJobQueued,
+        }
+
+        /// Similar to [ZinharoQueuedJob] but without some parts converted
+        #[derive(Debug, Deserialize)]
+        struct JsonJobQueued {
+            cap: String,
+            id: i32,
+            hash_id: i32,
+            essid: Option<String>,
+            created: String,
+            wordlist: Option<String>,
+            slice: Option<ZinharoSlice>,
+            lease_expires: Option<String>,
+        }
+
+        let resp_json = resp.json::<ApiJson<JsonJob>>()?;
+
+        let final_cap = decode_base64("cap", &resp_json.body.queued.cap)?;
+        let final_created = parse_time("created", &resp_json.body.queued.created)?;
+        let final_lease_expires = match &resp_json.body.queued.lease_expires {
+            Some(expires) => Some(parse_time("lease_expires", expires)?),
+            None => None,
+        };
+
+        Ok(ZinharoQueuedJob {
+            cap: final_cap,
+            id: resp_json.body.queued.id,
+            hash_id: resp_json.body.queued.hash_id,
+            essid: resp_json.body.queued.essid,
+            created: final_created,
+            wordlist: resp_json.body.queued.wordlist,
+            slice: resp_json.body.queued.slice,
+            lease_expires: final_lease_expires,
+        })
+    }
+
+    /// Tells the server this client is still working on the job, extending
+    /// the lease. Returns when the new lease expires, or
+    /// [ZinharoError::JobNotLeased] if the job has been taken away already
+    pub fn heartbeat(
+        &self,
+        access: &ZinharoAccess,
+        progress: &ZinharoProgress,
+    ) -> Result<DateTime<Utc>, ZinharoError> {
+        /// Temp payload used to send heartbeat info
         #[derive(Debug, Serialize)]
         struct JsonPayload<'a> {
             id: i32,
-            password: &'a str,
+            progress: &'a ZinharoProgress,
+        }
+
+        /// Renewed lease given back by the api
+        #[derive(Debug, Deserialize)]
+        struct JsonLease {
+            lease_expires: String,
         }
 
         let payload = JsonPayload {
             id: self.id,
-            password: password,
+            progress,
         };
 
         let resp = access
             .client
-            .post(&format!("{}job/", API_PREFIX))
+            .post(&format!("{}job/heartbeat/", access.api_prefix))
             .json(&payload)
             .bearer_auth(String::clone(&access.token))
             .send()?;
 
         match resp.status().as_u16() {
-            200 => Ok(()),
+            200 => {
+                let resp_json = resp.json::<ApiJson<JsonLease>>()?;
+
+                parse_time("lease_expires", &resp_json.body.lease_expires)
+            }
             429 => Err(ZinharoError::Ratelimited),
+            403 | 404 | 410 => Err(ZinharoError::JobNotLeased),
             e => Err(ZinharoError::UnknownStatusCode(e)),
         }
     }
 
-    /// Reports current job with optional provided infomation
-    pub fn report(&self, access: &ZinharoAccess, info: Option<&str>) -> Result<(), ZinharoError> {
-        /// Temp payload used to send report info
-        #[derive(Debug, Serialize)]
-        struct JsonPayload<'a> {
-            hash_id: i32,
-            info: Option<&'a str>,
-        }
+    /// Hands the job back to the queue untouched so another client can pick
+    /// it up straight away, used when shutting down gracefully
+    pub fn release(&self, access: &ZinharoAccess) -> Result<(), ZinharoError> {
+        self.end_lease(access, "release")
+    }
 
-        let payload = JsonPayload {
-            hash_id: self.id,
-            info: info,
-        };
+    /// Gives up on the job after this client failed to work on it, letting the
+    /// server requeue it and keep track of clients that can't handle it
+    pub fn abandon(&self, access: &ZinharoAccess) -> Result<(), ZinharoError> {
+        self.end_lease(access, "abandon")
+    }
+
+    /// Ends this client's lease on the job through the given `job/<route>/`
+    fn end_lease(&self, access: &ZinharoAccess, route: &str) -> Result<(), ZinharoError> {
+        let mut payload = HashMap::new();
+        payload.insert("id", self.id);
 
         let resp = access
             .client
-            .post(&format!("{}report/", API_PREFIX))
+            .post(&format!("{}job/{}/", access.api_prefix, route))
             .json(&payload)
             .bearer_auth(String::clone(&access.token))
             .send()?;
@@ -117,14 +208,69 @@ impl ZinharoQueuedJob {
         match resp.status().as_u16() {
             200 => Ok(()),
             429 => Err(ZinharoError::Ratelimited),
+            403 | 404 | 410 => Err(ZinharoError::JobNotLeased),
             e => Err(ZinharoError::UnknownStatusCode(e)),
         }
     }
 
+    /// Submits job when finished with the raw bytes of the found passphrase,
+    /// which are encoded with [crate::encode_passphrase]. If the job has a
+    /// [ZinharoQueuedJob::slice], the submission is recorded against that slice
+    pub fn submit(&self, access: &ZinharoAccess, password: &[u8]) -> Result<(), ZinharoError> {
+        self.submission(password).send(access)
+    }
+
+    /// Creates the submission [ZinharoQueuedJob::submit] sends, to be sent
+    /// later with [ZinharoSubmission::send]
+    pub fn submission(&self, password: &[u8]) -> ZinharoSubmission {
+        ZinharoSubmission {
+            job_id: self.id,
+            password: encode_passphrase(password),
+            wordlist: self.wordlist.clone(),
+            slice_id: self.slice.as_ref().map(|slice| slice.id),
+        }
+    }
+
+    /// Reports current job for the given `reason`, with optional diagnostics
+    /// on what was tried. For sliced jobs, this marks only
+    /// [ZinharoQueuedJob::slice] as exhausted
+    pub fn report(
+        &self,
+        access: &ZinharoAccess,
+        reason: &ReportReason,
+        diagnostics: Option<&ZinharoDiagnostics>,
+    ) -> Result<(), ZinharoError> {
+        self.report_for(reason, diagnostics).send(access)
+    }
+
+    /// Creates the report [ZinharoQueuedJob::report] sends, to be sent later
+    /// with [ZinharoReportSubmission::send]
+    pub fn report_for(
+        &self,
+        reason: &ReportReason,
+        diagnostics: Option<&ZinharoDiagnostics>,
+    ) -> ZinharoReportSubmission {
+        ZinharoReportSubmission {
+            hash_id: self.hash_id,
+            info: reason.to_string(),
+            reason: ReportReason::clone(reason),
+            diagnostics: diagnostics.cloned(),
+            wordlist: self.wordlist.clone(),
+            slice_id: self.slice.as_ref().map(|slice| slice.id),
+        }
+    }
+
     /// Dumps [ZinharoQueuedJob::cap] to a given filepath. This often returns
-    /// [ZinharoError::IOError(_)].
+    /// [ZinharoError::IOError(_)]. On unix the file is only readable by the
+    /// current user, as captures are sensitive
     pub fn dump_cap(&self, path: PathBuf) -> Result<(), ZinharoError> {
-        let mut file = File::create(path)?;
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let mut file = options.open(path)?;
         file.write_all(&self.cap)?;
 
         Ok(())
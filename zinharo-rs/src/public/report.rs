@@ -2,6 +2,8 @@
 //! probably be updated in the future
 
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
 /// A representation of a report. If you are looking for how to make reports,
 /// please view [ZinharoAccess::report] as [ZinharoAccess] houses cracking/server
@@ -14,6 +16,9 @@ pub struct ZinharoReport {
     /// report as official client always states reason
     pub info: Option<String>,
 
+    /// Structured reason for the report, if the reporting client gave one
+    pub reason: Option<ReportReason>,
+
     /// ID of client that made report
     pub client_id: i32,
 
@@ -23,3 +28,96 @@ pub struct ZinharoReport {
     /// When report was filed/created
     pub created: DateTime<Utc>,
 }
+
+/// Why a job is being reported. Serialized as `{"reason": "...", "detail": ...}`
+/// with `detail` left out for reasons that carry none, so the server can
+/// aggregate reports without parsing free-form text
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "reason", content = "detail", rename_all = "snake_case")]
+pub enum ReportReason {
+    /// The capture couldn't be read at all
+    InvalidCapture,
+
+    /// The capture holds no usable WPA handshake
+    NoHandshake,
+
+    /// Every candidate of the attack plan was tried without finding the key
+    Exhausted {
+        /// Stages of the attack plan that were exhausted, like wordlist names
+        /// or keyspace slices
+        attack_plan: Vec<String>,
+    },
+
+    /// The capture's network uses encryption that can't be cracked
+    UnsupportedEncryption,
+
+    /// The cracker claimed to find a key which didn't validate against the
+    /// capture's handshake, so it was never submitted
+    UnverifiedKey,
+
+    /// The cracker was still running when its wall-clock deadline passed
+    Timeout {
+        /// Deadline the cracker was given, in seconds
+        limit_secs: u64,
+    },
+
+    /// The cracker stopped producing output and consuming candidates, so was
+    /// presumed stuck and killed
+    Hung {
+        /// How long the cracker was silent before being killed, in seconds
+        silent_secs: u64,
+    },
+
+    /// The cracker ran into a cpu or memory limit set on this client
+    ResourceLimit {
+        /// Which limit was hit, like `cpu` or `memory`
+        resource: String,
+    },
+
+    /// Any other reason, described for humans
+    Other(String),
+}
+
+impl fmt::Display for ReportReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReportReason::InvalidCapture => write!(f, "Capture could not be read"),
+            ReportReason::NoHandshake => write!(f, "No WPA handshake in capture"),
+            ReportReason::Exhausted { attack_plan } if attack_plan.is_empty() => {
+                write!(f, "Could not crack using standardised wordlist")
+            }
+            ReportReason::Exhausted { attack_plan } => {
+                write!(f, "Could not crack using {}", attack_plan.join(", "))
+            }
+            ReportReason::UnsupportedEncryption => write!(f, "Unsupported encryption"),
+            ReportReason::UnverifiedKey => write!(f, "Found key does not validate"),
+            ReportReason::Timeout { limit_secs } => {
+                write!(f, "Cracker timed out after {}s", limit_secs)
+            }
+            ReportReason::Hung { silent_secs } => {
+                write!(f, "Cracker hung, silent for {}s", silent_secs)
+            }
+            ReportReason::ResourceLimit { resource } => {
+                write!(f, "Cracker hit its {} limit", resource)
+            }
+            ReportReason::Other(info) => write!(f, "{}", info),
+        }
+    }
+}
+
+/// Extra infomation sent alongside a [ReportReason] to help work out what went
+/// wrong with a job
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ZinharoDiagnostics {
+    /// Stages of the attack plan that were tried before reporting
+    pub stages_tried: Vec<String>,
+
+    /// Seconds spent on the job before reporting
+    pub elapsed_secs: Option<u64>,
+
+    /// Candidates tried before reporting
+    pub keys_tested: Option<u64>,
+
+    /// Relevant output from the cracker, if any
+    pub cracker_output: Option<String>,
+}
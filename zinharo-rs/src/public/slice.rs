@@ -0,0 +1,34 @@
+//! Contains [ZinharoSlice], the portion of a hash's keyspace that a queued job
+//! covers. Slicing lets the server fan a single hash out across many clients
+//! and keep track of which parts of the keyspace have been exhausted
+
+use serde::{Deserialize, Serialize};
+
+/// A slice of keyspace handed out alongside a [crate::ZinharoQueuedJob]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ZinharoSlice {
+    /// ID of slice, referenced when submitting or reporting
+    pub id: i32,
+
+    /// Which candidates this slice covers
+    pub range: ZinharoRange,
+}
+
+/// The candidates covered by a [ZinharoSlice]. Wordlist ranges index into the
+/// decompressed [crate::ZinharoQueuedJob::wordlist] exactly as distributed by
+/// the manifest, before any client-side preprocessing. All ranges are
+/// zero-indexed and exclusive of `end`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ZinharoRange {
+    /// Lines `start..end` of the wordlist
+    Lines { start: u64, end: u64 },
+
+    /// Bytes `start..end` of the wordlist. A line belongs to the slice its
+    /// first byte falls in, so neighbouring slices never overlap
+    Bytes { start: u64, end: u64 },
+
+    /// Candidates `start..end` of a hashcat-style mask like `?u?l?l?l?d?d?d?d`,
+    /// counted like an odometer with the last position changing fastest
+    Mask { mask: String, start: u64, end: u64 },
+}
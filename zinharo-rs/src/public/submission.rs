@@ -0,0 +1,86 @@
+//! Contains [ZinharoSubmission] and [ZinharoReportSubmission], the results of
+//! a job as sent to the api. They can be serialized and sent later on without
+//! the [crate::ZinharoQueuedJob] they came from, so clients can hold onto
+//! results whilst the api is unreachable
+
+use crate::{ReportReason, ZinharoAccess, ZinharoDiagnostics, ZinharoError};
+use serde::{Deserialize, Serialize};
+
+/// A passphrase found for a job, made with
+/// [crate::ZinharoQueuedJob::submission]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ZinharoSubmission {
+    /// ID of the job the passphrase was found for
+    #[serde(rename = "id")]
+    pub job_id: i32,
+
+    /// The passphrase, encoded with [crate::encode_passphrase]
+    pub password: String,
+
+    /// Identifier of the wordlist the passphrase was found with
+    pub wordlist: Option<String>,
+
+    /// ID of the keyspace slice the passphrase was found in, if sliced
+    pub slice_id: Option<i32>,
+}
+
+impl ZinharoSubmission {
+    /// Sends the passphrase to the api
+    pub fn send(&self, access: &ZinharoAccess) -> Result<(), ZinharoError> {
+        let resp = access
+            .client
+            .post(&format!("{}job/", access.api_prefix))
+            .json(self)
+            .bearer_auth(String::clone(&access.token))
+            .send()?;
+
+        match resp.status().as_u16() {
+            200 => Ok(()),
+            429 => Err(ZinharoError::Ratelimited),
+            e => Err(ZinharoError::UnknownStatusCode(e)),
+        }
+    }
+}
+
+/// A report against the hash a job was cracking, made with
+/// [crate::ZinharoQueuedJob::report_for]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ZinharoReportSubmission {
+    /// ID of the hash being reported
+    pub hash_id: i32,
+
+    /// Human-readable form of [ZinharoReportSubmission::reason], for older
+    /// servers
+    pub info: String,
+
+    /// Why the hash is being reported
+    #[serde(flatten)]
+    pub reason: ReportReason,
+
+    /// What was tried before reporting, if known
+    pub diagnostics: Option<ZinharoDiagnostics>,
+
+    /// Identifier of the wordlist that was tried
+    pub wordlist: Option<String>,
+
+    /// ID of the keyspace slice that was exhausted, if sliced
+    pub slice_id: Option<i32>,
+}
+
+impl ZinharoReportSubmission {
+    /// Sends the report to the api
+    pub fn send(&self, access: &ZinharoAccess) -> Result<(), ZinharoError> {
+        let resp = access
+            .client
+            .post(&format!("{}report/", access.api_prefix))
+            .json(self)
+            .bearer_auth(String::clone(&access.token))
+            .send()?;
+
+        match resp.status().as_u16() {
+            200 => Ok(()),
+            429 => Err(ZinharoError::Ratelimited),
+            e => Err(ZinharoError::UnknownStatusCode(e)),
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! Contains the [ZinharoManifest] of server-distributed wordlists, used to
+//! resolve the wordlist identifier given on a [crate::ZinharoQueuedJob] into
+//! something a client can download
+
+use crate::utils::ApiJson;
+use crate::{ZinharoAccess, ZinharoError};
+use serde::Deserialize;
+
+/// A single dictionary distributed to cracking clients by the platform
+#[derive(Debug, Clone, Deserialize)]
+pub struct ZinharoWordlist {
+    /// Unique identifier, as referenced by [crate::ZinharoQueuedJob::wordlist]
+    pub id: String,
+
+    /// Where the wordlist can be downloaded from, may be compressed
+    pub url: String,
+
+    /// Optional hex-encoded SHA-256 of the file served at [ZinharoWordlist::url]
+    pub sha256: Option<String>,
+}
+
+/// The list of wordlists a server knows about, fetched from the api so every
+/// client agrees on what a wordlist identifier means
+#[derive(Debug, Clone, Deserialize)]
+pub struct ZinharoManifest {
+    /// Identifier of the wordlist to use for jobs that don't name one
+    pub default: Option<String>,
+
+    /// All wordlists currently distributed
+    pub wordlists: Vec<ZinharoWordlist>,
+}
+
+impl ZinharoManifest {
+    /// Fetches the current wordlist manifest from the api
+    pub fn fetch(access: &ZinharoAccess) -> Result<Self, ZinharoError> {
+        let resp = access
+            .client
+            .get(&format!("{}wordlist/", access.api_prefix))
+            .bearer_auth(String::clone(&access.token))
+            .send()?;
+
+        match resp.status().as_u16() {
+            200 => Ok(resp.json::<ApiJson<ZinharoManifest>>()?.body),
+            429 => Err(ZinharoError::Ratelimited),
+            e => Err(ZinharoError::UnknownStatusCode(e)),
+        }
+    }
+
+    /// Finds the wordlist with the given identifier, or the manifest's default
+    /// if [Option::None] is passed
+    pub fn resolve(&self, id: Option<&str>) -> Option<&ZinharoWordlist> {
+        let id = id.or(self.default.as_deref())?;
+
+        self.wordlists.iter().find(|wordlist| wordlist.id == id)
+    }
+}
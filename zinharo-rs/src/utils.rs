@@ -1,6 +1,7 @@
 //! Used for internal utilities and should never be public, only a simple `use` or `mod`
 
-use crate::{API_PREFIX, MIN_VERSION, ZinharoError};
+use crate::{ZinharoError, MIN_VERSION};
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
 
 /// Version for comparing between api and this library
@@ -16,17 +17,23 @@ pub struct Version {
 }
 
 impl Version {
-    /// Creates a [Version] from a string like `2.4.23`
-    pub fn from_str(ver_str: &str) -> Self {
-        let version_vec: Vec<i32> = ver_str
+    /// Creates a [Version] from a string like `2.4.23`, giving
+    /// [ZinharoError::MalformedResponse] if it isn't three numbers
+    pub fn from_str(ver_str: &str) -> Result<Self, ZinharoError> {
+        let malformed =
+            || ZinharoError::MalformedResponse(format!("`{}` isn't a version", ver_str));
+        let version_vec = ver_str
             .split(".")
-            .map(|s| s.parse::<i32>().unwrap())
-            .collect();
+            .map(|s| s.parse::<i32>().map_err(|_| malformed()))
+            .collect::<Result<Vec<i32>, ZinharoError>>()?;
 
-        Version {
-            major: version_vec[0],
-            minor: version_vec[1],
-            patch: version_vec[2],
+        match version_vec[..] {
+            [major, minor, patch] => Ok(Version {
+                major,
+                minor,
+                patch,
+            }),
+            _ => Err(malformed()),
         }
     }
 
@@ -40,21 +47,23 @@ impl Version {
         match resp.status().as_u16() {
             200 => {
                 let resp_json = resp.json::<ApiJson<MinVersion>>()?;
-                Ok(Version::from_str(&resp_json.body.min_version))
+                Version::from_str(&resp_json.body.min_version)
             }
             403 => Err(ZinharoError::FirewallBlock),
             e => Err(ZinharoError::UnknownStatusCode(e)),
         }
     }
 
+    /// Checks if this version is the same as or newer than `other`
+    pub fn at_least(&self, other: &Version) -> bool {
+        (self.major, self.minor, self.patch) >= (other.major, other.minor, other.patch)
+    }
+
     /// Check if version is within rights
     pub fn compare_versions(&self, compare: &Version) -> bool {
-        if self.major >= compare.major {
-            if self.minor >= compare.minor {
-                if self.patch >= compare.patch {
-                    return false;
-                }
-            }
+        if self.major >= compare.major && self.minor >= compare.minor && self.patch >= compare.patch
+        {
+            return false;
         }
 
         true
@@ -63,8 +72,11 @@ impl Version {
 
 /// Connects to api's `min_version` to ensure client is not out of date. If
 /// successful, should return an empty [Result::Ok]
-pub fn err_min_version(client: &reqwest::blocking::Client) -> Result<(), ZinharoError> {
-    let min_version_resp = client.get(&format!("{}min_version/", API_PREFIX)).send()?;
+pub fn err_min_version(
+    client: &reqwest::blocking::Client,
+    api_prefix: &str,
+) -> Result<(), ZinharoError> {
+    let min_version_resp = client.get(&format!("{}min_version/", api_prefix)).send()?;
     let min_version = Version::from_resp(min_version_resp)?;
 
     if MIN_VERSION.compare_versions(&min_version) {
@@ -74,10 +86,58 @@ pub fn err_min_version(client: &reqwest::blocking::Client) -> Result<(), Zinharo
     Ok(())
 }
 
+/// Connects to api's `min_version` to find the version the api reports for
+/// itself alongside it. Apis too old to report one give [Option::None]
+pub fn api_version(
+    client: &reqwest::blocking::Client,
+    api_prefix: &str,
+) -> Result<Option<Version>, ZinharoError> {
+    #[derive(Debug, Deserialize)]
+    struct ApiVersion {
+        version: Option<String>,
+    }
+
+    let resp = client.get(&format!("{}min_version/", api_prefix)).send()?;
+
+    match resp.status().as_u16() {
+        200 => {
+            let resp_json = resp.json::<ApiJson<ApiVersion>>()?;
+            resp_json
+                .body
+                .version
+                .map(|ver| Version::from_str(&ver))
+                .transpose()
+        }
+        403 => Err(ZinharoError::FirewallBlock),
+        e => Err(ZinharoError::UnknownStatusCode(e)),
+    }
+}
+
+/// Parses the RFC 3339 timestamp `value` the api gave for `field`, giving
+/// [ZinharoError::MalformedResponse] if it isn't one
+pub fn parse_time(field: &str, value: &str) -> Result<DateTime<Utc>, ZinharoError> {
+    match DateTime::parse_from_rfc3339(value) {
+        Ok(time) => Ok(time.with_timezone(&Utc)),
+        Err(e) => Err(ZinharoError::MalformedResponse(format!(
+            "`{}` isn't a timestamp: {}",
+            field, e
+        ))),
+    }
+}
+
+/// Decodes the base64 `value` the api gave for `field`, giving
+/// [ZinharoError::MalformedResponse] if it isn't valid
+pub fn decode_base64(field: &str, value: &str) -> Result<Vec<u8>, ZinharoError> {
+    base64::decode(value).map_err(|e| {
+        ZinharoError::MalformedResponse(format!("`{}` isn't valid base64: {}", field, e))
+    })
+}
+
 /// Generalised API response as all of them should use this baisic schema, used
 /// internally
 #[derive(Debug, Deserialize)]
 pub struct ApiJson<T> {
+    #[allow(dead_code)]
     pub status: String,
     pub body: T,
 }